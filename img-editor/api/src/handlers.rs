@@ -3,12 +3,11 @@ use axum::{
     response::Response,
 };
 use hex;
-use image;
 use sp1_sdk::SP1Stdin;
 use tracing::{info, error};
 use alloy_sol_types::SolType;
 
-use crate::types::{AppState, ProofData, ProofResponse, HexSignatureData};
+use crate::types::{AppState, EstimateResponse, ProofData, ProofResponse, HexSignatureData};
 
 pub async fn health_check() -> &'static str {
     "OK"
@@ -16,33 +15,132 @@ pub async fn health_check() -> &'static str {
 
 pub async fn generate_proof(
     State(state): State<AppState>,
-    mut multipart: Multipart
+    multipart: Multipart
 ) -> Response {
+    match read_input(multipart).await {
+        Ok(input) => generate_proof_and_response(state, input).await,
+        Err(response) => response,
+    }
+}
+
+/// Runs the program in the executor, without proving, and reports the cycles it took with the
+/// proving time and cost they amount to.
+pub async fn estimate_proof(
+    State(state): State<AppState>,
+    multipart: Multipart
+) -> Response {
+    let input = match read_input(multipart).await {
+        Ok(input) => input,
+        Err(response) => return response,
+    };
+
+    // Apply the transformations on the host first so invalid requests fail with their error code
+    if let Err(e) = render_final_image(&input) {
+        error!("Failed to apply transformations: {}", e);
+        return ProofResponse::editor_error(&e);
+    }
+
+    let mut stdin = SP1Stdin::new();
+    stdin.write(&input);
+    info!("Executing program...");
+    match state.prover.execute(&state.elf_data, &stdin).run() {
+        Ok((_, report)) => {
+            let estimate = img_editor_lib::ProofEstimate::new(
+                &input,
+                report.cycle_tracker.clone(),
+                report.total_instruction_count(),
+                &state.rates,
+            );
+            info!("Estimated {} cycles", estimate.total_cycles);
+            EstimateResponse::success(estimate)
+        }
+        Err(e) => {
+            error!("Failed to execute program: {}", e);
+            ProofResponse::proof_error(format!("Failed to execute program: {}", e))
+        }
+    }
+}
+
+/// Reads the fields shared by `/prove` and `/estimate` into a proof input.
+async fn read_input(mut multipart: Multipart) -> Result<img_editor_lib::ImageInput, Response> {
     let mut image_data = Vec::new();
     let mut transformations = None;
     let mut signature_data = None;
+    let mut fonts = Vec::new();
+    let mut output_format = img_editor_lib::OutputFormat::default();
+    let mut encode_options = img_editor_lib::EncodeOptions::default();
+    let mut animation = None;
+    let mut tile_rows = None;
     let mut found_fields = vec![];
 
     // Process multipart form data
-    while let Some(field) = multipart.next_field().await.unwrap() {
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => {
+                error!("Failed to read multipart form: {}", e);
+                return Err(ProofResponse::error(format!("Invalid multipart form: {}", e)));
+            }
+        };
         if let Some(name) = field.name() {
             info!("Found field: {}", name);
             found_fields.push(name.to_string());
             
             match name {
                 "image" => {
-                    image_data = field.bytes().await.unwrap().to_vec();
+                    image_data = field_bytes(field).await?.to_vec();
                     info!("Received image data: {} bytes", image_data.len());
                 },
                 "transformations" => {
-                    if let Err(response) = process_transformations(&mut transformations, field).await {
-                        return response;
-                    }
+                    process_transformations(&mut transformations, field).await?;
                 },
                 "signature_data" => {
-                    if let Err(response) = process_signature_data(&mut signature_data, field).await {
-                        return response;
+                    process_signature_data(&mut signature_data, field).await?;
+                },
+                "output_format" => {
+                    let bytes = field_bytes(field).await?;
+                    match String::from_utf8_lossy(&bytes).trim().parse() {
+                        Ok(format) => output_format = format,
+                        Err(e) => return Err(ProofResponse::editor_error(&e)),
+                    }
+                    info!("Output format: {:?}", output_format);
+                },
+                "encode_options" => {
+                    let bytes = field_bytes(field).await?;
+                    info!("Received encode options: {}", String::from_utf8_lossy(&bytes));
+                    match serde_json::from_slice(&bytes) {
+                        Ok(options) => encode_options = options,
+                        Err(e) => {
+                            error!("JSON parse error for encode_options: {}", e);
+                            return Err(ProofResponse::error(format!("Invalid encode_options JSON format: {}", e)));
+                        }
+                    }
+                },
+                "animation" => {
+                    let bytes = field_bytes(field).await?;
+                    info!("Received animation: {}", String::from_utf8_lossy(&bytes));
+                    match serde_json::from_slice(&bytes) {
+                        Ok(frames) => animation = Some(frames),
+                        Err(e) => {
+                            error!("JSON parse error for animation: {}", e);
+                            return Err(ProofResponse::error(format!("Invalid animation JSON format: {}", e)));
+                        }
+                    }
+                },
+                "tile_rows" => {
+                    let bytes = field_bytes(field).await?;
+                    match String::from_utf8_lossy(&bytes).trim().parse() {
+                        Ok(rows) => tile_rows = Some(rows),
+                        Err(e) => return Err(ProofResponse::error(format!("Invalid tile_rows: {}", e))),
                     }
+                    info!("Tile rows: {:?}", tile_rows);
+                },
+                font_field if font_field.starts_with("font:") => {
+                    let font_name = font_field.trim_start_matches("font:").to_string();
+                    let data = field_bytes(field).await?.to_vec();
+                    info!("Received font '{}': {} bytes", font_name, data.len());
+                    fonts.push(img_editor_lib::FontData { name: font_name, data });
                 },
                 _ => {
                     info!("Unexpected field in request: {}", name);
@@ -54,9 +152,7 @@ pub async fn generate_proof(
     info!("Found fields: {:?}", found_fields);
 
     // Validate required fields
-    if let Err(response) = validate_fields(&found_fields, &image_data, &transformations) {
-        return response;
-    }
+    validate_fields(&found_fields, &image_data, &transformations)?;
 
     // Unwrap transformations (validation ensures it's Some)
     let transformations = transformations.unwrap();
@@ -64,15 +160,34 @@ pub async fn generate_proof(
     // Log transformation details
     log_transformation_details(&image_data, &transformations);
 
-    // Generate proof
-    generate_proof_and_response(state, image_data, transformations, signature_data).await
+    Ok(img_editor_lib::ImageInput {
+        image_data,
+        transformations,
+        signature_data,
+        fonts,
+        layers: Vec::new(),
+        output_format,
+        encode_options,
+        animation,
+        tile_rows,
+        canvas: None,
+    })
+}
+
+/// Reads a whole form field, answering with a 400 when the upload is cut short or malformed.
+async fn field_bytes(field: axum::extract::multipart::Field<'_>) -> Result<axum::body::Bytes, Response> {
+    let name = field.name().unwrap_or_default().to_string();
+    field.bytes().await.map_err(|e| {
+        error!("Failed to read field {}: {}", name, e);
+        ProofResponse::error(format!("Failed to read the {} field: {}", name, e))
+    })
 }
 
 async fn process_transformations(
     transformations: &mut Option<Vec<img_editor_lib::Transformation>>,
     field: axum::extract::multipart::Field<'_>
 ) -> Result<(), Response> {
-    let bytes = field.bytes().await.unwrap();
+    let bytes = field_bytes(field).await?;
     info!("Received transformations data: {}", String::from_utf8_lossy(&bytes));
     
     match String::from_utf8(bytes.to_vec()) {
@@ -96,7 +211,7 @@ async fn process_signature_data(
     signature_data: &mut Option<img_editor_lib::SignatureData>,
     field: axum::extract::multipart::Field<'_>
 ) -> Result<(), Response> {
-    let bytes = field.bytes().await.unwrap();
+    let bytes = field_bytes(field).await?;
     info!("Received signature data: {}", String::from_utf8_lossy(&bytes));
     
     match String::from_utf8(bytes.to_vec()) {
@@ -190,16 +305,16 @@ fn log_transformation_details(
 
 async fn generate_proof_and_response(
     state: AppState,
-    image_data: Vec<u8>,
-    transformations: Vec<img_editor_lib::Transformation>,
-    signature_data: Option<img_editor_lib::SignatureData>
+    input: img_editor_lib::ImageInput
 ) -> Response {
-    // Create input with optional signature_data
-    let image_data_clone = image_data.clone();
-    let input = img_editor_lib::ImageInput {
-        image_data,
-        transformations: transformations.clone(),
-        signature_data,
+    // Apply the transformations on the host first so invalid requests fail before proving. The
+    // image is checked against the proven hash before it is returned.
+    let final_image = match render_final_image(&input) {
+        Ok(final_image) => final_image,
+        Err(e) => {
+            error!("Failed to apply transformations: {}", e);
+            return ProofResponse::editor_error(&e);
+        }
     };
 
     // Setup stdin with serialized input
@@ -224,20 +339,14 @@ async fn generate_proof_and_response(
 
             // Get and decode public values
             let public_values = proof.public_values.as_slice();
-            let decoded_values = img_editor_lib::ImageProofPublicValues::abi_decode(public_values, false)
+            let decoded_values = img_editor_lib::ImageProofPublicValues::abi_decode_params(public_values, false)
                 .expect("Failed to decode public values");
 
-            // Generate final image using the cloned data
-            let mut layer = img_editor_lib::Layer::new(&image_data_clone)
-                .expect("Failed to create layer");
-            
-            for transformation in transformations.iter() {
-                layer.apply_transformation(transformation.clone())
-                    .expect("Failed to apply transformation");
+            // The program only commits the hash of the image, so make sure the host rendered those bytes
+            if let Err(e) = img_editor_lib::check_rendered_image(&final_image, &decoded_values.transformed_image_hash.0) {
+                error!("{}", e);
+                return ProofResponse::editor_error(&e);
             }
-            
-            let final_image = layer.to_bytes(image::ImageFormat::Png, None)
-                .expect("Failed to encode image");
 
             let solidity_proof = proof.bytes();
             let verification_key = state.vk.clone();
@@ -248,6 +357,15 @@ async fn generate_proof_and_response(
                 format!("0x{}", hex::encode(decoded_values.transformed_image_hash.0)),
                 format!("0x{}", hex::encode(decoded_values.signer_public_key.0)),
                 decoded_values.has_signature,
+                decoded_values.font_hashes.iter()
+                    .map(|hash| format!("0x{}", hex::encode(hash.0)))
+                    .collect(),
+                decoded_values.layer_hashes.iter()
+                    .map(|hash| format!("0x{}", hex::encode(hash.0)))
+                    .collect(),
+                decoded_values.metadata_fields,
+                format!("0x{}", hex::encode(decoded_values.output_profile_hash.0)),
+                format!("0x{}", hex::encode(decoded_values.frames_hash.0)),
                 Some(ProofData {
                     proof: format!("0x{}", hex::encode(solidity_proof)),
                     verification_key,
@@ -257,7 +375,18 @@ async fn generate_proof_and_response(
         }
         Err(e) => {
             error!("Failed to generate proof: {}", e);
-            ProofResponse::error(format!("Failed to generate proof: {}", e))
+            ProofResponse::proof_error(format!("Failed to generate proof: {}", e))
         }
     }
+}
+
+fn render_final_image(
+    input: &img_editor_lib::ImageInput
+) -> Result<Vec<u8>, img_editor_lib::EditorError> {
+    let mut registry = img_editor_lib::FontRegistry::new();
+    for font in &input.fonts {
+        registry.register(&font.name, font.data.clone())?;
+    }
+
+    img_editor_lib::render_input(input, &registry)
 } 
\ No newline at end of file
@@ -50,6 +50,7 @@ async fn main() {
         elf_data: Arc::new(elf_data),
         pk: Arc::new(pk),
         vk: vk.bytes32(),
+        rates: img_editor_lib::ProvingRates::from_env().expect("Invalid proving rates"),
     };
 
     let cors = CorsLayer::new()
@@ -60,6 +61,7 @@ async fn main() {
     let app = Router::new()
         .route("/health", get(handlers::health_check))
         .route("/prove", post(handlers::generate_proof))
+        .route("/estimate", post(handlers::estimate_proof))
         .layer(cors)
         .with_state(state);
 
@@ -12,14 +12,28 @@ pub struct ProofData {
 pub struct ProofResponse {
     pub success: bool,
     pub message: String,
+    pub error_code: Option<String>,
     pub final_image: Vec<u8>,
     pub original_image_hash: String,
     pub transformed_image_hash: String,
     pub signer_public_key: String,
     pub has_signature: bool,
+    pub font_hashes: Vec<String>,
+    pub layer_hashes: Vec<String>,
+    pub metadata_fields: Vec<String>,
+    pub output_profile_hash: String,
+    pub frames_hash: String,
     pub proof_data: Option<ProofData>,
 }
 
+#[derive(Serialize)]
+pub struct EstimateResponse {
+    pub success: bool,
+    pub message: String,
+    pub error_code: Option<String>,
+    pub estimate: img_editor_lib::ProofEstimate,
+}
+
 #[derive(Deserialize)]
 pub struct HexSignatureData {
     pub signature: String,
@@ -28,10 +42,9 @@ pub struct HexSignatureData {
 
 #[derive(Clone)]
 pub struct AppState {
-    #[allow(dead_code)]
     pub prover: Arc<sp1_sdk::EnvProver>,
-    #[allow(dead_code)]
     pub elf_data: Arc<Vec<u8>>,
     pub pk: Arc<sp1_sdk::SP1ProvingKey>,
     pub vk: String, // Store the verification key as a string
+    pub rates: img_editor_lib::ProvingRates,
 } 
\ No newline at end of file
@@ -1,8 +1,9 @@
 use axum::{http::StatusCode, Json, response::{IntoResponse, Response}};
 use std::path::PathBuf;
 use tracing::info;
+use img_editor_lib::{EditorError, ProofEstimate};
 
-use crate::types::ProofResponse;
+use crate::types::{EstimateResponse, ProofResponse};
 
 pub fn load_elf() -> Vec<u8> {
     info!("Loading ELF file...");
@@ -19,18 +20,50 @@ pub fn load_elf() -> Vec<u8> {
 
 impl ProofResponse {
     pub fn error(message: impl Into<String>) -> Response {
+        Self::error_with_status(StatusCode::BAD_REQUEST, "BAD_REQUEST", message)
+    }
+
+    pub fn editor_error(error: &EditorError) -> Response {
+        let status = match error {
+            EditorError::Decode(_)
+            | EditorError::RegionOutOfBounds { .. }
+            | EditorError::InvalidColor(_)
+            | EditorError::Font(_)
+            | EditorError::InvalidTransformation(_)
+            | EditorError::InvalidArgument(_)
+            | EditorError::InvalidProject(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            EditorError::UnsupportedFormat(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            EditorError::LayerNotFound(_) | EditorError::EditNotFound(_) => StatusCode::NOT_FOUND,
+            EditorError::LayerLocked(_) => StatusCode::CONFLICT,
+            EditorError::Encode(_) | EditorError::ImageHashMismatch { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        Self::error_with_status(status, error.code(), error.to_string())
+    }
+
+    pub fn proof_error(message: impl Into<String>) -> Response {
+        Self::error_with_status(StatusCode::INTERNAL_SERVER_ERROR, "PROOF_FAILED", message)
+    }
+
+    fn error_with_status(status: StatusCode, code: &str, message: impl Into<String>) -> Response {
         let response = Self {
             success: false,
             message: message.into(),
+            error_code: Some(code.to_string()),
             final_image: vec![],
             original_image_hash: "0x".to_string(),
             transformed_image_hash: "0x".to_string(),
             signer_public_key: "0x".to_string(),
             has_signature: false,
+            font_hashes: vec![],
+            layer_hashes: vec![],
+            metadata_fields: vec![],
+            output_profile_hash: "0x".to_string(),
+            frames_hash: "0x".to_string(),
             proof_data: None,
         };
         
-        (StatusCode::BAD_REQUEST, Json(response)).into_response()
+        (status, Json(response)).into_response()
     }
 
     pub fn success(
@@ -39,19 +72,43 @@ impl ProofResponse {
         transformed_hash: String,
         signer_key: String,
         has_sig: bool,
+        font_hashes: Vec<String>,
+        layer_hashes: Vec<String>,
+        metadata_fields: Vec<String>,
+        output_profile_hash: String,
+        frames_hash: String,
         proof: Option<crate::types::ProofData>,
     ) -> Response {
         let response = Self {
             success: true,
             message: "Proof generated successfully".to_string(),
+            error_code: None,
             final_image,
             original_image_hash: original_hash,
             transformed_image_hash: transformed_hash,
             signer_public_key: signer_key,
             has_signature: has_sig,
+            font_hashes,
+            layer_hashes,
+            metadata_fields,
+            output_profile_hash,
+            frames_hash,
             proof_data: proof,
         };
 
         (StatusCode::OK, Json(response)).into_response()
     }
-} 
\ No newline at end of file
+}
+
+impl EstimateResponse {
+    pub fn success(estimate: ProofEstimate) -> Response {
+        let response = Self {
+            success: true,
+            message: "Program executed successfully".to_string(),
+            error_code: None,
+            estimate,
+        };
+
+        (StatusCode::OK, Json(response)).into_response()
+    }
+}
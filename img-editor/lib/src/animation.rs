@@ -0,0 +1,316 @@
+#[cfg(feature = "gif")]
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, DynamicImage, Frames, ImageError, ImageFormat, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use crate::color::working_space_transform;
+use crate::formats::{check_decodable, check_encodable, format_name};
+use crate::layer::decode;
+use crate::metadata::{png_chunks, riff_chunks};
+use crate::profile::track;
+use crate::{read_icc_profile, sniff_format, EditorError, EditorResult, FontRegistry, ImageInput, Layer, OutputFormat, Transformation};
+
+/// Speed of the GIF color quantizer, from 1 (best) to 30 (fastest). Frames with at most 256
+/// colors keep their exact palette at any speed.
+#[cfg(feature = "gif")]
+const GIF_QUANTIZER_SPEED: i32 = 10;
+
+/// Frames numbered from zero, `start` included and `end` excluded.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A transformation applied to some frames of an animation, or to all of them when `frames` is
+/// `None`. Transformations that change the size, such as crops and quarter turns, must apply to
+/// every frame.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FrameEdit {
+    pub transformation: Transformation,
+    #[serde(default)]
+    pub frames: Option<FrameRange>,
+}
+
+/// Renders every frame of an animated input instead of only the first.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AnimationInput {
+    /// Applied after `ImageInput::transformations`, which apply to every frame.
+    #[serde(default)]
+    pub frame_edits: Vec<FrameEdit>,
+}
+
+/// One frame, composited onto the full canvas, and how long it is shown.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub image: RgbaImage,
+    pub delay_ms: u32,
+}
+
+/// The frames of an animated GIF, PNG or WebP. Still images of any format open as a single frame.
+#[derive(Debug, Clone)]
+pub struct Animation {
+    image_data: Vec<u8>,
+    frames: Vec<Frame>,
+    loop_count: u32,
+    edits: Vec<FrameEdit>,
+}
+
+fn decode_error(error: ImageError) -> EditorError {
+    match error {
+        ImageError::Unsupported(e) => EditorError::UnsupportedFormat(e.to_string()),
+        e => EditorError::Decode(e.to_string()),
+    }
+}
+
+fn encode_error(error: impl std::fmt::Display) -> EditorError {
+    EditorError::Encode(error.to_string())
+}
+
+fn collect_frames(frames: Frames) -> EditorResult<Vec<Frame>> {
+    frames
+        .map(|frame| {
+            let frame = frame.map_err(decode_error)?;
+            let (numerator, denominator) = frame.delay().numer_denom_ms();
+            Ok(Frame { delay_ms: numerator / denominator.max(1), image: frame.into_buffer() })
+        })
+        .collect()
+}
+
+/// Plays of a GIF: its NETSCAPE2.0 extension counts repeats after the first play, zero meaning
+/// forever, and GIFs without one play once.
+#[cfg(feature = "gif")]
+fn gif_loop_count(image_data: &[u8]) -> u32 {
+    image_data.windows(15)
+        .find(|window| window.starts_with(b"NETSCAPE2.0\x03\x01"))
+        .map_or(1, |window| match u16::from_le_bytes([window[13], window[14]]) {
+            0 => 0,
+            repeats => u32::from(repeats) + 1,
+        })
+}
+
+/// Plays of an APNG, from its `acTL` chunk.
+fn apng_loop_count(image_data: &[u8]) -> u32 {
+    png_chunks(image_data).into_iter()
+        .find(|(kind, _)| kind == b"acTL")
+        .and_then(|(_, data)| Some(u32::from_be_bytes(data.get(4..8)?.try_into().ok()?)))
+        .unwrap_or(0)
+}
+
+/// Plays of an animated WebP, from its `ANIM` chunk.
+fn webp_loop_count(image_data: &[u8]) -> u32 {
+    riff_chunks(image_data).into_iter()
+        .find(|(id, _)| id == b"ANIM")
+        .and_then(|(_, data)| Some(u16::from_le_bytes(data.get(4..6)?.try_into().ok()?)))
+        .map_or(0, u32::from)
+}
+
+impl Animation {
+    /// Decodes every frame. Frames are converted to sRGB like `Layer::new` does, but
+    /// orientation tags are ignored.
+    pub fn new(image_data: &[u8]) -> EditorResult<Self> {
+        let format = sniff_format(image_data)
+            .ok_or_else(|| EditorError::UnsupportedFormat("unrecognized image data".to_string()))?;
+        check_decodable(format)?;
+        let animated = match format {
+            #[cfg(feature = "gif")]
+            ImageFormat::Gif => {
+                let decoder = GifDecoder::new(Cursor::new(image_data)).map_err(decode_error)?;
+                Some((collect_frames(decoder.into_frames())?, gif_loop_count(image_data)))
+            },
+            ImageFormat::Png => {
+                let decoder = PngDecoder::new(Cursor::new(image_data)).map_err(decode_error)?;
+                match decoder.is_apng() {
+                    true => Some((collect_frames(decoder.apng().into_frames())?, apng_loop_count(image_data))),
+                    false => None,
+                }
+            },
+            ImageFormat::WebP => {
+                let decoder = WebPDecoder::new(Cursor::new(image_data)).map_err(decode_error)?;
+                match decoder.has_animation() {
+                    true => Some((collect_frames(decoder.into_frames())?, webp_loop_count(image_data))),
+                    false => None,
+                }
+            },
+            _ => None,
+        };
+
+        let (frames, loop_count) = match animated {
+            Some((mut frames, loop_count)) => {
+                if frames.is_empty() {
+                    return Err(EditorError::Decode("animation has no frames".to_string()));
+                }
+                if let Some(transform) = read_icc_profile(image_data).and_then(|icc| working_space_transform(&icc)) {
+                    for frame in &mut frames {
+                        transform.apply(&mut frame.image, 4);
+                    }
+                }
+                (frames, loop_count)
+            },
+            None => (vec![Frame { image: decode(image_data)?.to_rgba8(), delay_ms: 0 }], 0),
+        };
+        Ok(Animation { image_data: image_data.to_vec(), frames, loop_count, edits: Vec::new() })
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.frames[0].image.dimensions()
+    }
+
+    /// How many times the animation plays, zero meaning forever.
+    pub fn loop_count(&self) -> u32 {
+        self.loop_count
+    }
+
+    /// The edits applied so far, in order.
+    pub fn edits(&self) -> &[FrameEdit] {
+        &self.edits
+    }
+
+    /// Applies `edit` to its frames. Nothing changes when any frame fails.
+    pub fn apply(&mut self, edit: FrameEdit, fonts: &FontRegistry) -> EditorResult<()> {
+        let range = edit.frames.unwrap_or(FrameRange { start: 0, end: self.frames.len() });
+        if range.start >= range.end || range.end > self.frames.len() {
+            return Err(EditorError::InvalidArgument(format!(
+                "frames {}..{} are outside the {} frames of the animation",
+                range.start, range.end, self.frames.len()
+            )));
+        }
+
+        let mut edited = Vec::with_capacity(range.end - range.start);
+        for frame in &self.frames[range.start..range.end] {
+            let mut layer = Layer::from_image(DynamicImage::ImageRgba8(frame.image.clone()), Vec::new());
+            layer.transform(&edit.transformation, fonts)?;
+            edited.push(layer.image.to_rgba8());
+        }
+        let partial = range.end - range.start < self.frames.len();
+        if partial && edited.iter().any(|image| image.dimensions() != self.size()) {
+            return Err(EditorError::InvalidTransformation(
+                "transformations that change the size must apply to every frame".to_string(),
+            ));
+        }
+
+        for (frame, image) in self.frames[range.start..range.end].iter_mut().zip(edited) {
+            frame.image = image;
+        }
+        self.edits.push(edit);
+        Ok(())
+    }
+
+    /// Encodes every frame as an animated GIF or PNG.
+    pub fn encode(&self, format: ImageFormat) -> EditorResult<Vec<u8>> {
+        check_encodable(format)?;
+        match format {
+            #[cfg(feature = "gif")]
+            ImageFormat::Gif => self.encode_gif(),
+            ImageFormat::Png => self.encode_apng(),
+            format => Err(EditorError::UnsupportedFormat(format!(
+                "animations cannot be written as {}, only as gif or png",
+                format_name(format)
+            ))),
+        }
+    }
+
+    /// GIF delays are stored in hundredths of a second, so they are rounded down to 10 ms.
+    #[cfg(feature = "gif")]
+    fn encode_gif(&self) -> EditorResult<Vec<u8>> {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = GifEncoder::new_with_speed(&mut bytes, GIF_QUANTIZER_SPEED);
+            match self.loop_count {
+                0 => encoder.set_repeat(Repeat::Infinite).map_err(encode_error)?,
+                1 => {},
+                plays => {
+                    let repeats = u16::try_from(plays - 1).unwrap_or(u16::MAX);
+                    encoder.set_repeat(Repeat::Finite(repeats)).map_err(encode_error)?
+                },
+            }
+            for frame in &self.frames {
+                let delay = image::Delay::from_numer_denom_ms(frame.delay_ms, 1);
+                encoder.encode_frame(image::Frame::from_parts(frame.image.clone(), 0, 0, delay))
+                    .map_err(encode_error)?;
+            }
+        }
+        Ok(bytes)
+    }
+
+    fn encode_apng(&self) -> EditorResult<Vec<u8>> {
+        let (width, height) = self.size();
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_compression(png::Compression::Fast);
+        encoder.set_animated(self.frames.len() as u32, self.loop_count).map_err(encode_error)?;
+        let mut writer = encoder.write_header().map_err(encode_error)?;
+        for frame in &self.frames {
+            // Delays are a u16 fraction of a second; long ones lose their milliseconds
+            let (numerator, denominator) = match u16::try_from(frame.delay_ms) {
+                Ok(ms) => (ms, 1000),
+                Err(_) => (u16::try_from(frame.delay_ms / 1000).unwrap_or(u16::MAX), 1),
+            };
+            writer.set_frame_delay(numerator, denominator).map_err(encode_error)?;
+            writer.write_image_data(frame.image.as_raw()).map_err(encode_error)?;
+        }
+        writer.finish().map_err(encode_error)?;
+        Ok(bytes)
+    }
+
+    /// Hashes the frame sequence independently of its encoding: `hash` of the width and height as
+    /// big-endian `u32`s and the loop count as a big-endian `u32`, followed for every frame by its
+    /// delay in milliseconds as a big-endian `u32` and the `hash` of its RGBA pixels.
+    pub fn frame_sequence_hash(&self, hash: impl Fn(&[u8]) -> [u8; 32]) -> [u8; 32] {
+        let (width, height) = self.size();
+        let mut sequence = Vec::with_capacity(12 + self.frames.len() * 36);
+        sequence.extend_from_slice(&width.to_be_bytes());
+        sequence.extend_from_slice(&height.to_be_bytes());
+        sequence.extend_from_slice(&self.loop_count.to_be_bytes());
+        for frame in &self.frames {
+            sequence.extend_from_slice(&frame.delay_ms.to_be_bytes());
+            sequence.extend_from_slice(&hash(frame.image.as_raw()));
+        }
+        hash(&sequence)
+    }
+
+    /// The `ImageInput` that proves the edits applied so far, written as an animated `format`.
+    pub fn proof_input(&self, output_format: OutputFormat) -> ImageInput {
+        ImageInput {
+            image_data: self.image_data.clone(),
+            transformations: Vec::new(),
+            signature_data: None,
+            fonts: Vec::new(),
+            layers: Vec::new(),
+            output_format,
+            encode_options: Default::default(),
+            animation: Some(AnimationInput { frame_edits: self.edits.clone() }),
+            tile_rows: None,
+            canvas: None,
+        }
+    }
+}
+
+/// Renders an animated `ImageInput`. Encode options other than the format do not apply, and
+/// extra layers and canvases are not supported.
+pub(crate) fn render_animation(input: &ImageInput, animation_input: &AnimationInput, fonts: &FontRegistry) -> EditorResult<Vec<u8>> {
+    if !input.layers.is_empty() || input.canvas.is_some() {
+        return Err(EditorError::InvalidArgument("animated images cannot have extra layers or a canvas".to_string()));
+    }
+    let mut animation = track(format_args!("decode"), || Animation::new(&input.image_data))?;
+    for (index, transformation) in input.transformations.iter().enumerate() {
+        let edit = FrameEdit { transformation: transformation.clone(), frames: None };
+        track(format_args!("transform_{index}"), || animation.apply(edit, fonts))?;
+    }
+    for (index, edit) in animation_input.frame_edits.iter().enumerate() {
+        track(format_args!("frame_edit_{index}"), || animation.apply(edit.clone(), fonts))?;
+    }
+    track(format_args!("encode"), || animation.encode(input.output_format.into()))
+}
@@ -0,0 +1,122 @@
+use image::{DynamicImage, ImageFormat};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use crate::icc::{Profile, Transform};
+use crate::read_icc_profile;
+
+/// ICC color space signatures, stored at offset 16 of a profile.
+const RGB_SIGNATURE: &[u8] = b"RGB ";
+const GRAY_SIGNATURE: &[u8] = b"GRAY";
+
+/// Color profile written into an output. Pixels are always edited in sRGB, the working space:
+/// images tagged with another RGB profile, such as Display P3 or Adobe RGB, are converted to sRGB
+/// when they are decoded.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorProfilePolicy {
+    /// Write sRGB pixels without a profile, which viewers display as sRGB.
+    #[default]
+    Srgb,
+    /// Convert back to the profile of the source image and embed it, so the output keeps the
+    /// source's color space. Colors outside sRGB were clipped by the working space. Only JPEG,
+    /// PNG and WebP outputs carry a profile; other formats are written as sRGB.
+    Source,
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+/// Reads the `desc` tag of an ICC profile, in its version 2 `desc` or version 4 `mluc` form.
+pub(crate) fn profile_description(icc: &[u8]) -> Option<String> {
+    let tag_count = read_u32(icc, 128)? as usize;
+    let (offset, size) = (0..tag_count.min(256))
+        .map(|index| 132 + index * 12)
+        .find(|&entry| icc.get(entry..entry + 4) == Some(b"desc"))
+        .and_then(|entry| Some((read_u32(icc, entry + 4)? as usize, read_u32(icc, entry + 8)? as usize)))?;
+    let tag = icc.get(offset..offset.checked_add(size)?)?;
+    let text = match tag.get(..4)? {
+        b"desc" => {
+            let length = read_u32(tag, 8)? as usize;
+            let ascii = tag.get(12..12usize.checked_add(length)?)?;
+            String::from_utf8_lossy(ascii.split(|&b| b == 0).next()?).into_owned()
+        },
+        b"mluc" => {
+            // First record: language, country, length and offset from the start of the tag
+            let length = read_u32(tag, 20)? as usize;
+            let start = read_u32(tag, 24)? as usize;
+            let utf16: Vec<u16> = tag.get(start..start.checked_add(length)?)?
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&utf16).trim_end_matches('\0').to_string()
+        },
+        _ => return None,
+    };
+    Some(text.trim().to_string())
+}
+
+/// Parses an RGB profile whose pixels must be converted to reach sRGB. Profiles describing
+/// themselves as sRGB are taken at their word, so sRGB images decode exactly as untagged ones.
+fn conversion_profile(icc: &[u8]) -> Option<Profile> {
+    if profile_description(icc).is_some_and(|description| description.to_ascii_lowercase().starts_with("srgb")) {
+        return None;
+    }
+    Profile::parse(icc)
+}
+
+/// Converts 8-bit RGB or RGBA pixels. Other layouts are reduced to 8 bits first.
+pub(crate) fn convert(image: &DynamicImage, transform: &Transform) -> DynamicImage {
+    if image.color().has_alpha() {
+        let mut pixels = image.to_rgba8();
+        transform.apply(&mut pixels, 4);
+        DynamicImage::ImageRgba8(pixels)
+    } else {
+        let mut pixels = image.to_rgb8();
+        transform.apply(&mut pixels, 3);
+        DynamicImage::ImageRgb8(pixels)
+    }
+}
+
+/// The conversion from an image tagged with `icc` into the sRGB working space, built once for
+/// images converted piece by piece. `None` for non-RGB profiles and profiles without usable
+/// tags, which are left untouched, as browsers do.
+pub(crate) fn working_space_transform(icc: &[u8]) -> Option<Transform> {
+    Transform::to_srgb(&conversion_profile(icc)?)
+}
+
+/// Converts a decoded image tagged with `icc` into the sRGB working space. Grayscale images are
+/// left untouched, as are those `working_space_transform` leaves alone.
+pub(crate) fn to_working_space(image: DynamicImage, icc: &[u8]) -> DynamicImage {
+    if image.color().channel_count() < 3 {
+        return image;
+    }
+    match working_space_transform(icc) {
+        Some(transform) => convert(&image, &transform),
+        None => image,
+    }
+}
+
+/// Prepares an encoder-ready image for embedding `icc`: RGB images are converted from sRGB back
+/// into the profile's space. `None` when the profile cannot describe the image, for example an
+/// RGB profile once the image was turned to grayscale, in which case no profile is embedded.
+pub(crate) fn to_output_space<'a>(image: &'a DynamicImage, icc: &[u8]) -> Option<Cow<'a, DynamicImage>> {
+    let rgb = image.color().channel_count() >= 3;
+    match icc.get(16..20)? {
+        RGB_SIGNATURE if rgb => match conversion_profile(icc) {
+            Some(profile) => Transform::from_srgb(&profile).map(|transform| Cow::Owned(convert(image, &transform))),
+            // Left in the source's space when decoding, so it still matches the profile
+            None => Some(Cow::Borrowed(image)),
+        },
+        GRAY_SIGNATURE if !rgb => Some(Cow::Borrowed(image)),
+        _ => None,
+    }
+}
+
+/// The ICC profile of `source` that `policy` embeds in an output encoded as `format`.
+pub(crate) fn output_profile(policy: ColorProfilePolicy, source: &[u8], format: ImageFormat) -> Option<Vec<u8>> {
+    let carries_profile = matches!(format, ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP);
+    match policy {
+        ColorProfilePolicy::Source if carries_profile => read_icc_profile(source),
+        _ => None,
+    }
+}
@@ -0,0 +1,429 @@
+//! Cases that must come out the same on every target: natively, in the WASM preview and in the
+//! zkVM. The hash functions are checked against published test vectors, then each case renders a
+//! synthetic image through `render_input` and compares the SHA-256 of the output with a
+//! checked-in hash, which is the `transformed_image_hash` the program commits for the same input.
+//! Run them with `run_conformance`, which WASM builds with the `conformance` feature also export,
+//! or with the script's `conformance` binary, which proves each case as well. The module is only
+//! compiled with that feature, so the program does not carry the fixtures.
+use image::{DynamicImage, ImageBuffer, Rgb, Rgba};
+use serde::Serialize;
+use crate::layer::encode;
+use crate::hash::{sha256, to_hex, KNOWN_ANSWERS};
+use crate::metadata::embed_metadata;
+use crate::{
+    render_input, Anchor, CanvasInput, ColorProfilePolicy, EditorResult, EncodeOptions, FontRegistry, HorizontalAlign,
+    ImageInput, LayerInput, MetadataPolicy, OutputFormat, Region, TextOverlayParameters, TextShadow, TextStroke,
+    Transformation, VerticalAlign,
+};
+
+pub struct ConformanceCase {
+    pub name: &'static str,
+    pub input: ImageInput,
+    /// Hex SHA-256 of the rendered PNG.
+    pub expected_hash: &'static str,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ConformanceResult {
+    pub name: String,
+    pub expected_hash: String,
+    pub actual_hash: String,
+    pub passed: bool,
+}
+
+/// An RGBA image with gradients, a hard-edged pattern and partly transparent corner, so every
+/// channel and both kinds of edges go through the kernels.
+fn test_image(width: u32, height: u32) -> Vec<u8> {
+    let image = ImageBuffer::from_fn(width, height, |x, y| {
+        let pattern = if (x / 8 + y / 8) % 2 == 0 { 200 } else { 40 };
+        let alpha = if x < width / 4 && y < height / 4 { 96 + x * 4 } else { 255 };
+        Rgba([(x * 255 / width) as u8, (y * 255 / height) as u8, pattern, alpha.min(255) as u8])
+    });
+    encode(&DynamicImage::ImageRgba8(image), image::ImageFormat::Png).expect("test image encodes")
+}
+
+/// A 16-bit RGB gradient whose low bytes vary too.
+fn test_image_16(width: u32, height: u32) -> Vec<u8> {
+    let image = ImageBuffer::from_fn(width, height, |x, y| {
+        Rgb([(x * 65535 / width) as u16, (y * 65535 / height) as u16, ((x * 7919 + y * 104729) % 65536) as u16])
+    });
+    encode(&DynamicImage::ImageRgb16(image), image::ImageFormat::Png).expect("test image encodes")
+}
+
+/// A floating point RGBA TIFF whose samples fall between 16-bit steps and partly outside [0, 1].
+#[cfg(feature = "tiff")]
+fn test_image_float(width: u32, height: u32) -> Vec<u8> {
+    let samples: Vec<f32> = (0..width * height)
+        .flat_map(|index| {
+            let (x, y) = (index % width, index / width);
+            [x as f32 / width as f32 * 1.25 - 0.1, y as f32 / height as f32, ((x * 7 + y * 13) % 17) as f32 / 16.0, 0.8]
+        })
+        .collect();
+    let mut bytes = std::io::Cursor::new(Vec::new());
+    tiff::encoder::TiffEncoder::new(&mut bytes)
+        .and_then(|mut encoder| encoder.write_image::<tiff::encoder::colortype::RGBA32Float>(width, height, &samples))
+        .expect("test image encodes");
+    bytes.into_inner()
+}
+
+fn push_u16(bytes: &mut Vec<u8>, values: impl IntoIterator<Item = u16>) {
+    bytes.extend(values.into_iter().flat_map(u16::to_be_bytes));
+}
+
+fn push_i32(bytes: &mut Vec<u8>, values: impl IntoIterator<Item = i32>) {
+    bytes.extend(values.into_iter().flat_map(i32::to_be_bytes));
+}
+
+fn pad(bytes: &mut Vec<u8>) {
+    bytes.resize(bytes.len().next_multiple_of(4), 0);
+}
+
+/// A `curv` tag: the identity for gamma 0, otherwise a u8Fixed8 exponent.
+fn curve_tag(gamma: u16) -> Vec<u8> {
+    let mut tag = b"curv\0\0\0\0".to_vec();
+    push_i32(&mut tag, [(gamma != 0) as i32]);
+    push_u16(&mut tag, (gamma != 0).then_some(gamma));
+    tag
+}
+
+fn parametric_tag(function: u16, parameters: &[i32]) -> Vec<u8> {
+    let mut tag = b"para\0\0\0\0".to_vec();
+    push_u16(&mut tag, [function, 0]);
+    push_i32(&mut tag, parameters.iter().copied());
+    tag
+}
+
+/// The same curve for each of the three channels, as LUT tags nest them.
+fn three_curves(curve: &[u8]) -> Vec<u8> {
+    let mut curves = Vec::new();
+    for _ in 0..3 {
+        curves.extend_from_slice(curve);
+        pad(&mut curves);
+    }
+    curves
+}
+
+/// An `mAB ` or `mBA ` tag from its B curves, matrix, M curves, CLUT and A curves.
+fn lut_tag(signature: &[u8; 4], elements: [Option<Vec<u8>>; 5]) -> Vec<u8> {
+    let mut tag = signature.to_vec();
+    tag.extend_from_slice(&[0, 0, 0, 0, 3, 3, 0, 0]);
+    tag.resize(32, 0);
+    for (index, element) in elements.into_iter().enumerate() {
+        if let Some(element) = element {
+            let offset = tag.len() as u32;
+            tag[12 + 4 * index..16 + 4 * index].copy_from_slice(&offset.to_be_bytes());
+            tag.extend(element);
+            pad(&mut tag);
+        }
+    }
+    tag
+}
+
+/// The CLUT element of a LUT tag with 16-bit samples.
+fn clut(nodes: u8, values: impl IntoIterator<Item = u16>) -> Vec<u8> {
+    let mut clut = vec![0; 20];
+    clut[..3].fill(nodes);
+    clut[16] = 2;
+    push_u16(&mut clut, values);
+    clut
+}
+
+/// An RGB display profile with a `pcs` connection space.
+fn icc_profile(version: u8, pcs: &[u8; 4], tags: Vec<(&[u8; 4], Vec<u8>)>) -> Vec<u8> {
+    let mut profile = vec![0; 128];
+    profile[8] = version;
+    profile[12..20].copy_from_slice(b"mntrRGB ");
+    profile[20..24].copy_from_slice(pcs);
+    profile[36..40].copy_from_slice(b"acsp");
+    push_i32(&mut profile, [tags.len() as i32]);
+    let table = profile.len();
+    profile.resize(table + 12 * tags.len(), 0);
+    for (index, (signature, data)) in tags.into_iter().enumerate() {
+        let entry = table + 12 * index;
+        let offset = profile.len() as u32;
+        profile[entry..entry + 4].copy_from_slice(signature);
+        profile[entry + 4..entry + 8].copy_from_slice(&offset.to_be_bytes());
+        profile[entry + 8..entry + 12].copy_from_slice(&(data.len() as u32).to_be_bytes());
+        profile.extend(data);
+        pad(&mut profile);
+    }
+    let size = profile.len() as u32;
+    profile[..4].copy_from_slice(&size.to_be_bytes());
+    profile
+}
+
+/// Display P3 colorants adapted to D50, as s15Fixed16 XYZ columns.
+const DISPLAY_P3: [[i32; 3]; 3] = [[33758, 15807, -72], [19137, 45364, 2746], [10296, 4365, 51387]];
+
+/// Display P3 as a matrix/TRC profile, with the sRGB tone curve.
+fn display_p3_profile() -> Vec<u8> {
+    let curve = parametric_tag(3, &[0x26666, 0xF2A7, 0x0D59, 0x13D0, 0x0A5B]);
+    let mut tags = Vec::new();
+    for (signature, colorant) in [b"rXYZ", b"gXYZ", b"bXYZ"].into_iter().zip(DISPLAY_P3) {
+        let mut tag = b"XYZ \0\0\0\0".to_vec();
+        push_i32(&mut tag, colorant);
+        tags.push((signature, tag));
+    }
+    tags.extend([b"rTRC", b"gTRC", b"bTRC"].map(|signature| (signature, curve.clone())));
+    icc_profile(2, b"XYZ ", tags)
+}
+
+/// P3 primaries with a gamma of 1.8 stored as version 4 LUTs. `A2B0` runs the gamma curves into
+/// a CLUT of XYZ values; `B2A0` runs the inverse matrix into curves of gamma 1/1.8.
+fn p3_lut_profile() -> Vec<u8> {
+    const NODES: i32 = 5;
+    let mut xyz = Vec::new();
+    for node in 0..NODES * NODES * NODES {
+        let rgb = [node / (NODES * NODES), node / NODES % NODES, node % NODES];
+        // u1Fixed15 XYZ of the linear node
+        xyz.extend((0..3).map(|row| {
+            let sum: i32 = (0..3).map(|column| DISPLAY_P3[column][row] * rgb[column]).sum();
+            (sum / (2 * (NODES - 1))).max(0) as u16
+        }));
+    }
+    let identity = three_curves(&curve_tag(0));
+    let a2b0 = lut_tag(b"mAB ", [
+        Some(identity.clone()), None, None, Some(clut(NODES as u8, xyz)), Some(three_curves(&curve_tag(0x01CD))),
+    ]);
+    let mut matrix = Vec::new();
+    push_i32(&mut matrix, [315108, -129775, -52112, -110409, 235803, 2092, 6341, -12783, 166974, 0, 0, 0]);
+    let b2a0 = lut_tag(b"mBA ", [
+        Some(identity.clone()), Some(matrix), Some(identity), None, Some(three_curves(&parametric_tag(0, &[36409]))),
+    ]);
+    icc_profile(4, b"XYZ ", vec![(b"A2B0", a2b0), (b"B2A0", b2a0)])
+}
+
+/// A version 2 profile with a Lab connection space and a 16-bit `mft2` LUT.
+fn lab_lut_profile() -> Vec<u8> {
+    let mut tag = b"mft2\0\0\0\0".to_vec();
+    tag.extend_from_slice(&[3, 3, 3, 0]);
+    push_i32(&mut tag, [0x10000, 0, 0, 0, 0x10000, 0, 0, 0, 0x10000]);
+    let input = [0, 9000, 28000, 65535];
+    let output = [0, 65535];
+    push_u16(&mut tag, [input.len() as u16, output.len() as u16]);
+    push_u16(&mut tag, input.repeat(3));
+    for node in 0..27 {
+        let [red, green, blue] = [node / 9, node / 3 % 3, node % 3];
+        // L* from 0 to 100 in steps of 0xFF00 / 16, a* and b* within ±80
+        let lightness = (2 * red + 5 * green + blue) * 0xFF00 / 16;
+        push_u16(&mut tag, [lightness, 0x8000 + (red - green) * 40 * 256, 0x8000 + (green - blue) * 40 * 256].map(|v| v as u16));
+    }
+    push_u16(&mut tag, output.repeat(3));
+    icc_profile(2, b"Lab ", vec![(b"A2B0", tag)])
+}
+
+/// `image_data`, a PNG, with `icc` embedded.
+fn tagged(image_data: Vec<u8>, icc: &[u8]) -> Vec<u8> {
+    embed_metadata(image_data, image::ImageFormat::Png, &MetadataPolicy::Strip, Some(icc), &[]).expect("profile embeds")
+}
+
+fn input(image_data: Vec<u8>, transformations: Vec<Transformation>) -> ImageInput {
+    ImageInput {
+        image_data,
+        transformations,
+        signature_data: None,
+        fonts: vec![],
+        layers: vec![],
+        output_format: OutputFormat::Png,
+        encode_options: EncodeOptions::default(),
+        animation: None,
+        tile_rows: None,
+        canvas: None,
+    }
+}
+
+fn text(text: &str, x: u32, y: u32, size: u32) -> TextOverlayParameters {
+    TextOverlayParameters {
+        text: text.to_string(),
+        x,
+        y,
+        size,
+        color: "#ffffff".to_string(),
+        max_width: None,
+        line_height: 1.0,
+        align: HorizontalAlign::Left,
+        vertical_align: VerticalAlign::Top,
+        anchor: Anchor::TopLeft,
+        rotation: 0.0,
+        letter_spacing: 0.0,
+        stroke: None,
+        shadow: None,
+        font: None,
+    }
+}
+
+pub fn conformance_cases() -> Vec<ConformanceCase> {
+    let image = test_image(96, 64);
+    let region = Some(Region { x: 20, y: 10, width: 50, height: 40 });
+    let case = |name, expected_hash, transformations| ConformanceCase {
+        name,
+        input: input(image.clone(), transformations),
+        expected_hash,
+    };
+
+    let layered = |canvas| ImageInput {
+        layers: vec![LayerInput {
+            image_data: test_image(40, 30),
+            transformations: vec![Transformation::Blur { sigma: 1.5, region: None }],
+            x: 30,
+            y: 20,
+            opacity: 0.6,
+            mask: None,
+            clipped: false,
+        }],
+        canvas,
+        ..input(image.clone(), vec![Transformation::Contrast { contrast: 12.5, region: None }])
+    };
+
+    vec![
+        case(
+            "contrast",
+            "5aeea72be9c3bc5f207ef46a125141ddc44a6183f3918baef4c75072650d829d",
+            vec![Transformation::Contrast { contrast: 35.5, region: None }],
+        ),
+        case(
+            "contrast region",
+            "23b98663759a627357a003abd7dcf3ced2eb3377e96f966f39ebabd2a87cfbb3",
+            vec![Transformation::Contrast { contrast: -60.0, region: region.clone() }],
+        ),
+        case(
+            "blur",
+            "3b0518526a900a4562d0b5472b6bb749738d2a0b03ed49ceb8bd5b119cb7cb19",
+            vec![Transformation::Blur { sigma: 2.5, region: None }],
+        ),
+        case(
+            "blur region",
+            "a6446fc71b0c5b47efd48fbf3a0f6e7201e5f7bff637c3eb89992fbd6abd7845",
+            vec![Transformation::Blur { sigma: 1.25, region: region.clone() }],
+        ),
+        case(
+            "text",
+            "b0992714b82b699176d047f713295f60c67cfc9d4b8d931a8b1d41f7cdfb71da",
+            vec![Transformation::TextOverlay(text("Proof 42", 4, 8, 22))],
+        ),
+        case(
+            "text rotated",
+            "4f4003a9fffbd612f09cad0980d3369c2eacf02d4bd1ddcee33de3e7002d80a9",
+            vec![Transformation::TextOverlay(TextOverlayParameters {
+                anchor: Anchor::Center,
+                rotation: 30.0,
+                ..text("Tilted", 48, 32, 18)
+            })],
+        ),
+        case(
+            "text stroke and shadow",
+            "26abce0cc99ebde2bfc632ee79f9ac0e7bf8436b6274e6b685d506dc292e4945",
+            vec![Transformation::TextOverlay(TextOverlayParameters {
+                color: "#ffd700".to_string(),
+                stroke: Some(TextStroke { width: 2.5, color: "#000000".to_string() }),
+                shadow: Some(TextShadow { offset_x: 2, offset_y: 3, blur: 2, color: "#00000080".to_string() }),
+                ..text("Two\nlines", 10, 4, 20)
+            })],
+        ),
+        case(
+            "text wrapped",
+            "07fdfa796336a329a83d9e545fcba8cc24178327cbd3fa92b9687da6e66c86bd",
+            vec![Transformation::TextOverlay(TextOverlayParameters {
+                max_width: Some(70),
+                line_height: 1.2,
+                align: HorizontalAlign::Center,
+                letter_spacing: 1.5,
+                ..text("wrap these few words", 8, 2, 14)
+            })],
+        ),
+        case(
+            "chain",
+            "4e4ca50201bd8236cb4e868647f5a8bcbec6ded367bb87db007221246fa67307",
+            vec![
+                Transformation::Brighten { value: 20, region: region.clone() },
+                Transformation::Grayscale { region: region.clone() },
+                Transformation::Contrast { contrast: 20.0, region: None },
+                Transformation::Blur { sigma: 0.8, region: region.clone() },
+                Transformation::Rotate90,
+            ],
+        ),
+        ConformanceCase {
+            name: "16-bit contrast and blur",
+            input: input(test_image_16(64, 48), vec![
+                Transformation::Contrast { contrast: 25.0, region: None },
+                Transformation::Blur { sigma: 2.0, region: Some(Region { x: 8, y: 8, width: 40, height: 30 }) },
+            ]),
+            expected_hash: "dffc3540b3a7182d3469d83c12d4ded520f28595eb4d4f293f8c0b453403ceb5",
+        },
+        ConformanceCase {
+            name: "layer opacity",
+            input: layered(None),
+            expected_hash: "33cddf0153d8286a78e21e5112f6f2c6fc1510f8fc779f019f9934db9bef85e2",
+        },
+        ConformanceCase {
+            name: "canvas",
+            // A canvas larger than the base image, with the base moved partly off it
+            input: layered(Some(CanvasInput { width: 120, height: 80, x: -12, y: 6 })),
+            expected_hash: "240a66becf2881d986b30c6438fd32dd81f58104cb339b13e5be412921fc9297",
+        },
+        ConformanceCase {
+            name: "icc matrix/TRC",
+            input: input(tagged(image.clone(), &display_p3_profile()), vec![Transformation::Contrast { contrast: 10.0, region: None }]),
+            expected_hash: "4f4f0a01d76685db9a3b14f7658ad2f1ac2223d0b4f3db85ee733448457646be",
+        },
+        ConformanceCase {
+            name: "icc matrix/TRC to source",
+            input: ImageInput {
+                encode_options: EncodeOptions { color_profile: ColorProfilePolicy::Source, ..EncodeOptions::default() },
+                ..input(tagged(image.clone(), &display_p3_profile()), vec![Transformation::Grayscale { region: region.clone() }])
+            },
+            expected_hash: "c93c0cb7cc4fc0480032caf102d1168bdc7cf991a6365284faaa1cc00cebcc5d",
+        },
+        ConformanceCase {
+            name: "icc LUT to source",
+            input: ImageInput {
+                encode_options: EncodeOptions { color_profile: ColorProfilePolicy::Source, ..EncodeOptions::default() },
+                ..input(tagged(image.clone(), &p3_lut_profile()), vec![Transformation::Brighten { value: 15, region: None }])
+            },
+            expected_hash: "f096c6f6c1747b11ad6015bc34d1b022981a0ef0e468b4fac8fde40854692564",
+        },
+        ConformanceCase {
+            name: "icc Lab LUT",
+            input: input(tagged(image.clone(), &lab_lut_profile()), vec![]),
+            expected_hash: "b47d8dcac242c4716001e885993afd02536117abcfabc016b91463fa5c2ef71b",
+        },
+        #[cfg(feature = "tiff")]
+        ConformanceCase {
+            name: "float contrast, blur and brighten",
+            input: input(test_image_float(64, 48), vec![
+                Transformation::Contrast { contrast: 15.0, region: None },
+                Transformation::Blur { sigma: 1.5, region: Some(Region { x: 10, y: 6, width: 36, height: 30 }) },
+                Transformation::Brighten { value: -12, region: None },
+            ]),
+            expected_hash: "3ce25902b6840a88e314e0f6b2e3657985263c4d5a2043eb426737efa0f48e08",
+        },
+    ]
+}
+
+/// Hashes every published test vector with the hash it is for.
+pub fn check_known_answers() -> Vec<ConformanceResult> {
+    KNOWN_ANSWERS.iter()
+        .map(|&(name, hash, input, expected)| result(name, expected, to_hex(&hash(input))))
+        .collect()
+}
+
+/// Checks the hash functions against their known answers, then renders every case and compares
+/// its hash with the expected one.
+pub fn run_conformance() -> EditorResult<Vec<ConformanceResult>> {
+    let mut results = check_known_answers();
+    let fonts = FontRegistry::new();
+    for case in conformance_cases() {
+        let output = render_input(&case.input, &fonts)?;
+        results.push(result(case.name, case.expected_hash, to_hex(&sha256(&output))));
+    }
+    Ok(results)
+}
+
+fn result(name: &str, expected_hash: &str, actual_hash: String) -> ConformanceResult {
+    ConformanceResult {
+        name: name.to_string(),
+        expected_hash: expected_hash.to_string(),
+        passed: actual_hash == expected_hash,
+        actual_hash,
+    }
+}
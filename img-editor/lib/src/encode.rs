@@ -0,0 +1,300 @@
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::codecs::webp::WebPEncoder;
+use image::{DynamicImage, ImageBuffer, ImageEncoder, ImageFormat, Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use crate::color::{output_profile, to_output_space};
+use crate::fixed::div_round;
+use crate::formats::check_encodable;
+use crate::layer::{encode, is_16_bit};
+use crate::text::{blend, parse_color, FULL_COVERAGE};
+use crate::metadata::embed_metadata;
+use crate::{ColorProfilePolicy, EditorError, EditorResult, MetadataPolicy};
+
+/// Format of a proven image. Names match the `format` strings taken by `get_layer` and `export`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    Jpeg,
+    WebP,
+    /// Needs the `gif` feature. Still images are reduced to a 256 color palette.
+    Gif,
+}
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::WebP => "webp",
+            OutputFormat::Gif => "gif",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "image/png",
+            OutputFormat::Jpeg => "image/jpeg",
+            OutputFormat::WebP => "image/webp",
+            OutputFormat::Gif => "image/gif",
+        }
+    }
+}
+
+impl From<OutputFormat> for ImageFormat {
+    fn from(format: OutputFormat) -> Self {
+        match format {
+            OutputFormat::Png => ImageFormat::Png,
+            OutputFormat::Jpeg => ImageFormat::Jpeg,
+            OutputFormat::WebP => ImageFormat::WebP,
+            OutputFormat::Gif => ImageFormat::Gif,
+        }
+    }
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = EditorError;
+
+    fn from_str(format: &str) -> EditorResult<Self> {
+        match format.to_ascii_lowercase().as_str() {
+            "png" => Ok(OutputFormat::Png),
+            "jpeg" | "jpg" => Ok(OutputFormat::Jpeg),
+            "webp" => Ok(OutputFormat::WebP),
+            "gif" => Ok(OutputFormat::Gif),
+            _ => Err(EditorError::UnsupportedFormat(format.to_string())),
+        }
+    }
+}
+
+/// Encoder settings for every output format. Only the options of the format being written are
+/// used, so one value can be passed around regardless of the format the user picks.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct EncodeOptions {
+    pub jpeg: JpegOptions,
+    pub png: PngOptions,
+    pub alpha: AlphaHandling,
+    /// Metadata copied from the image the output was made from.
+    pub metadata: MetadataPolicy,
+    /// Color profile of the output, taken from the same image.
+    pub color_profile: ColorProfilePolicy,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct JpegOptions {
+    /// 1 to 100.
+    pub quality: u8,
+    pub subsampling: ChromaSubsampling,
+}
+
+impl Default for JpegOptions {
+    fn default() -> Self {
+        Self { quality: 90, subsampling: ChromaSubsampling::default() }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChromaSubsampling {
+    /// Full resolution chroma.
+    Yuv444,
+    /// Chroma halved horizontally.
+    Yuv422,
+    /// Chroma halved in both directions, the usual choice for photos.
+    #[default]
+    Yuv420,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct PngOptions {
+    pub compression: PngCompression,
+    pub filter: PngFilter,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PngCompression {
+    #[default]
+    Fast,
+    Balanced,
+    Best,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PngFilter {
+    NoFilter,
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+    /// Picks the best filter for each row.
+    #[default]
+    Adaptive,
+}
+
+/// What happens to transparency when encoding.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub enum AlphaHandling {
+    /// Keep the alpha channel when the format has one. JPEG has none, so its alpha is dropped.
+    #[default]
+    Keep,
+    /// Drop the alpha channel, revealing the color stored under transparent pixels.
+    Discard,
+    /// Composite onto an opaque `#RRGGBB` background.
+    Flatten { background: String },
+}
+
+impl From<PngCompression> for CompressionType {
+    fn from(compression: PngCompression) -> Self {
+        match compression {
+            PngCompression::Fast => CompressionType::Fast,
+            PngCompression::Balanced => CompressionType::Default,
+            PngCompression::Best => CompressionType::Best,
+        }
+    }
+}
+
+impl From<PngFilter> for FilterType {
+    fn from(filter: PngFilter) -> Self {
+        match filter {
+            PngFilter::NoFilter => FilterType::NoFilter,
+            PngFilter::Sub => FilterType::Sub,
+            PngFilter::Up => FilterType::Up,
+            PngFilter::Avg => FilterType::Avg,
+            PngFilter::Paeth => FilterType::Paeth,
+            PngFilter::Adaptive => FilterType::Adaptive,
+        }
+    }
+}
+
+impl From<ChromaSubsampling> for jpeg_encoder::SamplingFactor {
+    fn from(subsampling: ChromaSubsampling) -> Self {
+        match subsampling {
+            ChromaSubsampling::Yuv444 => jpeg_encoder::SamplingFactor::R_4_4_4,
+            ChromaSubsampling::Yuv422 => jpeg_encoder::SamplingFactor::R_4_2_2,
+            ChromaSubsampling::Yuv420 => jpeg_encoder::SamplingFactor::R_4_2_0,
+        }
+    }
+}
+
+fn encode_error(e: impl std::fmt::Display) -> EditorError {
+    EditorError::Encode(e.to_string())
+}
+
+fn apply_alpha<'a>(image: &'a DynamicImage, alpha: &AlphaHandling) -> EditorResult<Cow<'a, DynamicImage>> {
+    if !image.color().has_alpha() {
+        return Ok(Cow::Borrowed(image));
+    }
+    match alpha {
+        AlphaHandling::Keep => Ok(Cow::Borrowed(image)),
+        AlphaHandling::Discard if is_16_bit(image) => Ok(Cow::Owned(DynamicImage::ImageRgb16(image.to_rgb16()))),
+        AlphaHandling::Discard => Ok(Cow::Owned(DynamicImage::ImageRgb8(image.to_rgb8()))),
+        AlphaHandling::Flatten { background } if is_16_bit(image) => {
+            // Over an opaque background `blend` reduces to this mix, rounded once
+            const MAX: i64 = u16::MAX as i64;
+            let background = parse_color(background)?.0.map(|channel| channel as i64 * 257);
+            let rgba = image.to_rgba16();
+            let flattened = ImageBuffer::from_fn(rgba.width(), rgba.height(), |x, y| {
+                let pixel = rgba.get_pixel(x, y);
+                let alpha = pixel[3] as i64;
+                Rgb([0, 1, 2].map(|channel| {
+                    div_round(pixel[channel] as i64 * alpha + background[channel] * (MAX - alpha), MAX) as u16
+                }))
+            });
+            Ok(Cow::Owned(DynamicImage::ImageRgb16(flattened)))
+        },
+        AlphaHandling::Flatten { background } => {
+            let mut background = parse_color(background)?;
+            background[3] = 255;
+            let rgba = image.to_rgba8();
+            let flattened = RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+                let mut pixel = background;
+                blend(&mut pixel, *rgba.get_pixel(x, y), FULL_COVERAGE);
+                Rgb([pixel[0], pixel[1], pixel[2]])
+            });
+            Ok(Cow::Owned(DynamicImage::ImageRgb8(flattened)))
+        },
+    }
+}
+
+fn encode_jpeg(image: &DynamicImage, options: &JpegOptions) -> EditorResult<Vec<u8>> {
+    if !(1..=100).contains(&options.quality) {
+        return Err(EditorError::InvalidArgument(format!(
+            "JPEG quality must be between 1 and 100, got {}",
+            options.quality
+        )));
+    }
+    let (width, height) = match (u16::try_from(image.width()), u16::try_from(image.height())) {
+        (Ok(width), Ok(height)) => (width, height),
+        _ => return Err(encode_error("JPEG images are limited to 65535 pixels per side")),
+    };
+
+    let mut bytes = Vec::new();
+    let mut encoder = jpeg_encoder::Encoder::new(&mut bytes, options.quality);
+    encoder.set_sampling_factor(options.subsampling.into());
+    match image {
+        DynamicImage::ImageLuma8(gray) => encoder.encode(gray.as_raw(), width, height, jpeg_encoder::ColorType::Luma),
+        _ => encoder.encode(image.to_rgb8().as_raw(), width, height, jpeg_encoder::ColorType::Rgb),
+    }
+    .map_err(encode_error)?;
+    Ok(bytes)
+}
+
+fn encode_png(image: &DynamicImage, options: &PngOptions) -> EditorResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    PngEncoder::new_with_quality(&mut bytes, options.compression.into(), options.filter.into())
+        .write_image(image.as_bytes(), image.width(), image.height(), image.color())
+        .map_err(|e| match e {
+            image::ImageError::Unsupported(e) => EditorError::UnsupportedFormat(e.to_string()),
+            e => EditorError::Encode(e.to_string()),
+        })?;
+    Ok(bytes)
+}
+
+/// Converts to 8 bits per channel, RGB or RGBA, for encoders that only take those layouts.
+fn to_rgb8_layout(image: &DynamicImage) -> Cow<'_, DynamicImage> {
+    match image {
+        DynamicImage::ImageRgb8(_) | DynamicImage::ImageRgba8(_) => Cow::Borrowed(image),
+        _ if image.color().has_alpha() => Cow::Owned(DynamicImage::ImageRgba8(image.to_rgba8())),
+        _ => Cow::Owned(DynamicImage::ImageRgb8(image.to_rgb8())),
+    }
+}
+
+/// WebP is always written losslessly: lossy VP8 encoding needs libwebp, which neither the zkVM
+/// nor the WASM build can link.
+fn encode_webp(image: &DynamicImage) -> EditorResult<Vec<u8>> {
+    let image = match image {
+        DynamicImage::ImageLuma8(_) | DynamicImage::ImageLumaA8(_) => Cow::Borrowed(image),
+        _ => to_rgb8_layout(image),
+    };
+    let mut bytes = Vec::new();
+    WebPEncoder::new_lossless(&mut bytes)
+        .write_image(image.as_bytes(), image.width(), image.height(), image.color())
+        .map_err(encode_error)?;
+    Ok(bytes)
+}
+
+/// Encodes `image` as `format` using the matching settings from `options`.
+fn encode_pixels(image: &DynamicImage, format: ImageFormat, options: &EncodeOptions) -> EditorResult<Vec<u8>> {
+    match format {
+        ImageFormat::Jpeg => encode_jpeg(image, &options.jpeg),
+        ImageFormat::Png => encode_png(image, &options.png),
+        ImageFormat::WebP => encode_webp(image),
+        ImageFormat::Gif | ImageFormat::Bmp | ImageFormat::Qoi => encode(&to_rgb8_layout(image), format),
+        format => encode(image, format),
+    }
+}
+
+/// Encodes `image` as `format` with `options`, adding the metadata and color profile that
+/// `options` keeps from `source`, the encoded image the output was made from.
+pub(crate) fn encode_with_metadata(image: &DynamicImage, format: ImageFormat, options: &EncodeOptions, source: &[u8]) -> EditorResult<Vec<u8>> {
+    check_encodable(format)?;
+    let image = apply_alpha(image, &options.alpha)?;
+    let icc = output_profile(options.color_profile, source, format);
+    let converted = icc.as_deref().and_then(|icc| to_output_space(&image, icc));
+    let icc = icc.filter(|_| converted.is_some());
+    let encoded = encode_pixels(converted.as_deref().unwrap_or(&image), format, options)?;
+    embed_metadata(encoded, format, &options.metadata, icc.as_deref(), source)
+}
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use crate::{EditId, LayerId};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EditorError {
+    Decode(String),
+    UnsupportedFormat(String),
+    RegionOutOfBounds {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        image_width: u32,
+        image_height: u32,
+    },
+    InvalidColor(String),
+    Font(String),
+    Encode(String),
+    LayerNotFound(LayerId),
+    LayerLocked(LayerId),
+    EditNotFound(EditId),
+    InvalidTransformation(String),
+    InvalidArgument(String),
+    InvalidProject(String),
+    /// Hex SHA-256 hashes of the image a proof committed and of the image rendered on the host.
+    ImageHashMismatch { expected: String, actual: String },
+}
+
+pub type EditorResult<T> = Result<T, EditorError>;
+
+impl EditorError {
+    /// Stable, machine readable identifier. These strings are part of the public API
+    /// (HTTP responses, JS exceptions) and must not change once published.
+    pub fn code(&self) -> &'static str {
+        match self {
+            EditorError::Decode(_) => "DECODE_FAILED",
+            EditorError::UnsupportedFormat(_) => "UNSUPPORTED_FORMAT",
+            EditorError::RegionOutOfBounds { .. } => "REGION_OUT_OF_BOUNDS",
+            EditorError::InvalidColor(_) => "INVALID_COLOR",
+            EditorError::Font(_) => "FONT_FAILED",
+            EditorError::Encode(_) => "ENCODE_FAILED",
+            EditorError::LayerNotFound(_) => "LAYER_NOT_FOUND",
+            EditorError::LayerLocked(_) => "LAYER_LOCKED",
+            EditorError::EditNotFound(_) => "EDIT_NOT_FOUND",
+            EditorError::InvalidTransformation(_) => "INVALID_TRANSFORMATION",
+            EditorError::InvalidArgument(_) => "INVALID_ARGUMENT",
+            EditorError::InvalidProject(_) => "INVALID_PROJECT",
+            EditorError::ImageHashMismatch { .. } => "IMAGE_HASH_MISMATCH",
+        }
+    }
+
+    pub(crate) fn check_region(x: u32, y: u32, width: u32, height: u32, image_width: u32, image_height: u32) -> EditorResult<()> {
+        let fits_x = x.checked_add(width).map_or(false, |right| right <= image_width);
+        let fits_y = y.checked_add(height).map_or(false, |bottom| bottom <= image_height);
+        if width == 0 || height == 0 || !fits_x || !fits_y {
+            return Err(EditorError::RegionOutOfBounds { x, y, width, height, image_width, image_height });
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for EditorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditorError::Decode(e) => write!(f, "Failed to load image: {}", e),
+            EditorError::UnsupportedFormat(format) => write!(f, "Unsupported image format: {}", format),
+            EditorError::RegionOutOfBounds { x, y, width, height, image_width, image_height } => write!(
+                f,
+                "Region {}x{} at ({}, {}) is outside the {}x{} image",
+                width, height, x, y, image_width, image_height
+            ),
+            EditorError::InvalidColor(color) => write!(f, "Invalid hex color: {}", color),
+            EditorError::Font(e) => write!(f, "Failed to load font: {}", e),
+            EditorError::Encode(e) => write!(f, "Failed to encode image: {}", e),
+            EditorError::LayerNotFound(id) => write!(f, "Layer {} not found", id),
+            EditorError::LayerLocked(id) => write!(f, "Layer {} is locked", id),
+            EditorError::EditNotFound(id) => write!(f, "Edit {} not found", id),
+            EditorError::InvalidTransformation(e) => write!(f, "Invalid transformation: {}", e),
+            EditorError::InvalidArgument(e) => write!(f, "Invalid argument: {}", e),
+            EditorError::InvalidProject(e) => write!(f, "Invalid project file: {}", e),
+            EditorError::ImageHashMismatch { expected, actual } => write!(
+                f,
+                "Rendered image hash 0x{} does not match the proven hash 0x{}",
+                actual, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EditorError {}
+
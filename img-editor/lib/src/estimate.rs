@@ -0,0 +1,257 @@
+//! What proving an input costs. `estimate_proof` is a cheap model driven by image sizes and
+//! transformation kinds alone, so UIs can show an estimate before anything runs. The API's
+//! `/estimate` endpoint and the CLI's `--estimate` run the program in SP1's executor instead,
+//! which is exact but takes as long as the program does, and report it with `ProofEstimate::new`.
+use image::ImageFormat;
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use crate::metadata::orientation;
+use crate::{sniff_format, Animation, EditorError, EditorResult, ImageInput, OutputFormat, Region, TextOverlayParameters, Transformation};
+
+/// Cycles one stage of the program takes, named as `track` names it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StageEstimate {
+    pub stage: String,
+    /// Kind of the transformation a `transform_{index}` stage applies.
+    pub transformation: Option<String>,
+    pub cycles: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProofEstimate {
+    pub total_cycles: u64,
+    /// Cycles outside every stage: reading the input, loading fonts, committing the public values.
+    pub untracked_cycles: u64,
+    /// Stages in the order the program runs them.
+    pub stages: Vec<StageEstimate>,
+    pub proving_seconds: f64,
+    pub cost: f64,
+}
+
+/// How fast proofs are generated and what they cost. The defaults are rough figures for Groth16
+/// proofs on the prover network; set them from current pricing and observed throughput.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ProvingRates {
+    pub cycles_per_second: f64,
+    pub cost_per_million_cycles: f64,
+}
+
+impl Default for ProvingRates {
+    fn default() -> Self {
+        Self { cycles_per_second: 1_000_000.0, cost_per_million_cycles: 0.01 }
+    }
+}
+
+impl ProvingRates {
+    /// Reads the rates from `PROVING_CYCLES_PER_SECOND` and `PROVING_COST_PER_MILLION_CYCLES`,
+    /// keeping the defaults for unset variables.
+    pub fn from_env() -> EditorResult<Self> {
+        let rate = |name: &str, default: f64| match std::env::var(name) {
+            Ok(value) => value.trim().parse()
+                .map_err(|_| EditorError::InvalidArgument(format!("{name} must be a number, got {value:?}"))),
+            Err(_) => Ok(default),
+        };
+        let defaults = Self::default();
+        Ok(Self {
+            cycles_per_second: rate("PROVING_CYCLES_PER_SECOND", defaults.cycles_per_second)?,
+            cost_per_million_cycles: rate("PROVING_COST_PER_MILLION_CYCLES", defaults.cost_per_million_cycles)?,
+        })
+    }
+}
+
+/// Orders stages as the program runs them, transformations by index.
+fn stage_order(stage: &str) -> (usize, usize) {
+    const ORDER: [&str; 11] = [
+        "hash_input", "decode", "transform_", "frame_edit_", "layer_", "composite", "encode", "hash_output", "metadata",
+        "frames_hash", "signature",
+    ];
+    let rank = ORDER.iter().position(|prefix| stage.starts_with(prefix)).unwrap_or(ORDER.len());
+    let index = stage.rsplit('_').next().and_then(|index| index.parse().ok()).unwrap_or(0);
+    (rank, index)
+}
+
+impl ProofEstimate {
+    /// Builds an estimate of proving `input` from the cycles of each stage and of the whole run.
+    pub fn new(input: &ImageInput, stages: impl IntoIterator<Item = (String, u64)>, total_cycles: u64, rates: &ProvingRates) -> Self {
+        let mut stages: Vec<StageEstimate> = stages.into_iter()
+            .map(|(stage, cycles)| StageEstimate {
+                transformation: stage.strip_prefix("transform_")
+                    .and_then(|index| input.transformations.get(index.parse::<usize>().ok()?))
+                    .map(|transformation| transformation.name().to_string()),
+                stage,
+                cycles,
+            })
+            .collect();
+        stages.sort_by_key(|stage| stage_order(&stage.stage));
+        let tracked: u64 = stages.iter().map(|stage| stage.cycles).sum();
+        let total_cycles = total_cycles.max(tracked);
+        ProofEstimate {
+            total_cycles,
+            untracked_cycles: total_cycles - tracked,
+            stages,
+            proving_seconds: total_cycles as f64 / rates.cycles_per_second,
+            cost: total_cycles as f64 / 1_000_000.0 * rates.cost_per_million_cycles,
+        }
+    }
+}
+
+// Rough costs of the current code in RISC-V cycles, per pixel unless noted. Recalibrate them
+// from the `bench` binary or `/estimate` results when kernels change.
+const BASE: u64 = 2_000_000;
+const READ_PER_BYTE: u64 = 4;
+const SHA256_PER_BYTE: u64 = 2;
+const METADATA: u64 = 100_000;
+const SIGNATURE: u64 = 10_000_000;
+const COMPOSITE: u64 = 40;
+/// Per pixel of the text's box, and per pixel of its box and stroke radius.
+const TEXT: u64 = 400;
+const TEXT_STROKE: u64 = 60;
+const TEXT_ROTATED: u64 = 100;
+
+fn decode_cost(format: Option<ImageFormat>) -> u64 {
+    match format {
+        Some(ImageFormat::Jpeg) => 200,
+        Some(ImageFormat::WebP) => 250,
+        Some(ImageFormat::Gif) => 60,
+        Some(ImageFormat::Bmp) => 20,
+        Some(ImageFormat::Tiff) => 40,
+        Some(ImageFormat::Qoi) => 30,
+        _ => 80,
+    }
+}
+
+/// Cycles per pixel, and encoded bytes per 16 pixels for hashing the output.
+fn encode_cost(format: OutputFormat) -> (u64, u64) {
+    match format {
+        OutputFormat::Png => (120, 32),
+        OutputFormat::Jpeg => (250, 5),
+        OutputFormat::WebP => (300, 24),
+        OutputFormat::Gif => (1_500, 8),
+    }
+}
+
+fn region_pixels(region: &Option<Region>, width: u32, height: u32) -> u64 {
+    match region {
+        Some(region) => region.width.min(width) as u64 * region.height.min(height) as u64,
+        None => width as u64 * height as u64,
+    }
+}
+
+fn text_cost(params: &TextOverlayParameters) -> u64 {
+    let glyphs = params.text.chars().filter(|c| !c.is_whitespace()).count() as u64;
+    let size = params.size as u64;
+    // Glyphs are about 0.6 em wide
+    let area = glyphs * size * size * 3 / 5;
+    let mut cycles = area * TEXT;
+    if let Some(stroke) = &params.stroke {
+        cycles += area * TEXT_STROKE * stroke.width.max(0.0).ceil() as u64;
+    }
+    if let Some(shadow) = &params.shadow {
+        cycles += area * TEXT + area * 3 * shadow.blur as u64;
+    }
+    if params.rotation % 360.0 != 0.0 {
+        cycles += area * TEXT_ROTATED;
+    }
+    cycles
+}
+
+/// Cycles `transformation` takes on a `width` by `height` image, and the size it leaves.
+fn transformation_cost(transformation: &Transformation, width: u32, height: u32) -> (u64, u32, u32) {
+    let pixels = width as u64 * height as u64;
+    match transformation {
+        Transformation::Crop(crop) => {
+            let (width, height) = (crop.width.min(width), crop.height.min(height));
+            (6 * width as u64 * height as u64, width, height)
+        },
+        Transformation::Rotate90 | Transformation::Rotate270 => (30 * pixels, height, width),
+        Transformation::Rotate180 => (8 * pixels, width, height),
+        Transformation::Grayscale { region } => (18 * region_pixels(region, width, height), width, height),
+        Transformation::FlipVertical { region } | Transformation::FlipHorizontal { region } => {
+            (10 * region_pixels(region, width, height), width, height)
+        },
+        Transformation::Brighten { region, .. } => (12 * region_pixels(region, width, height), width, height),
+        Transformation::Contrast { region, .. } => (14 * region_pixels(region, width, height), width, height),
+        // Three box blurs in both directions, whatever the radius
+        Transformation::Blur { region, .. } => (220 * region_pixels(region, width, height), width, height),
+        Transformation::TextOverlay(params) => (text_cost(params), width, height),
+    }
+}
+
+/// Width and height of encoded image data, turned upright, read from its header.
+fn dimensions(image_data: &[u8]) -> EditorResult<(u32, u32)> {
+    let (width, height) = image::io::Reader::new(Cursor::new(image_data))
+        .with_guessed_format()
+        .map_err(|e| EditorError::Decode(e.to_string()))?
+        .into_dimensions()
+        .map_err(|e| EditorError::Decode(e.to_string()))?;
+    Ok(if orientation(image_data) >= 5 { (height, width) } else { (width, height) })
+}
+
+/// Estimates what proving `input` costs without decoding its images, except to count the frames
+/// of animations.
+pub fn estimate_proof(input: &ImageInput, rates: &ProvingRates) -> EditorResult<ProofEstimate> {
+    let mut stages = Vec::new();
+    let mut stage = |name: String, cycles: u64| stages.push((name, cycles));
+
+    let input_bytes = input.image_data.len()
+        + input.fonts.iter().map(|font| font.data.len()).sum::<usize>()
+        + input.layers.iter().map(|layer| layer.image_data.len()).sum::<usize>();
+    stage("hash_input".to_string(), SHA256_PER_BYTE * input_bytes as u64);
+
+    let (frames, (mut width, mut height)) = match &input.animation {
+        Some(_) => {
+            let animation = Animation::new(&input.image_data)?;
+            (animation.frame_count() as u64, animation.size())
+        },
+        None => (1, dimensions(&input.image_data)?),
+    };
+    let source_format = sniff_format(&input.image_data);
+    stage("decode".to_string(), frames * decode_cost(source_format) * width as u64 * height as u64);
+    for (index, transformation) in input.transformations.iter().enumerate() {
+        let (cycles, new_width, new_height) = transformation_cost(transformation, width, height);
+        stage(format!("transform_{index}"), frames * cycles);
+        (width, height) = (new_width, new_height);
+    }
+    if let Some(animation) = &input.animation {
+        for (index, edit) in animation.frame_edits.iter().enumerate() {
+            let edited = edit.frames.as_ref().map_or(frames, |range| range.end.saturating_sub(range.start) as u64);
+            let (cycles, _, _) = transformation_cost(&edit.transformation, width, height);
+            stage(format!("frame_edit_{index}"), edited.min(frames) * cycles);
+        }
+    }
+
+    if let Some(canvas) = input.canvas {
+        (width, height) = (canvas.width, canvas.height);
+    }
+    let pixels = width as u64 * height as u64;
+    for (index, layer) in input.layers.iter().enumerate() {
+        let (mut layer_width, mut layer_height) = dimensions(&layer.image_data)?;
+        let mut cycles = decode_cost(sniff_format(&layer.image_data)) * layer_width as u64 * layer_height as u64;
+        for transformation in &layer.transformations {
+            let (transformation_cycles, new_width, new_height) = transformation_cost(transformation, layer_width, layer_height);
+            cycles += transformation_cycles;
+            (layer_width, layer_height) = (new_width, new_height);
+        }
+        stage(format!("layer_{index}"), cycles);
+    }
+    if !input.layers.is_empty() || input.canvas.is_some() {
+        stage("composite".to_string(), COMPOSITE * pixels * (input.layers.len() as u64 + 1));
+    }
+
+    let (encode, bytes_per_16_pixels) = encode_cost(input.output_format);
+    let output_bytes = frames * pixels * bytes_per_16_pixels / 16;
+    stage("encode".to_string(), frames * encode * pixels);
+    stage("hash_output".to_string(), SHA256_PER_BYTE * output_bytes);
+    stage("metadata".to_string(), METADATA);
+    if input.animation.is_some() {
+        let decode = decode_cost(Some(input.output_format.into()));
+        stage("frames_hash".to_string(), frames * (decode + 4 * SHA256_PER_BYTE) * pixels);
+    }
+    if input.signature_data.is_some() {
+        stage("signature".to_string(), SIGNATURE);
+    }
+
+    let tracked: u64 = stages.iter().map(|(_, cycles)| cycles).sum();
+    let total_cycles = tracked + BASE + READ_PER_BYTE * input_bytes as u64;
+    Ok(ProofEstimate::new(input, stages, total_cycles, rates))
+}
@@ -0,0 +1,136 @@
+//! Integer helpers for the fixed-point kernels. Pixels that end up in a proof are computed
+//! without floating point math, so the host, the WASM preview and the zkVM produce the same
+//! bytes. Float parameters are only ever scaled by a power of two and rounded, which is exact.
+
+/// `value * 2^bits`, rounded to the nearest integer. NaN becomes zero and out of range values
+/// saturate.
+pub(crate) fn quantize(value: f32, bits: u32) -> i64 {
+    (value as f64 * (1u64 << bits) as f64).round() as i64
+}
+
+/// `numerator / denominator` rounded to the nearest integer, halves away from zero. The
+/// denominator must be positive.
+pub(crate) fn div_round(numerator: i64, denominator: i64) -> i64 {
+    let half = denominator / 2;
+    if numerator >= 0 {
+        (numerator + half) / denominator
+    } else {
+        (numerator - half) / denominator
+    }
+}
+
+/// Largest integer whose square is at most `value`.
+pub(crate) fn isqrt(value: u64) -> u64 {
+    if value < 2 {
+        return value;
+    }
+    // Newton's method from an overestimate converges from above
+    let mut x = 1u64 << ((64 - value.leading_zeros()).div_ceil(2));
+    loop {
+        let next = (x + value / x) / 2;
+        if next >= x {
+            return x;
+        }
+        x = next;
+    }
+}
+
+/// Fraction bits of the values `sin_cos` returns.
+pub(crate) const TRIG_BITS: u32 = 30;
+
+/// π in Q61, accurate to the last bit.
+const PI_Q61: i128 = 7244019458077122842;
+
+/// Sine and cosine of an angle in 1/256 degree steps, in Q30.
+pub(crate) fn sin_cos(angle: i64) -> (i64, i64) {
+    const TURN: i64 = 360 * 256;
+    const EIGHTH: i64 = TURN / 8;
+    let angle = angle.rem_euclid(TURN);
+    // Fold into the first octant, then undo the symmetry on the result
+    let octant = angle / EIGHTH;
+    let offset = angle % EIGHTH;
+    let reduced = if octant % 2 == 0 { offset } else { EIGHTH - offset };
+    let (sin, cos) = first_octant(reduced);
+    let (sin, cos) = if octant % 4 == 1 || octant % 4 == 2 { (cos, sin) } else { (sin, cos) };
+    match octant {
+        0 | 1 => (sin, cos),
+        2 | 3 => (sin, -cos),
+        4 | 5 => (-sin, -cos),
+        _ => (-sin, cos),
+    }
+}
+
+/// Taylor series for angles up to 45 degrees, where the terms left out stay below 2^-34.
+fn first_octant(angle: i64) -> (i64, i64) {
+    const BITS: u32 = 61;
+    let x = (angle as i128 * PI_Q61) / (180 * 256);
+    let x2 = (x * x) >> BITS;
+    let mut sin = 0i128;
+    let mut cos = 0i128;
+    let mut sin_term = x;
+    let mut cos_term = 1i128 << BITS;
+    for n in 1..=8i128 {
+        sin += sin_term;
+        cos += cos_term;
+        sin_term = -((sin_term * x2) >> BITS) / ((2 * n) * (2 * n + 1));
+        cos_term = -((cos_term * x2) >> BITS) / ((2 * n - 1) * (2 * n));
+    }
+    let shift = BITS - TRIG_BITS;
+    let round = 1i128 << (shift - 1);
+    (((sin + round) >> shift) as i64, ((cos + round) >> shift) as i64)
+}
+
+/// Fraction bits of the logarithms and exponents `log2`, `exp2` and `pow` work with.
+pub(crate) const LOG_BITS: u32 = 30;
+
+/// ln 2 in Q30.
+const LN2_Q30: i64 = 744261118;
+
+/// Base 2 logarithm of the positive value `value * 2^-bits`, in Q30.
+pub(crate) fn log2(value: u64, bits: u32) -> i64 {
+    let msb = 63 - value.leading_zeros() as i64;
+    let mut mantissa = if msb >= 30 { value >> (msb - 30) } else { value << (30 - msb) };
+    // Squaring the mantissa in [1, 2) doubles its logarithm, so every step yields one bit
+    let mut fraction = 0;
+    for bit in (0..LOG_BITS).rev() {
+        mantissa = (mantissa * mantissa) >> 30;
+        if mantissa >= 2 << 30 {
+            mantissa >>= 1;
+            fraction |= 1 << bit;
+        }
+    }
+    ((msb - bits as i64) << LOG_BITS) + fraction
+}
+
+/// `2^exponent` for an exponent in Q30, in Q`bits`. Results too large for 62 bits saturate.
+pub(crate) fn exp2(exponent: i64, bits: u32) -> i64 {
+    let whole = exponent >> LOG_BITS;
+    let x = ((exponent & ((1 << LOG_BITS) - 1)) * LN2_Q30) >> LOG_BITS;
+    // e^x for x below ln 2, where the terms left out stay below 2^-32
+    let mut power = 0;
+    let mut term = 1 << LOG_BITS;
+    for n in 1..=13 {
+        power += term;
+        term = ((term * x) >> LOG_BITS) / n;
+    }
+    let shift = whole + bits as i64 - LOG_BITS as i64;
+    match shift {
+        31.. => i64::MAX >> 1,
+        0..=30 => power << shift,
+        -62..=-1 => (power + (1 << (-shift - 1))) >> -shift,
+        _ => 0,
+    }
+}
+
+/// `base^exponent` for a base in Q`bits` and an exponent in Q30, in Q`bits`. Bases below zero
+/// count as zero.
+pub(crate) fn pow(base: i64, exponent: i64, bits: u32) -> i64 {
+    if exponent == 0 {
+        return 1 << bits;
+    }
+    if base <= 0 {
+        return 0;
+    }
+    let log = (log2(base as u64, bits) as i128 * exponent as i128) >> LOG_BITS;
+    exp2(log.clamp(i64::MIN as i128 >> 2, i64::MAX as i128 >> 2) as i64, bits)
+}
@@ -0,0 +1,203 @@
+use rusttype::{Font, GlyphId, OutlineBuilder, Scale};
+use crate::fixed::div_round;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+use crate::{EditorError, EditorResult, FontData};
+
+/// Fonts compiled into the library. They are part of the program binary, so proofs do not need
+/// to commit to them.
+pub const BUNDLED_FONTS: &[&str] = &["impact"];
+
+pub const DEFAULT_FONT: &str = "impact";
+
+const IMPACT_DATA: &[u8] = include_bytes!("../assets/impact.ttf");
+
+fn bundled_face(name: &str) -> Option<FontFace<'static>> {
+    static IMPACT: OnceLock<Option<ParsedFont>> = OnceLock::new();
+    match name {
+        "impact" => IMPACT
+            .get_or_init(|| ParsedFont::parse(IMPACT_DATA))
+            .as_ref()
+            .map(ParsedFont::face),
+        _ => None,
+    }
+}
+
+/// A font parsed once for drawing and, with `shaping`, for the shaper.
+struct ParsedFont {
+    font: Font<'static>,
+    /// `None` when rustybuzz rejects a font that rusttype accepted; its text is then left out.
+    #[cfg(feature = "shaping")]
+    shaper: Option<rustybuzz::Face<'static>>,
+}
+
+impl ParsedFont {
+    fn parse(data: &'static [u8]) -> Option<Self> {
+        Some(Self {
+            font: Font::try_from_bytes(data)?,
+            #[cfg(feature = "shaping")]
+            shaper: rustybuzz::Face::from_slice(data, 0),
+        })
+    }
+
+    /// The faces, with their lifetime shortened to the borrow so they cannot outlive the font.
+    fn face(&self) -> FontFace<'_> {
+        FontFace {
+            font: &self.font,
+            #[cfg(feature = "shaping")]
+            shaper: self.shaper.as_ref(),
+        }
+    }
+}
+
+/// A parsed font together with the shaper's parse of the same bytes.
+#[derive(Clone, Copy)]
+pub(crate) struct FontFace<'a> {
+    pub(crate) font: &'a Font<'a>,
+    #[cfg(feature = "shaping")]
+    pub(crate) shaper: Option<&'a rustybuzz::Face<'a>>,
+}
+
+/// Positions are laid out in 1/256 pixel steps.
+pub(crate) const SUBPIXELS: i64 = 256;
+
+/// Metrics of a face in font units.
+pub(crate) struct UnitMetrics {
+    pub(crate) ascent: i64,
+    pub(crate) descent: i64,
+    pub(crate) line_gap: i64,
+}
+
+impl FontFace<'_> {
+    pub(crate) fn has_glyph(&self, c: char) -> bool {
+        self.font.glyph(c).id().0 != 0
+    }
+
+    pub(crate) fn metrics(&self) -> UnitMetrics {
+        let metrics = self.font.v_metrics_unscaled();
+        UnitMetrics { ascent: metrics.ascent as i64, descent: metrics.descent as i64, line_gap: metrics.line_gap as i64 }
+    }
+
+    /// The scale at which rusttype reports font units unchanged: it scales text so that ascent
+    /// minus descent spans the pixel size.
+    fn unit_scale(&self) -> Scale {
+        let metrics = self.font.v_metrics_unscaled();
+        Scale::uniform(metrics.ascent - metrics.descent)
+    }
+
+    /// Converts font units to 1/256 pixels for text of `size` pixels.
+    pub(crate) fn subpixels(&self, units: i64, size: u32) -> i64 {
+        let metrics = self.metrics();
+        let height = (metrics.ascent - metrics.descent).max(1);
+        div_round(units * size as i64 * SUBPIXELS, height)
+    }
+
+    #[cfg_attr(feature = "shaping", allow(dead_code))]
+    pub(crate) fn advance(&self, id: GlyphId) -> i64 {
+        self.font.glyph(id).scaled(self.unit_scale()).h_metrics().advance_width as i64
+    }
+
+    #[cfg_attr(feature = "shaping", allow(dead_code))]
+    pub(crate) fn kerning(&self, first: GlyphId, second: GlyphId) -> i64 {
+        self.font.pair_kerning(self.unit_scale(), first, second) as i64
+    }
+
+    /// Walks the outline of a glyph in font units, with y pointing down. Returns false for
+    /// glyphs without an outline.
+    pub(crate) fn outline(&self, id: GlyphId, builder: &mut impl OutlineBuilder) -> bool {
+        self.font.glyph(id).scaled(self.unit_scale()).build_outline(builder)
+    }
+}
+
+/// Index of the first face in `chain` that can render `c`.
+pub(crate) fn face_for_char(chain: &[FontFace], c: char) -> Option<usize> {
+    chain.iter().position(|face| face.has_glyph(c))
+}
+
+/// A custom font's bytes and the faces parsed from them. The faces borrow the heap buffer of
+/// `data`, which is never changed or handed out mutably, so they stay valid for as long as the
+/// font is registered. Fields drop in order, the faces before the bytes they borrow.
+struct RegisteredFont {
+    parsed: ParsedFont,
+    data: FontData,
+}
+
+impl RegisteredFont {
+    fn new(name: &str, data: Vec<u8>) -> Option<Self> {
+        // SAFETY: the bytes live on the heap until `data` is dropped, after `parsed`, and moving
+        // the `Vec` does not move them
+        let bytes: &'static [u8] = unsafe { &*(data.as_slice() as *const [u8]) };
+        let parsed = ParsedFont::parse(bytes)?;
+        Some(Self { parsed, data: FontData { name: name.to_string(), data } })
+    }
+}
+
+/// User-supplied TTF/OTF fonts, parsed once on registration, with the bundled fonts as fallback.
+#[derive(Default)]
+pub struct FontRegistry {
+    fonts: BTreeMap<String, RegisteredFont>,
+}
+
+impl FontRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a font under `name`, replacing any previous font with that name. Custom fonts
+    /// shadow bundled fonts of the same name.
+    pub fn register(&mut self, name: &str, data: Vec<u8>) -> EditorResult<()> {
+        let font = RegisteredFont::new(name, data)
+            .ok_or_else(|| EditorError::Font(format!("'{}' is not a valid TrueType/OpenType font", name)))?;
+        self.fonts.insert(name.to_string(), font);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.fonts.remove(name).is_some()
+    }
+
+    /// Resolves a font by name, falling back to the default bundled font when no name is given.
+    pub fn get(&self, name: Option<&str>) -> EditorResult<&Font<'_>> {
+        self.face(name).map(|face| face.font)
+    }
+
+    pub(crate) fn face(&self, name: Option<&str>) -> EditorResult<FontFace<'_>> {
+        let name = name.unwrap_or(DEFAULT_FONT);
+        if let Some(font) = self.fonts.get(name) {
+            return Ok(font.parsed.face());
+        }
+        if BUNDLED_FONTS.contains(&name) {
+            return bundled_face(name)
+                .ok_or_else(|| EditorError::Font(format!("bundled font '{}' is not a valid TrueType file", name)));
+        }
+        Err(EditorError::Font(format!("unknown font '{}'", name)))
+    }
+
+    /// The selected font followed by every other available font, used for glyph fallback.
+    pub(crate) fn fallback_chain(&self, name: Option<&str>) -> EditorResult<Vec<FontFace<'_>>> {
+        let primary = self.face(name)?;
+        let mut chain = vec![primary];
+        for other in self.names() {
+            if let Ok(face) = self.face(Some(&other)) {
+                if !std::ptr::eq(face.font, primary.font) {
+                    chain.push(face);
+                }
+            }
+        }
+        Ok(chain)
+    }
+
+    /// Names of all fonts that can be selected, custom fonts first.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.fonts.keys().cloned().collect();
+        names.extend(BUNDLED_FONTS.iter()
+            .filter(|name| !self.fonts.contains_key(**name))
+            .map(|name| name.to_string()));
+        names
+    }
+
+    /// The raw bytes of every custom font, in name order.
+    pub fn custom_fonts(&self) -> impl Iterator<Item = &FontData> {
+        self.fonts.values().map(|font| &font.data)
+    }
+}
@@ -0,0 +1,95 @@
+use image::ImageFormat;
+use crate::{EditorError, EditorResult};
+
+/// Formats `Layer::new` may accept, in the order they are reported.
+const KNOWN_FORMATS: [ImageFormat; 8] = [
+    ImageFormat::Png,
+    ImageFormat::Jpeg,
+    ImageFormat::WebP,
+    ImageFormat::Gif,
+    ImageFormat::Bmp,
+    ImageFormat::Tiff,
+    ImageFormat::Qoi,
+    ImageFormat::Avif,
+];
+
+/// Whether this build decodes `format`. Only this crate's features count, not codecs that other
+/// crates enable on `image`, so a host and the zkVM program built with the same features accept
+/// exactly the same files.
+pub fn can_decode(format: ImageFormat) -> bool {
+    match format {
+        ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP => true,
+        ImageFormat::Gif => cfg!(feature = "gif"),
+        ImageFormat::Bmp => cfg!(feature = "bmp"),
+        ImageFormat::Tiff => cfg!(feature = "tiff"),
+        ImageFormat::Qoi => cfg!(feature = "qoi"),
+        ImageFormat::Avif => cfg!(feature = "avif"),
+        _ => false,
+    }
+}
+
+/// Whether this build encodes `format`. AVIF is decode only.
+pub fn can_encode(format: ImageFormat) -> bool {
+    format != ImageFormat::Avif && can_decode(format)
+}
+
+/// Short lowercase name used in format strings and error messages.
+pub fn format_name(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Png => "png",
+        ImageFormat::Jpeg => "jpeg",
+        ImageFormat::WebP => "webp",
+        ImageFormat::Gif => "gif",
+        ImageFormat::Bmp => "bmp",
+        ImageFormat::Tiff => "tiff",
+        ImageFormat::Qoi => "qoi",
+        ImageFormat::Avif => "avif",
+        format => format.extensions_str().first().copied().unwrap_or("unknown"),
+    }
+}
+
+/// Names of the formats this build decodes.
+pub fn input_formats() -> Vec<&'static str> {
+    KNOWN_FORMATS.into_iter().filter(|format| can_decode(*format)).map(format_name).collect()
+}
+
+/// Names of the formats this build encodes.
+pub fn output_formats() -> Vec<&'static str> {
+    KNOWN_FORMATS.into_iter().filter(|format| can_encode(*format)).map(format_name).collect()
+}
+
+/// Identifies encoded image data from its magic bytes.
+pub fn sniff_format(image_data: &[u8]) -> Option<ImageFormat> {
+    image::guess_format(image_data).ok()
+}
+
+/// Resolves a format name or file extension such as `jpg` or `tif`. Formats this build cannot
+/// encode are rejected rather than replaced with PNG.
+pub fn parse_format(name: &str) -> EditorResult<ImageFormat> {
+    let format = ImageFormat::from_extension(name.to_ascii_lowercase())
+        .ok_or_else(|| EditorError::UnsupportedFormat(name.to_string()))?;
+    check_encodable(format)?;
+    Ok(format)
+}
+
+/// Fails unless `format` can be decoded by this build.
+pub(crate) fn check_decodable(format: ImageFormat) -> EditorResult<()> {
+    if !can_decode(format) {
+        return Err(EditorError::UnsupportedFormat(format!(
+            "{} decoding is not enabled in this build",
+            format_name(format)
+        )));
+    }
+    Ok(())
+}
+
+/// Fails unless `format` can be encoded by this build.
+pub(crate) fn check_encodable(format: ImageFormat) -> EditorResult<()> {
+    if !can_encode(format) {
+        return Err(EditorError::UnsupportedFormat(format!(
+            "{} encoding is not enabled in this build",
+            format_name(format)
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,88 @@
+//! The hashes a proof commits, computed by the same code in the program, on the host and in the
+//! WASM preview, so hashes taken by clients always agree with the proof. The workspace patches
+//! `sha2` and `sha3` with SP1's forks, which run on its precompiles in the zkVM.
+use sha3::{Digest, Keccak256};
+
+/// SHA-256 over data fed in pieces. Writing to it hashes the bytes written, so encoders can
+/// stream their output straight into it.
+pub struct Sha256 {
+    inner: sha2::Sha256,
+}
+
+impl Sha256 {
+    pub fn new() -> Self {
+        Self { inner: Default::default() }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    pub fn finalize(self) -> [u8; 32] {
+        self.inner.finalize().into()
+    }
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::io::Write for Sha256 {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.update(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// SHA-256 of `data`, as committed for images, fonts, layers and color profiles.
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+/// The hash an Ethereum wallet signs for `personal_sign` of `message` written as lowercase hex,
+/// which is how owners sign the hash of their original image.
+pub fn ethereum_message_hash(message: &[u8]) -> [u8; 32] {
+    let hex = to_hex(message);
+    let mut hasher = Keccak256::new();
+    hasher.update(format!("\x19Ethereum Signed Message:\n{}", hex.len()));
+    hasher.update(hex.as_bytes());
+    hasher.finalize().into()
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+    bytes.iter().fold(String::with_capacity(2 * bytes.len()), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+#[cfg(feature = "conformance")]
+pub(crate) type HashFn = fn(&[u8]) -> [u8; 32];
+
+/// Published test vectors for every hash above, as `(name, hash, input, expected hex)`.
+#[cfg(feature = "conformance")]
+pub(crate) const KNOWN_ANSWERS: &[(&str, HashFn, &[u8], &str)] = &[
+    ("sha256 empty", sha256, b"", "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+    ("sha256 abc", sha256, b"abc", "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+    (
+        "sha256 two blocks",
+        sha256,
+        b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+    ),
+    ("keccak256 empty", keccak256, b"", "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"),
+    ("keccak256 abc", keccak256, b"abc", "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45"),
+];
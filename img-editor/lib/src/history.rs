@@ -0,0 +1,210 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use crate::{BrushStroke, EditId, HorizontalAlign, Layer, LayerId, LayerMask, Selection, Transformation, VerticalAlign};
+
+/// Default memory budget for the journal and its keyframes.
+pub const DEFAULT_HISTORY_BUDGET: usize = 256 * 1024 * 1024;
+
+const DEFAULT_KEYFRAME_INTERVAL: usize = 10;
+
+/// A single journaled edit to an `ImageProject`. Replaying the same edits from the same state
+/// always produces the same project.
+#[derive(Debug, Clone)]
+pub(crate) enum Operation {
+    /// Keeps the decoded layer, so replaying the edit does not decode the source again.
+    AddLayer { layer: Arc<Layer> },
+    AddEmptyLayer { width: u32, height: u32 },
+    TransformLayer { id: LayerId, transformation: Transformation },
+    SetEditEnabled { id: LayerId, edit: EditId, enabled: bool },
+    MoveEdit { id: LayerId, edit: EditId, position: usize },
+    UpdateEdit { id: LayerId, edit: EditId, transformation: Transformation },
+    RemoveEdit { id: LayerId, edit: EditId },
+    RemoveLayer { id: LayerId },
+    MoveLayer { id: LayerId, position: usize },
+    ReorderLayers { ids: Vec<LayerId> },
+    DuplicateLayer { id: LayerId },
+    RenameLayer { id: LayerId, name: String },
+    SetLayerVisible { id: LayerId, visible: bool },
+    SetLayerLocked { id: LayerId, locked: bool },
+    SetLayerOpacity { id: LayerId, opacity: f32 },
+    SetLayerPosition { id: LayerId, x: i32, y: i32 },
+    TranslateLayer { id: LayerId, dx: i32, dy: i32 },
+    AlignLayer { id: LayerId, horizontal: Option<HorizontalAlign>, vertical: Option<VerticalAlign> },
+    SetLayerMask { id: LayerId, mask: Option<LayerMask> },
+    AddLayerMask { id: LayerId, selection: Option<Selection> },
+    InvertLayerMask { id: LayerId },
+    FillLayerMask { id: LayerId, selection: Selection, value: u8 },
+    PaintLayerMask { id: LayerId, stroke: BrushStroke },
+    SetLayerClipped { id: LayerId, clipped: bool },
+    SetCanvasSize { width: u32, height: u32 },
+}
+
+impl Operation {
+    fn memory(&self) -> usize {
+        let payload = match self {
+            Operation::AddLayer { layer } => layer.memory(),
+            Operation::SetLayerMask { mask: Some(mask), .. } => mask.memory(),
+            Operation::ReorderLayers { ids } => ids.len() * std::mem::size_of::<LayerId>(),
+            Operation::PaintLayerMask { stroke, .. } => stroke.points.len() * std::mem::size_of::<[f32; 2]>(),
+            Operation::FillLayerMask { selection: Selection::Polygon { points }, .. } => {
+                points.len() * std::mem::size_of::<[f32; 2]>()
+            },
+            _ => 0,
+        };
+        std::mem::size_of::<Self>() + payload
+    }
+
+    /// The existing layer whose contents the edit changes. Adding, duplicating and reordering
+    /// layers leave every existing layer as it was.
+    fn changed_layer(&self) -> Option<LayerId> {
+        match self {
+            Operation::AddLayer { .. }
+            | Operation::AddEmptyLayer { .. }
+            | Operation::MoveLayer { .. }
+            | Operation::ReorderLayers { .. }
+            | Operation::DuplicateLayer { .. }
+            | Operation::SetCanvasSize { .. } => None,
+            Operation::TransformLayer { id, .. }
+            | Operation::SetEditEnabled { id, .. }
+            | Operation::MoveEdit { id, .. }
+            | Operation::UpdateEdit { id, .. }
+            | Operation::RemoveEdit { id, .. }
+            | Operation::RemoveLayer { id }
+            | Operation::RenameLayer { id, .. }
+            | Operation::SetLayerVisible { id, .. }
+            | Operation::SetLayerLocked { id, .. }
+            | Operation::SetLayerOpacity { id, .. }
+            | Operation::SetLayerPosition { id, .. }
+            | Operation::TranslateLayer { id, .. }
+            | Operation::AlignLayer { id, .. }
+            | Operation::SetLayerMask { id, .. }
+            | Operation::AddLayerMask { id, .. }
+            | Operation::InvertLayerMask { id }
+            | Operation::FillLayerMask { id, .. }
+            | Operation::PaintLayerMask { id, .. }
+            | Operation::SetLayerClipped { id, .. } => Some(*id),
+        }
+    }
+}
+
+/// The editable state of a project at one point in its history. Layers that did not change
+/// between keyframes are shared rather than copied.
+#[derive(Clone)]
+pub(crate) struct Snapshot {
+    pub(crate) layers: Vec<Arc<Layer>>,
+    pub(crate) next_layer_id: LayerId,
+    pub(crate) canvas_width: u32,
+    pub(crate) canvas_height: u32,
+}
+
+/// Undo journal: every edit in order, plus keyframe snapshots so that reaching any position
+/// replays at most `keyframe_interval` edits. The first keyframe always sits at position 0.
+pub(crate) struct History {
+    pub(crate) entries: Vec<Operation>,
+    /// Number of entries applied to the current state.
+    pub(crate) cursor: usize,
+    keyframes: Vec<(usize, Snapshot)>,
+    pub(crate) checkpoints: BTreeMap<String, usize>,
+    pub(crate) budget: usize,
+    pub(crate) keyframe_interval: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            cursor: 0,
+            keyframes: Vec::new(),
+            checkpoints: BTreeMap::new(),
+            budget: DEFAULT_HISTORY_BUDGET,
+            keyframe_interval: DEFAULT_KEYFRAME_INTERVAL,
+        }
+    }
+}
+
+impl History {
+    /// Drops all entries and starts again from `snapshot`, keeping the settings.
+    pub(crate) fn reset(&mut self, snapshot: Snapshot) {
+        self.entries.clear();
+        self.cursor = 0;
+        self.keyframes = vec![(0, snapshot)];
+        self.checkpoints.clear();
+    }
+
+    /// Appends an applied edit, discarding the redo branch. Returns whether a keyframe of the
+    /// new state should be pushed.
+    pub(crate) fn record(&mut self, operation: Operation) -> bool {
+        let cursor = self.cursor;
+        self.entries.truncate(cursor);
+        self.keyframes.retain(|(position, _)| *position <= cursor);
+        self.checkpoints.retain(|_, position| *position <= cursor);
+
+        self.entries.push(operation);
+        self.cursor += 1;
+        let last_keyframe = self.keyframes.last().map_or(0, |(position, _)| *position);
+        self.cursor - last_keyframe >= self.keyframe_interval
+    }
+
+    /// A snapshot of the state at the cursor. Layers that no edit since the previous keyframe
+    /// changed are shared with that keyframe.
+    pub(crate) fn snapshot(&self, layers: &[Layer], next_layer_id: LayerId, canvas_width: u32, canvas_height: u32) -> Snapshot {
+        let previous = self.keyframes.iter().rev().find(|(position, _)| *position <= self.cursor);
+        let shared = |layer: &Layer| {
+            let (position, snapshot) = previous?;
+            if self.entries[*position..self.cursor].iter().any(|operation| operation.changed_layer() == Some(layer.id)) {
+                return None;
+            }
+            snapshot.layers.iter().find(|kept| kept.id == layer.id).cloned()
+        };
+        Snapshot {
+            layers: layers.iter()
+                .map(|layer| shared(layer).unwrap_or_else(|| Arc::new(layer.clone())))
+                .collect(),
+            next_layer_id,
+            canvas_width,
+            canvas_height,
+        }
+    }
+
+    pub(crate) fn push_keyframe(&mut self, snapshot: Snapshot) {
+        self.keyframes.push((self.cursor, snapshot));
+    }
+
+    /// The latest keyframe at or before `position`.
+    pub(crate) fn keyframe_before(&self, position: usize) -> (usize, &Snapshot) {
+        self.keyframes.iter()
+            .rev()
+            .find(|(keyframe, _)| *keyframe <= position)
+            .map(|(keyframe, snapshot)| (*keyframe, snapshot))
+            .expect("the first keyframe is at position 0")
+    }
+
+    fn memory(&self) -> usize {
+        let entries: usize = self.entries.iter().map(Operation::memory).sum();
+        let mut counted = BTreeSet::new();
+        let keyframes: usize = self.keyframes.iter()
+            .flat_map(|(_, snapshot)| &snapshot.layers)
+            .filter(|layer| counted.insert(Arc::as_ptr(layer)))
+            .map(|layer| layer.memory())
+            .sum();
+        entries + keyframes
+    }
+
+    /// Forgets the oldest history until the journal fits the budget. Only keyframes at or
+    /// before the cursor are dropped, so the current state can always be rebuilt.
+    pub(crate) fn enforce_budget(&mut self) {
+        while self.memory() > self.budget && self.keyframes.len() > 1 && self.keyframes[1].0 <= self.cursor {
+            self.keyframes.remove(0);
+            let base = self.keyframes[0].0;
+            self.entries.drain(..base);
+            self.cursor -= base;
+            for (position, _) in self.keyframes.iter_mut() {
+                *position -= base;
+            }
+            self.checkpoints.retain(|_, position| *position >= base);
+            for position in self.checkpoints.values_mut() {
+                *position -= base;
+            }
+        }
+    }
+}
@@ -0,0 +1,615 @@
+//! ICC profile conversions in fixed point. Profiles are parsed into their tone curves, matrices
+//! and lookup tables and every conversion is evaluated with integer math, as the kernels in
+//! `fixed` are, so a tagged image decodes to the same sRGB pixels on the host, in the WASM
+//! preview and in the zkVM.
+
+use std::array::from_fn;
+use crate::fixed::{div_round, pow, LOG_BITS};
+
+/// Fraction bits of the values flowing through a conversion: 1.0 is `ONE`.
+const BITS: u32 = 24;
+const ONE: i64 = 1 << BITS;
+
+/// Nodes per axis of the grid a LUT-based conversion is sampled on.
+const GRID_NODES: usize = 33;
+
+/// A 3x3 matrix in Q30.
+type Matrix = [[i64; 3]; 3];
+
+/// Colorants of sRGB adapted to the D50 white of the profile connection space, in the
+/// s15Fixed16 form sRGB profiles store them. Columns are red, green and blue.
+const SRGB_COLORANTS: [[i64; 3]; 3] = [
+    [0x6FA2, 0x6299, 0x24A0],
+    [0x38F5, 0xB785, 0x0F84],
+    [0x0390, 0x18DA, 0xB6CF],
+];
+
+/// The sRGB tone curve as parametric curve 3: g, a, b, c and d in s15Fixed16.
+const SRGB_CURVE: [i64; 5] = [0x26666, 0xF2A7, 0x0D59, 0x13D0, 0x0A5B];
+
+/// The D50 white of the profile connection space in s15Fixed16.
+const D50: [i64; 3] = [0xF6D6, 0x10000, 0xD32D];
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(offset..offset.checked_add(2)?)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset.checked_add(4)?)?.try_into().ok()?))
+}
+
+fn read_s15_fixed16(data: &[u8], offset: usize) -> Option<i64> {
+    Some(read_u32(data, offset)? as i32 as i64)
+}
+
+/// `a * b` for two Q24 values.
+fn mul(a: i64, b: i64) -> i64 {
+    ((a as i128 * b as i128 + (1 << (BITS - 1))) >> BITS) as i64
+}
+
+/// `a * ONE / b` for two Q24 values, `b` positive.
+fn div(a: i64, b: i64) -> i64 {
+    div_round(a << BITS, b)
+}
+
+/// `numerator / denominator` rounded to the nearest integer, for any non-zero denominator.
+fn div_round_wide(numerator: i128, denominator: i128) -> i128 {
+    let (numerator, denominator) = if denominator < 0 { (-numerator, -denominator) } else { (numerator, denominator) };
+    let half = denominator / 2;
+    if numerator >= 0 {
+        (numerator + half) / denominator
+    } else {
+        (numerator - half) / denominator
+    }
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    from_fn(|row| from_fn(|column| {
+        let sum: i128 = (0..3).map(|k| a[row][k] as i128 * b[k][column] as i128).sum();
+        ((sum + (1 << 29)) >> 30) as i64
+    }))
+}
+
+/// Inverts a matrix whose entries lie within ±2, so the products below fit in 128 bits.
+fn invert(matrix: &Matrix) -> Option<Matrix> {
+    let m = matrix.map(|row| row.map(i128::from));
+    // Cyclic indices give the cofactors their signs
+    let cofactor = |row: usize, column: usize| {
+        let (r1, r2, c1, c2) = ((row + 1) % 3, (row + 2) % 3, (column + 1) % 3, (column + 2) % 3);
+        m[r1][c1] * m[r2][c2] - m[r1][c2] * m[r2][c1]
+    };
+    let determinant: i128 = (0..3).map(|column| m[0][column] * cofactor(0, column)).sum();
+    if determinant == 0 {
+        return None;
+    }
+    Some(from_fn(|row| from_fn(|column| div_round_wide(cofactor(column, row) << 60, determinant) as i64)))
+}
+
+/// Multiplies Q24 values by a Q30 matrix.
+fn transform(matrix: &Matrix, values: [i64; 3]) -> [i64; 3] {
+    matrix.map(|row| {
+        let sum: i64 = (0..3).map(|k| row[k] * values[k]).sum();
+        (sum + (1 << 29)) >> 30
+    })
+}
+
+/// A tone curve of a `curv` or `para` tag.
+enum Curve {
+    Identity,
+    /// `x^gamma`, the exponent in Q30.
+    Gamma(i64),
+    /// Samples spread evenly over the input range, 65535 being 1.0.
+    Table(Vec<u16>),
+    /// One of the parametric functions 0 to 4, with its parameters g, a, b, c, d, e and f in
+    /// s15Fixed16.
+    Parametric(u16, [i64; 7]),
+}
+
+impl Curve {
+    /// Parses the curve at the start of `data`, returning it with its length in bytes.
+    fn parse(data: &[u8]) -> Option<(Curve, usize)> {
+        match data.get(..4)? {
+            b"curv" => {
+                let count = read_u32(data, 8)? as usize;
+                if count > data.len() / 2 {
+                    return None;
+                }
+                let curve = match count {
+                    0 => Curve::Identity,
+                    // u8Fixed8 exponent
+                    1 => Curve::Gamma((read_u16(data, 12)? as i64) << (LOG_BITS - 8)),
+                    _ => Curve::Table((0..count).map(|index| read_u16(data, 12 + 2 * index)).collect::<Option<_>>()?),
+                };
+                Some((curve, 12 + 2 * count))
+            },
+            b"para" => {
+                let kind = read_u16(data, 8)?;
+                let count = *[1, 3, 4, 5, 7].get(kind as usize)?;
+                let mut parameters = [0; 7];
+                for (index, parameter) in parameters.iter_mut().take(count).enumerate() {
+                    *parameter = read_s15_fixed16(data, 12 + 4 * index)?;
+                }
+                Some((Curve::Parametric(kind, parameters), 12 + 4 * count))
+            },
+            _ => None,
+        }
+    }
+
+    /// Parses three consecutive curves, each starting on a 4-byte boundary.
+    fn parse_three(data: &[u8], offset: usize) -> Option<[Curve; 3]> {
+        let mut offset = offset;
+        let mut curves = Vec::with_capacity(3);
+        for _ in 0..3 {
+            let (curve, length) = Curve::parse(data.get(offset..)?)?;
+            curves.push(curve);
+            offset = offset.checked_add(length.div_ceil(4) * 4)?;
+        }
+        curves.try_into().ok()
+    }
+
+    /// Evaluates the curve at a Q24 input, clipping input and output to [0, 1].
+    fn eval(&self, x: i64) -> i64 {
+        let x = x.clamp(0, ONE);
+        let y = match self {
+            Curve::Identity => x,
+            Curve::Gamma(gamma) => pow(x, *gamma, BITS),
+            Curve::Table(table) => {
+                let position = x * (table.len() as i64 - 1);
+                let index = (position >> BITS) as usize;
+                let fraction = position & (ONE - 1);
+                let low = table[index] as i64;
+                let high = table.get(index + 1).map_or(low, |&high| high as i64);
+                div_round((low << BITS) + (high - low) * fraction, 65535)
+            },
+            Curve::Parametric(kind, parameters) => {
+                let gamma = parameters[0] << (LOG_BITS - 16);
+                let [a, b, c, d, e, f] = from_fn(|index| parameters[index + 1] << (BITS - 16));
+                // A negative base counts as zero, which covers the cut-off of functions 1 and 2
+                let power = |x: i64| pow(mul(a, x) + b, gamma, BITS);
+                match kind {
+                    0 => pow(x, gamma, BITS),
+                    1 => power(x),
+                    2 => power(x) + c,
+                    3 if x >= d => power(x),
+                    3 => mul(c, x),
+                    _ if x >= d => power(x) + e,
+                    _ => mul(c, x) + f,
+                }
+            },
+        };
+        y.clamp(0, ONE)
+    }
+}
+
+/// The color lookup table of a LUT with three inputs and three outputs.
+pub(crate) struct Clut {
+    /// Nodes per input, the first input varying slowest.
+    nodes: [usize; 3],
+    /// Three outputs per node, 65535 being 1.0.
+    values: Vec<u16>,
+}
+
+impl Clut {
+    /// Reads a table of `nodes` with samples of `precision` bytes starting at `offset`.
+    fn parse(data: &[u8], offset: usize, nodes: [usize; 3], precision: usize) -> Option<Clut> {
+        if nodes.contains(&0) || !matches!(precision, 1 | 2) {
+            return None;
+        }
+        let count = nodes.iter().try_fold(3usize, |count, &n| count.checked_mul(n))?;
+        let bytes = data.get(offset..offset.checked_add(count.checked_mul(precision)?)?)?;
+        let values = match precision {
+            1 => bytes.iter().map(|&value| value as u16 * 257).collect(),
+            _ => bytes.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect(),
+        };
+        Some(Clut { nodes, values })
+    }
+
+    /// Trilinear interpolation at Q24 inputs clipped to [0, 1].
+    fn eval(&self, input: [i64; 3]) -> [i64; 3] {
+        let mut base = [0; 3];
+        let mut fraction = [0; 3];
+        for axis in 0..3 {
+            let intervals = self.nodes[axis] as i64 - 1;
+            let position = input[axis].clamp(0, ONE) * intervals;
+            base[axis] = (position >> BITS).min((intervals - 1).max(0)) as usize;
+            fraction[axis] = position - ((base[axis] as i64) << BITS);
+        }
+        let mut sums = [0i64; 3];
+        for corner in 0..8 {
+            let mut weight = ONE;
+            let mut index = 0;
+            for axis in 0..3 {
+                let upper = corner >> (2 - axis) & 1 == 1;
+                weight = (weight * if upper { fraction[axis] } else { ONE - fraction[axis] }) >> BITS;
+                let node = (base[axis] + upper as usize).min(self.nodes[axis] - 1);
+                index = index * self.nodes[axis] + node;
+            }
+            if weight != 0 {
+                for (sum, &value) in sums.iter_mut().zip(&self.values[index * 3..index * 3 + 3]) {
+                    *sum += weight * value as i64;
+                }
+            }
+        }
+        sums.map(|sum| div_round(sum, 65535))
+    }
+}
+
+/// How a LUT encodes the profile connection space on its PCS side.
+#[derive(Clone, Copy)]
+enum Pcs {
+    Xyz,
+    Lab,
+    /// The 16-bit Lab encoding of version 2 `mft2` tables, where 0xFF00 is L* 100.
+    LegacyLab,
+}
+
+/// 6/29 and 4/29 in Q24, the constants of the CIELAB function.
+fn lab_constants() -> (i64, i64) {
+    (div_round(6 * ONE, 29), div_round(4 * ONE, 29))
+}
+
+impl Pcs {
+    /// Decodes normalized PCS values into D50 XYZ, all in Q24.
+    fn decode(self, values: [i64; 3]) -> [i64; 3] {
+        let [v0, v1, v2] = values;
+        let (l, a, b) = match self {
+            // u1Fixed15: 0x8000 is 1.0
+            Pcs::Xyz => return values.map(|value| div_round(value * 65535, 32768)),
+            Pcs::Lab => (v0 * 100, v1 * 255 - 128 * ONE, v2 * 255 - 128 * ONE),
+            Pcs::LegacyLab => (
+                div_round(v0 * 100 * 65535, 65280),
+                div_round(v1 * 65535, 256) - 128 * ONE,
+                div_round(v2 * 65535, 256) - 128 * ONE,
+            ),
+        };
+        let (six_29ths, four_29ths) = lab_constants();
+        let fy = div_round(l + 16 * ONE, 116);
+        let f = [fy + div_round(a, 500), fy, fy - div_round(b, 200)];
+        from_fn(|axis| {
+            let t = f[axis];
+            let linear = if t > six_29ths { mul(mul(t, t), t) } else { div_round((t - four_29ths) * 108, 841) };
+            mul(linear, D50[axis] << (BITS - 16))
+        })
+    }
+
+    /// Encodes D50 XYZ in Q24 as normalized PCS values clipped to [0, 1].
+    fn encode(self, xyz: [i64; 3]) -> [i64; 3] {
+        let values = match self {
+            Pcs::Xyz => xyz.map(|value| div_round(value * 32768, 65535)),
+            Pcs::Lab | Pcs::LegacyLab => {
+                let (six_29ths, four_29ths) = lab_constants();
+                let epsilon = mul(mul(six_29ths, six_29ths), six_29ths);
+                let one_third = div_round(1 << LOG_BITS, 3);
+                let [fx, fy, fz] = from_fn(|axis| {
+                    let t = div(xyz[axis], D50[axis] << (BITS - 16));
+                    if t > epsilon { pow(t, one_third, BITS) } else { div_round(t * 841, 108) + four_29ths }
+                });
+                let (l, a, b) = (116 * fy - 16 * ONE, 500 * (fx - fy), 200 * (fy - fz));
+                match self {
+                    Pcs::Lab => [div_round(l, 100), div_round(a + 128 * ONE, 255), div_round(b + 128 * ONE, 255)],
+                    _ => [
+                        div_round(l * 65280 / 100, 65535),
+                        div_round((a + 128 * ONE) * 256, 65535),
+                        div_round((b + 128 * ONE) * 256, 65535),
+                    ],
+                }
+            },
+        };
+        values.map(|value| value.clamp(0, ONE))
+    }
+}
+
+/// An `A2B0` or `B2A0` table in its `mft1`, `mft2`, `mAB` or `mBA` form. `mft` tables map onto
+/// the same stages: their input tables are the A curves and their output tables the B curves.
+struct Lut {
+    a: Option<[Curve; 3]>,
+    clut: Option<Clut>,
+    m: Option<[Curve; 3]>,
+    /// A Q30 matrix followed by a Q24 offset.
+    matrix: Option<(Matrix, [i64; 3])>,
+    b: Option<[Curve; 3]>,
+    /// PCS to device: the stages run from the B curves to the A curves.
+    reversed: bool,
+    pcs: Pcs,
+}
+
+fn apply_curves(curves: &Option<[Curve; 3]>, values: [i64; 3]) -> [i64; 3] {
+    match curves {
+        Some(curves) => from_fn(|channel| curves[channel].eval(values[channel])),
+        None => values,
+    }
+}
+
+impl Lut {
+    /// Parses a LUT tag with three inputs and three outputs, `reversed` for `B2A0`.
+    fn parse(tag: &[u8], reversed: bool, pcs_is_lab: bool) -> Option<Lut> {
+        if tag.get(8..10)? != [3, 3] {
+            return None;
+        }
+        let signature = tag.get(..4)?;
+        let pcs = match (pcs_is_lab, signature) {
+            (false, _) => Pcs::Xyz,
+            (true, b"mft2") => Pcs::LegacyLab,
+            (true, _) => Pcs::Lab,
+        };
+        match signature {
+            b"mft1" | b"mft2" => {
+                let nodes = *tag.get(10)? as usize;
+                let (precision, input_entries, output_entries, start) = match signature {
+                    b"mft1" => (1, 256, 256, 48),
+                    _ => (2, read_u16(tag, 48)? as usize, read_u16(tag, 50)? as usize, 52),
+                };
+                if input_entries < 2 || output_entries < 2 {
+                    return None;
+                }
+                let table = |offset: usize, entries: usize| -> Option<Curve> {
+                    let bytes = tag.get(offset..offset + entries * precision)?;
+                    Some(Curve::Table(match precision {
+                        1 => bytes.iter().map(|&value| value as u16 * 257).collect(),
+                        _ => bytes.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect(),
+                    }))
+                };
+                let input: [Curve; 3] = (0..3)
+                    .map(|channel| table(start + channel * input_entries * precision, input_entries))
+                    .collect::<Option<Vec<_>>>()?
+                    .try_into()
+                    .ok()?;
+                let clut_offset = start + 3 * input_entries * precision;
+                let clut = Clut::parse(tag, clut_offset, [nodes; 3], precision)?;
+                let output_offset = clut_offset + clut.values.len() * precision;
+                let output: [Curve; 3] = (0..3)
+                    .map(|channel| table(output_offset + channel * output_entries * precision, output_entries))
+                    .collect::<Option<Vec<_>>>()?
+                    .try_into()
+                    .ok()?;
+                // The matrix only applies to XYZ input, so only to the PCS side of a B2A0 table
+                let matrix = match (reversed, pcs) {
+                    (true, Pcs::Xyz) => {
+                        let mut matrix = [[0; 3]; 3];
+                        for (index, entry) in matrix.iter_mut().flatten().enumerate() {
+                            *entry = read_s15_fixed16(tag, 12 + 4 * index)? << 14;
+                        }
+                        Some((matrix, [0; 3]))
+                    },
+                    _ => None,
+                };
+                let (a, b) = if reversed { (output, input) } else { (input, output) };
+                Some(Lut { a: Some(a), clut: Some(clut), m: None, matrix, b: Some(b), reversed, pcs })
+            },
+            b"mAB " | b"mBA " => {
+                if (signature == b"mBA ") != reversed {
+                    return None;
+                }
+                // Offsets of the B curves, matrix, M curves, CLUT and A curves; zero when absent
+                let [b, matrix, m, clut, a] = from_fn(|index| match read_u32(tag, 12 + 4 * index) {
+                    Some(0) | None => None,
+                    Some(offset) => Some(offset as usize),
+                });
+                let curves = |offset: Option<usize>| match offset {
+                    Some(offset) => Curve::parse_three(tag, offset).map(Some),
+                    None => Some(None),
+                };
+                let b = Curve::parse_three(tag, b?)?;
+                let matrix = match matrix {
+                    Some(offset) => {
+                        let mut matrix = [[0; 3]; 3];
+                        for (index, entry) in matrix.iter_mut().flatten().enumerate() {
+                            *entry = read_s15_fixed16(tag, offset + 4 * index)? << 14;
+                        }
+                        let mut shift = [0; 3];
+                        for (index, entry) in shift.iter_mut().enumerate() {
+                            *entry = read_s15_fixed16(tag, offset + 36 + 4 * index)? << (BITS - 16);
+                        }
+                        Some((matrix, shift))
+                    },
+                    None => None,
+                };
+                let m = curves(m)?;
+                let clut = match clut {
+                    Some(offset) => {
+                        let nodes = from_fn(|axis| tag.get(offset + axis).map_or(0, |&nodes| nodes as usize));
+                        Some(Clut::parse(tag, offset + 20, nodes, *tag.get(offset + 16)? as usize)?)
+                    },
+                    None => None,
+                };
+                let a = curves(a)?;
+                Some(Lut { a, clut, m, matrix, b: Some(b), reversed, pcs })
+            },
+            _ => None,
+        }
+    }
+
+    /// Runs normalized Q24 values through the stages.
+    fn eval(&self, values: [i64; 3]) -> [i64; 3] {
+        let clut = |values: [i64; 3]| self.clut.as_ref().map_or(values, |clut| clut.eval(values));
+        let matrix = |values: [i64; 3]| match &self.matrix {
+            Some((matrix, shift)) => {
+                let product = transform(matrix, values);
+                from_fn(|channel| (product[channel] + shift[channel]).clamp(0, ONE))
+            },
+            None => values,
+        };
+        if self.reversed {
+            apply_curves(&self.a, clut(apply_curves(&self.m, matrix(apply_curves(&self.b, values)))))
+        } else {
+            apply_curves(&self.b, matrix(apply_curves(&self.m, clut(apply_curves(&self.a, values)))))
+        }
+    }
+}
+
+/// Colorants and tone curves of a matrix/TRC profile.
+struct MatrixTrc {
+    /// XYZ of the red, green and blue colorants as columns, in Q30.
+    colorants: Matrix,
+    curves: [Curve; 3],
+}
+
+fn srgb() -> MatrixTrc {
+    let [g, a, b, c, d] = SRGB_CURVE;
+    MatrixTrc {
+        colorants: SRGB_COLORANTS.map(|row| row.map(|value| value << 14)),
+        curves: from_fn(|_| Curve::Parametric(3, [g, a, b, c, d, 0, 0])),
+    }
+}
+
+/// Encodes linear sRGB in Q24 with the inverse of the sRGB tone curve, clipping to [0, 1].
+fn srgb_encode(linear: i64) -> i64 {
+    let [g, a, b, c, d] = SRGB_CURVE.map(|value| value << (BITS - 16));
+    let linear = linear.clamp(0, ONE);
+    let encoded = if linear < mul(c, d) {
+        div(linear, c)
+    } else {
+        let gamma = 1i64 << (LOG_BITS + BITS);
+        div(pow(linear, div_round(gamma, g), BITS) - b, a)
+    };
+    encoded.clamp(0, ONE)
+}
+
+/// The parts of an RGB profile a conversion to or from sRGB can use.
+pub(crate) struct Profile {
+    matrix_trc: Option<MatrixTrc>,
+    a2b0: Option<Lut>,
+    b2a0: Option<Lut>,
+}
+
+impl Profile {
+    /// Parses an RGB profile. `None` for other color spaces and for profiles without usable
+    /// matrix/TRC or LUT tags.
+    pub(crate) fn parse(icc: &[u8]) -> Option<Profile> {
+        if icc.get(16..20)? != b"RGB " {
+            return None;
+        }
+        let pcs_is_lab = match icc.get(20..24)? {
+            b"XYZ " => false,
+            b"Lab " => true,
+            _ => return None,
+        };
+        let tag_count = read_u32(icc, 128)? as usize;
+        let tag = |signature: &[u8]| -> Option<&[u8]> {
+            let entry = (0..tag_count.min(256))
+                .map(|index| 132 + index * 12)
+                .find(|&entry| icc.get(entry..entry + 4) == Some(signature))?;
+            let offset = read_u32(icc, entry + 4)? as usize;
+            icc.get(offset..offset.checked_add(read_u32(icc, entry + 8)? as usize)?)
+        };
+
+        let matrix_trc = (|| {
+            if pcs_is_lab {
+                return None;
+            }
+            let mut colorants = [[0; 3]; 3];
+            for (column, signature) in [b"rXYZ", b"gXYZ", b"bXYZ"].into_iter().enumerate() {
+                let xyz = tag(signature)?;
+                if xyz.get(..4)? != b"XYZ " {
+                    return None;
+                }
+                for (row, line) in colorants.iter_mut().enumerate() {
+                    let value = read_s15_fixed16(xyz, 8 + 4 * row)?;
+                    if value.abs() > 2 << 16 {
+                        return None;
+                    }
+                    line[column] = value << 14;
+                }
+            }
+            let curves = [b"rTRC", b"gTRC", b"bTRC"]
+                .into_iter()
+                .map(|signature| Some(Curve::parse(tag(signature)?)?.0))
+                .collect::<Option<Vec<_>>>()?
+                .try_into()
+                .ok()?;
+            Some(MatrixTrc { colorants, curves })
+        })();
+        let a2b0 = tag(b"A2B0").and_then(|data| Lut::parse(data, false, pcs_is_lab));
+        let b2a0 = tag(b"B2A0").and_then(|data| Lut::parse(data, true, pcs_is_lab));
+        if matrix_trc.is_none() && a2b0.is_none() && b2a0.is_none() {
+            return None;
+        }
+        Some(Profile { matrix_trc, a2b0, b2a0 })
+    }
+}
+
+/// A conversion of 8-bit RGB pixels between a profile and sRGB, built once per image.
+pub(crate) enum Transform {
+    /// Matrix/TRC to matrix/TRC: input tone curves tabulated per code, a 3x3 matrix between the
+    /// linear spaces, and for every output code but the last the linear value at which the
+    /// next code becomes the nearer one.
+    Matrix { input: [Vec<i64>; 3], matrix: Matrix, thresholds: [Vec<i64>; 3] },
+    /// Any conversion through a LUT, sampled on a grid and interpolated per pixel.
+    Grid(Clut),
+}
+
+impl Transform {
+    /// Conversion from `profile` to sRGB, through `A2B0` when the profile has one.
+    pub(crate) fn to_srgb(profile: &Profile) -> Option<Transform> {
+        let srgb = srgb();
+        match &profile.a2b0 {
+            Some(lut) => {
+                let to_linear = invert(&srgb.colorants)?;
+                Some(Transform::grid(|device| {
+                    transform(&to_linear, lut.pcs.decode(lut.eval(device))).map(srgb_encode)
+                }))
+            },
+            None => Transform::matrix(profile.matrix_trc.as_ref()?, &srgb),
+        }
+    }
+
+    /// Conversion from sRGB to `profile`, through `B2A0` when the profile has one.
+    pub(crate) fn from_srgb(profile: &Profile) -> Option<Transform> {
+        let srgb = srgb();
+        match &profile.b2a0 {
+            Some(lut) => Some(Transform::grid(|encoded| {
+                let linear = from_fn(|channel| srgb.curves[channel].eval(encoded[channel]));
+                lut.eval(lut.pcs.encode(transform(&srgb.colorants, linear)))
+            })),
+            None => Transform::matrix(&srgb, profile.matrix_trc.as_ref()?),
+        }
+    }
+
+    /// `None` when an output curve is not monotonic, so it has no inverse.
+    fn matrix(from: &MatrixTrc, to: &MatrixTrc) -> Option<Transform> {
+        let matrix = multiply(&invert(&to.colorants)?, &from.colorants);
+        let input = from_fn(|channel| (0..256).map(|code| from.curves[channel].eval(div_round(code * ONE, 255))).collect());
+        let thresholds: [Vec<i64>; 3] = from_fn(|channel| {
+            (0..255).map(|code| to.curves[channel].eval(div_round((2 * code + 1) * ONE, 510))).collect()
+        });
+        if thresholds.iter().any(|thresholds| thresholds.windows(2).any(|pair| pair[0] > pair[1])) {
+            return None;
+        }
+        Some(Transform::Matrix { input, matrix, thresholds })
+    }
+
+    fn grid(convert: impl Fn([i64; 3]) -> [i64; 3]) -> Transform {
+        let node = |index: usize| div_round(index as i64 * ONE, GRID_NODES as i64 - 1);
+        let mut values = Vec::with_capacity(GRID_NODES * GRID_NODES * GRID_NODES * 3);
+        for red in 0..GRID_NODES {
+            for green in 0..GRID_NODES {
+                for blue in 0..GRID_NODES {
+                    let output = convert([node(red), node(green), node(blue)]);
+                    values.extend(output.map(|value| div_round(value.clamp(0, ONE) * 65535, ONE) as u16));
+                }
+            }
+        }
+        Transform::Grid(Clut { nodes: [GRID_NODES; 3], values })
+    }
+
+    fn convert_pixel(&self, rgb: [u8; 3]) -> [u8; 3] {
+        match self {
+            Transform::Matrix { input, matrix, thresholds } => {
+                let linear = transform(matrix, from_fn(|channel| input[channel][rgb[channel] as usize]));
+                from_fn(|channel| thresholds[channel].partition_point(|&threshold| threshold < linear[channel]) as u8)
+            },
+            Transform::Grid(clut) => {
+                let output = clut.eval(rgb.map(|code| div_round(code as i64 * ONE, 255)));
+                output.map(|value| div_round(value * 255, ONE).clamp(0, 255) as u8)
+            },
+        }
+    }
+
+    /// Converts packed 8-bit pixels of `channels` channels in place, the first three being RGB.
+    pub(crate) fn apply(&self, pixels: &mut [u8], channels: usize) {
+        for pixel in pixels.chunks_exact_mut(channels) {
+            let rgb = self.convert_pixel([pixel[0], pixel[1], pixel[2]]);
+            pixel[..3].copy_from_slice(&rgb);
+        }
+    }
+}
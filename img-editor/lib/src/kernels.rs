@@ -0,0 +1,425 @@
+use crate::fixed::{div_round, isqrt, quantize};
+
+/// A rectangle of pixels, already checked to lie inside the image it is applied to.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// An integer channel type.
+pub(crate) trait Channel: bytemuck::Pod {
+    const MAX: u32;
+    fn get(self) -> u32;
+    fn from_u32(value: u32) -> Self;
+}
+
+impl Channel for u8 {
+    const MAX: u32 = u8::MAX as u32;
+
+    fn get(self) -> u32 {
+        self as u32
+    }
+
+    fn from_u32(value: u32) -> Self {
+        value as u8
+    }
+}
+
+impl Channel for u16 {
+    const MAX: u32 = u16::MAX as u32;
+
+    fn get(self) -> u32 {
+        self as u32
+    }
+
+    fn from_u32(value: u32) -> Self {
+        value as u16
+    }
+}
+
+/// Channels of every pixel: layers always store RGBA.
+const CHANNELS: usize = 4;
+
+/// Mutable view of an RGBA image with 8 or 16-bit channels. The kernels below edit it in place.
+/// Brighten, grayscale and the flips compute exactly what the `image` crate's allocating
+/// operations return; contrast and blur are fixed-point, so every target computes the same
+/// pixels.
+pub(crate) struct Pixels<'a, T = u8> {
+    data: &'a mut [T],
+    width: usize,
+    height: usize,
+    /// Whether the image has transparency. Without it every alpha stays fully opaque, and the
+    /// kernels leave the alpha channel alone.
+    alpha: bool,
+}
+
+impl<'a, T: Channel> Pixels<'a, T> {
+    pub(crate) fn new(data: &'a mut [T], width: u32, height: u32, alpha: bool) -> Self {
+        Pixels { data, width: width as usize, height: height as usize, alpha }
+    }
+}
+
+impl<T: Channel> Pixels<'_, T> {
+    /// The whole image. Rotations and crops change it without touching the buffer's length.
+    pub(crate) fn bounds(&self) -> Rect {
+        Rect { x: 0, y: 0, width: self.width as u32, height: self.height as u32 }
+    }
+
+    /// Channels that hold color, and alpha when the image has transparency.
+    fn channels(&self) -> usize {
+        if self.alpha { CHANNELS } else { CHANNELS - 1 }
+    }
+
+    fn stride(&self) -> usize {
+        self.width * CHANNELS
+    }
+
+    /// The image's pixels, row by row, so that moving a pixel copies all its channels at once.
+    fn pixels(&mut self) -> &mut [[T; CHANNELS]] {
+        let count = self.width * self.height;
+        &mut bytemuck::cast_slice_mut(self.data)[..count]
+    }
+
+    /// Range of `rect`'s channels within each of its rows.
+    fn span(&self, rect: Rect) -> std::ops::Range<usize> {
+        let start = rect.x as usize * CHANNELS;
+        start..start + rect.width as usize * CHANNELS
+    }
+
+    /// The channels of each row of `rect`, top to bottom.
+    fn rows(&mut self, rect: Rect) -> impl Iterator<Item = &mut [T]> {
+        let span = self.span(rect);
+        let stride = self.stride().max(1);
+        self.data.chunks_exact_mut(stride)
+            .skip(rect.y as usize)
+            .take(rect.height as usize)
+            .map(move |row| &mut row[span.clone()])
+    }
+
+    /// Looks every channel of `rect` up in `table`. Alpha is only mapped when `alpha` is set.
+    fn map(&mut self, rect: Rect, table: &[T], alpha: bool) {
+        let colors = if alpha { self.channels() } else { CHANNELS - 1 };
+        for row in self.rows(rect) {
+            for pixel in row.chunks_exact_mut(CHANNELS) {
+                for channel in &mut pixel[..colors] {
+                    *channel = table[channel.get() as usize];
+                }
+            }
+        }
+    }
+}
+
+/// The value of every channel passed through `map`.
+fn table<T: Channel>(map: impl Fn(i64) -> i64) -> Vec<T> {
+    let max = T::MAX as i64;
+    (0..=max).map(|value| T::from_u32(map(value).clamp(0, max) as u32)).collect()
+}
+
+/// Adds `value` steps of the 8-bit scale, which is 257 steps per unit on 16-bit channels. Matches
+/// `DynamicImage::brighten`, which leaves alpha alone.
+pub(crate) fn brighten<T: Channel>(pixels: &mut Pixels<T>, rect: Rect, value: i32) {
+    let value = value as i64 * (T::MAX / u8::MAX as u32) as i64;
+    let table = table::<T>(|channel| channel + value);
+    pixels.map(rect, &table, false);
+}
+
+/// The formula of `DynamicImage::adjust_contrast`, which also adjusts alpha when the image has
+/// transparency, in exact integer arithmetic: every channel moves away from the middle of its range by the factor
+/// `((100 + contrast) / 100)^2`, with `contrast` taken in 1/256 steps.
+pub(crate) fn contrast<T: Channel>(pixels: &mut Pixels<T>, rect: Rect, contrast: f32) {
+    const HUNDRED: i64 = 100 << 8;
+    // Beyond a factor of 10000 no channel value can move any further
+    let scaled = HUNDRED + quantize(contrast, 8).clamp(-101 * HUNDRED, 99 * HUNDRED);
+    let (numerator, denominator) = (scaled * scaled, HUNDRED * HUNDRED);
+    let max = T::MAX as i64;
+    let table = table::<T>(|value| ((2 * value - max) * numerator + max * denominator).div_euclid(2 * denominator));
+    pixels.map(rect, &table, true);
+}
+
+/// Widths of the three box blurs whose succession approximates a Gaussian blur of standard
+/// deviation `sigma`, taken in 1/256 pixel steps. Like `DynamicImage::blur`, a `sigma` that is
+/// not positive means one pixel.
+fn box_widths(sigma: f32) -> [u64; 3] {
+    let sigma = match quantize(sigma, 8) {
+        sigma if sigma <= 0 => 256,
+        sigma => sigma.min(4096 << 8),
+    } as u64;
+    let variance = 12 * sigma * sigma;
+    // The ideal width of three equal boxes is sqrt(12 sigma^2 / 3 + 1); round down to odd
+    let mut lower = isqrt((variance / 3 + (1 << 16)) >> 16);
+    if lower % 2 == 0 {
+        lower -= 1;
+    }
+    // Use `lower` for as many boxes as keeps the total variance closest to sigma^2
+    let numerator = ((3 * lower * lower + 12 * lower + 9) << 16) as i64 - variance as i64;
+    let denominator = ((4 * lower + 4) << 16) as i64;
+    let narrow = div_round(numerator, denominator).clamp(0, 3) as usize;
+    std::array::from_fn(|index| if index < narrow { lower } else { lower + 2 })
+}
+
+/// Box blurs `line` in place with a window of `radius` pixels on each side, repeating the edge
+/// pixels beyond its ends.
+fn box_blur_line(line: &mut [u32], output: &mut Vec<u32>, radius: usize) {
+    let length = line.len();
+    let width = 2 * radius as u64 + 1;
+    let last = length - 1;
+    let mut sum = (radius as u64 + 1) * line[0] as u64
+        + line[1..=radius.min(last)].iter().map(|&value| value as u64).sum::<u64>()
+        + radius.saturating_sub(last) as u64 * line[last] as u64;
+    output.clear();
+    for index in 0..length {
+        output.push(((sum + width / 2) / width) as u32);
+        sum += line[(index + radius + 1).min(last)] as u64;
+        sum -= line[index.saturating_sub(radius)] as u64;
+    }
+    line.copy_from_slice(output);
+}
+
+/// Approximates a Gaussian blur of `rect`, which only reads pixels inside `rect`, with three
+/// integer box blurs. Every channel is blurred, alpha included when the image has transparency.
+pub(crate) fn blur<T: Channel>(pixels: &mut Pixels<T>, rect: Rect, sigma: f32) {
+    if rect.width == 0 || rect.height == 0 {
+        return;
+    }
+    let channels = pixels.channels();
+    let stride = pixels.stride();
+    let (x, y) = (rect.x as usize, rect.y as usize);
+    let (width, height) = (rect.width as usize, rect.height as usize);
+    let mut line = Vec::with_capacity(width.max(height));
+    let mut output = Vec::with_capacity(width.max(height));
+    for box_width in box_widths(sigma) {
+        let radius = (box_width / 2) as usize;
+        if radius == 0 {
+            continue;
+        }
+        for row in y..y + height {
+            for channel in 0..channels {
+                let start = row * stride + x * CHANNELS + channel;
+                line.clear();
+                line.extend((0..width).map(|column| pixels.data[start + column * CHANNELS].get()));
+                box_blur_line(&mut line, &mut output, radius);
+                for (column, &value) in line.iter().enumerate() {
+                    pixels.data[start + column * CHANNELS] = T::from_u32(value);
+                }
+            }
+        }
+        for column in x..x + width {
+            for channel in 0..channels {
+                let start = y * stride + column * CHANNELS + channel;
+                line.clear();
+                line.extend((0..height).map(|row| pixels.data[start + row * stride].get()));
+                box_blur_line(&mut line, &mut output, radius);
+                for (row, &value) in line.iter().enumerate() {
+                    pixels.data[start + row * stride] = T::from_u32(value);
+                }
+            }
+        }
+    }
+}
+
+/// Replaces the color of each pixel with its luma, as `DynamicImage::grayscale` followed by a
+/// conversion back to RGBA does.
+pub(crate) fn grayscale<T: Channel>(pixels: &mut Pixels<T>, rect: Rect) {
+    for row in pixels.rows(rect) {
+        for pixel in row.chunks_exact_mut(CHANNELS) {
+            let luma = (2126 * pixel[0].get() + 7152 * pixel[1].get() + 722 * pixel[2].get()) / 10000;
+            pixel[..3].fill(T::from_u32(luma));
+        }
+    }
+}
+
+pub(crate) fn flip_horizontal<T: Channel>(pixels: &mut Pixels<T>, rect: Rect) {
+    let (x, width) = (rect.x as usize, rect.width as usize);
+    let stride = pixels.width.max(1);
+    for row in pixels.pixels().chunks_exact_mut(stride).skip(rect.y as usize).take(rect.height as usize) {
+        row[x..x + width].reverse();
+    }
+}
+
+pub(crate) fn flip_vertical<T: Channel>(pixels: &mut Pixels<T>, rect: Rect) {
+    let stride = pixels.stride();
+    let span = pixels.span(rect);
+    let (top, height) = (rect.y as usize, rect.height as usize);
+    for offset in 0..height / 2 {
+        let (upper, lower) = pixels.data.split_at_mut((top + height - 1 - offset) * stride);
+        let upper = &mut upper[(top + offset) * stride..];
+        upper[span.clone()].swap_with_slice(&mut lower[span.clone()]);
+    }
+}
+
+/// Turns the whole image upside down.
+pub(crate) fn rotate180<T: Channel>(pixels: &mut Pixels<T>) {
+    pixels.pixels().reverse();
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// `value + step` for `value` and `step` below `modulus`, wrapped to stay below it.
+fn add_wrapping(value: usize, step: usize, modulus: usize) -> usize {
+    let sum = value + step;
+    if sum >= modulus { sum - modulus } else { sum }
+}
+
+/// Columns that `transpose` permutes at a time.
+const STRIP: usize = 16;
+
+/// The rows `transpose` gathers one column from, top to bottom: `step` rows further each time
+/// and one row back after every `period` rows, all modulo `rows`.
+#[derive(Clone, Copy)]
+struct SourceRows {
+    next: usize,
+    step: usize,
+    period: usize,
+    countdown: usize,
+    rows: usize,
+}
+
+impl SourceRows {
+    fn new(first: usize, step: usize, period: usize, rows: usize) -> Self {
+        SourceRows { next: first, step, period, countdown: period, rows }
+    }
+
+    fn advance(&mut self) -> usize {
+        let current = self.next;
+        self.next = add_wrapping(current, self.step, self.rows);
+        self.countdown -= 1;
+        if self.countdown == 0 {
+            self.countdown = self.period;
+            self.next = add_wrapping(self.next, self.rows - 1, self.rows);
+        }
+        current
+    }
+}
+
+/// Moves the pixel in row `sources(column).advance()` of each of `columns`, which are at most a
+/// `STRIP`, to each row in turn. Taking several columns at a time reads and writes whole runs of
+/// pixels.
+fn gather_columns<P: Copy>(pixels: &mut [P], width: usize, columns: std::ops::Range<usize>, scratch: &mut Vec<P>, sources: impl Fn(usize) -> SourceRows) {
+    let count = columns.len();
+    scratch.clear();
+    for row in pixels.chunks_exact(width) {
+        scratch.extend_from_slice(&row[columns.clone()]);
+    }
+    let mut sources: [SourceRows; STRIP] = std::array::from_fn(|offset| sources(columns.start + offset.min(count - 1)));
+    for row in pixels.chunks_exact_mut(width) {
+        for (offset, (pixel, source)) in row[columns.clone()].iter_mut().zip(&mut sources).enumerate() {
+            *pixel = scratch[source.advance() * count + offset];
+        }
+    }
+}
+
+/// Transposes the whole image within its own buffer, with a row or a strip of columns of scratch
+/// space. The transposition is split into a permutation of every column, then of every row, then
+/// of every column again, as in Catanzaro, Keller and Garland, "A Decomposition for In-place
+/// Matrix Transposition". The indices of each permutation are stepped along without dividing.
+fn transpose<T: Channel>(pixels: &mut Pixels<T>) {
+    let (rows, columns) = (pixels.height, pixels.width);
+    (pixels.width, pixels.height) = (rows, columns);
+    if rows == 0 || columns == 0 {
+        return;
+    }
+    let divisor = gcd(rows, columns);
+    let (a, b) = (rows / divisor, columns / divisor);
+    let data = pixels.pixels();
+    let mut scratch = Vec::with_capacity(columns.max(rows * STRIP));
+    let strips = (0..columns).step_by(STRIP).map(|start| start..(start + STRIP).min(columns));
+
+    // Rotate column j up by j / b rows
+    if divisor > 1 {
+        for strip in strips.clone() {
+            gather_columns(data, columns, strip, &mut scratch, |column| SourceRows::new((column / b) % rows, 1, usize::MAX, rows));
+        }
+    }
+
+    // Send column j of row i to column ((i + j / b) % rows + j * rows) % columns
+    let step = rows % columns;
+    for (row, line) in data.chunks_exact_mut(columns).enumerate() {
+        scratch.clear();
+        scratch.extend_from_slice(line);
+        // `shifted` is (i + j / b) % rows, and `wrapped` is that modulo `columns`
+        let (mut shifted, mut wrapped, mut spread, mut countdown) = (row, row % columns, 0, b);
+        for &pixel in &scratch {
+            line[add_wrapping(wrapped, spread, columns)] = pixel;
+            spread = add_wrapping(spread, step, columns);
+            countdown -= 1;
+            if countdown == 0 {
+                countdown = b;
+                shifted += 1;
+                (shifted, wrapped) = if shifted == rows { (0, 0) } else { (shifted, add_wrapping(wrapped, 1, columns)) };
+            }
+        }
+    }
+
+    // Take row i of column j from row (j + i * columns - i / a) % rows
+    for strip in strips {
+        gather_columns(data, columns, strip, &mut scratch, |column| SourceRows::new(column % rows, columns % rows, a, rows));
+    }
+}
+
+/// Turns the whole image a quarter turn by moving every pixel straight to its place, following
+/// each cycle of the permutation and marking the pixels already placed. This takes the fewest
+/// instructions but jumps all over the buffer.
+fn rotate90_by_cycles<T: Channel>(pixels: &mut Pixels<T>, clockwise: bool) {
+    let (width, height) = (pixels.width, pixels.height);
+    (pixels.width, pixels.height) = (height, width);
+    // Pixel (x, y) goes to (height - 1 - y, x) clockwise and to (y, width - 1 - x) otherwise
+    let target = |index: usize| {
+        let (x, y) = (index % width, index / width);
+        if clockwise { x * height + height - 1 - y } else { (width - 1 - x) * height + y }
+    };
+    let data = pixels.pixels();
+    let mut placed = vec![0u32; data.len().div_ceil(32)];
+    let mut start = 0;
+    while start < data.len() {
+        if placed[start / 32] == u32::MAX {
+            start = (start / 32 + 1) * 32;
+            continue;
+        }
+        if placed[start / 32] & (1 << (start % 32)) == 0 {
+            let (mut index, mut pixel) = (start, data[start]);
+            loop {
+                index = target(index);
+                placed[index / 32] |= 1 << (index % 32);
+                std::mem::swap(&mut pixel, &mut data[index]);
+                if index == start {
+                    break;
+                }
+            }
+        }
+        start += 1;
+    }
+}
+
+/// Turns the whole image a quarter turn, clockwise or counterclockwise, within its own buffer.
+/// The zkVM has no cache and charges every instruction alike, so there each pixel is moved once,
+/// wherever it goes. Elsewhere a transposition followed by a flip, which read and write runs of
+/// pixels, is several times faster.
+pub(crate) fn rotate90<T: Channel>(pixels: &mut Pixels<T>, clockwise: bool) {
+    if cfg!(target_os = "zkvm") {
+        return rotate90_by_cycles(pixels, clockwise);
+    }
+    transpose(pixels);
+    let bounds = pixels.bounds();
+    if clockwise {
+        flip_horizontal(pixels, bounds);
+    } else {
+        flip_vertical(pixels, bounds);
+    }
+}
+
+/// Moves `rect` to the start of the buffer, its rows packed together, and makes it the whole
+/// image. The pixels after it are left for the caller to truncate.
+pub(crate) fn crop<T: Channel>(pixels: &mut Pixels<T>, rect: Rect) {
+    let stride = pixels.stride();
+    let span = pixels.span(rect);
+    for row in 0..rect.height as usize {
+        let start = (rect.y as usize + row) * stride + span.start;
+        pixels.data.copy_within(start..start + span.len(), row * span.len());
+    }
+    (pixels.width, pixels.height) = (rect.width as usize, rect.height as usize);
+}
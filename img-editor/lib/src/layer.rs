@@ -1,193 +1,486 @@
-use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+use image::{imageops, ColorType, DynamicImage, ImageBuffer, ImageFormat, Pixel, RgbaImage};
+use std::borrow::Cow;
 use serde::{Serialize, Deserialize};
-use crate::{TextOverlayParameters, Transformation, Region};
-use rusttype::{point, Font as RusttypeFont, Scale};
+use crate::encode::encode_with_metadata;
+use crate::formats::{check_decodable, sniff_format};
+use crate::color::to_working_space;
+use crate::kernels::{self, Channel, Pixels, Rect};
+use crate::metadata::{apply_orientation, orientation};
+use crate::{read_icc_profile, text, EditorError, EditorResult, EncodeOptions, FontRegistry, LayerMask, TextOverlayParameters, Transformation, Region};
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Identifier of a layer inside an `ImageProject`. IDs are never reused within a project, so they
+/// stay valid when other layers are removed or reordered.
+pub type LayerId = u32;
+
+/// Identifier of an edit within its layer's edit stack. Never reused within a layer.
+pub type EditId = u32;
+
+/// One entry of a layer's non-destructive edit stack.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Edit {
+    pub id: EditId,
+    pub transformation: Transformation,
+    pub enabled: bool,
+}
+
+/// Canvas offset that the enabled crops in `edits` add to a layer's position.
+fn crop_offset(edits: &[Edit]) -> (i32, i32) {
+    edits.iter()
+        .filter(|edit| edit.enabled)
+        .fold((0, 0), |(x, y), edit| match &edit.transformation {
+            Transformation::Crop(params) => (x.saturating_add(params.x as i32), y.saturating_add(params.y as i32)),
+            _ => (x, y),
+        })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Layer {
+    /// The rendered pixels in one RGBA buffer, 8 bits per channel or 16 for deeper sources. Every
+    /// edit changes it in place.
     #[serde(skip)]
-    pub image: DynamicImage,
+    pub(crate) image: DynamicImage,
+    /// Layout the edits leave the pixels in, which outputs are encoded with: the source's, gray
+    /// after a full grayscale, RGBA after a region edit or text.
+    #[serde(skip, default = "default_layout")]
+    layout: ColorType,
     #[serde(with = "serde_bytes")]
     image_data: Vec<u8>,
+    #[serde(default)]
+    pub(crate) id: LayerId,
+    #[serde(default)]
+    pub(crate) name: String,
+    #[serde(default = "default_visible")]
+    pub(crate) visible: bool,
+    #[serde(default)]
+    pub(crate) locked: bool,
+    /// Offset of the layer's top-left corner on the project canvas.
+    #[serde(default)]
+    pub(crate) x: i32,
+    #[serde(default)]
+    pub(crate) y: i32,
+    #[serde(default = "default_opacity")]
+    pub(crate) opacity: f32,
+    #[serde(default)]
+    pub(crate) mask: Option<LayerMask>,
+    /// Clipped layers only show inside the alpha of the nearest unclipped layer below them.
+    #[serde(default)]
+    pub(crate) clipped: bool,
+    /// Edits that `image` is rendered from, applied in order to `image_data`.
+    #[serde(default)]
+    pub(crate) edits: Vec<Edit>,
+    #[serde(default)]
+    pub(crate) next_edit_id: EditId,
+}
+
+fn default_visible() -> bool {
+    true
+}
+
+fn default_opacity() -> f32 {
+    1.0
+}
+
+fn default_layout() -> ColorType {
+    ColorType::Rgba8
+}
+
+/// The RGBA layout at the depth of `layout`.
+fn rgba_layout(layout: ColorType) -> ColorType {
+    match layout {
+        ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16 => ColorType::Rgba16,
+        _ => ColorType::Rgba8,
+    }
+}
+
+/// The gray layout, with or without alpha, that `DynamicImage::grayscale` turns `layout` into.
+fn gray_layout(layout: ColorType) -> ColorType {
+    match (rgba_layout(layout), layout.has_alpha()) {
+        (ColorType::Rgba16, true) => ColorType::La16,
+        (ColorType::Rgba16, false) => ColorType::L16,
+        (_, true) => ColorType::La8,
+        (_, false) => ColorType::L8,
+    }
+}
+
+/// Runs the kernel of `transformation` on `rect` of the RGBA `data` of a `width` by `height`
+/// image and returns the new size. Quarter turns and crops change the size of the image, not the
+/// allocation behind it.
+fn edit_buffer<T: Channel>(data: &mut Vec<T>, (width, height): (u32, u32), rect: Rect, transformation: &Transformation, alpha: bool) -> (u32, u32) {
+    let mut pixels = Pixels::new(data, width, height, alpha);
+    match transformation {
+        Transformation::Grayscale { .. } => kernels::grayscale(&mut pixels, rect),
+        Transformation::FlipHorizontal { .. } => kernels::flip_horizontal(&mut pixels, rect),
+        Transformation::FlipVertical { .. } => kernels::flip_vertical(&mut pixels, rect),
+        Transformation::Rotate90 => kernels::rotate90(&mut pixels, true),
+        Transformation::Rotate180 => kernels::rotate180(&mut pixels),
+        Transformation::Rotate270 => kernels::rotate90(&mut pixels, false),
+        Transformation::Brighten { value, .. } => kernels::brighten(&mut pixels, rect, *value),
+        Transformation::Contrast { contrast, .. } => kernels::contrast(&mut pixels, rect, *contrast),
+        Transformation::Blur { sigma, .. } => kernels::blur(&mut pixels, rect, *sigma),
+        Transformation::Crop(_) => kernels::crop(&mut pixels, rect),
+        Transformation::TextOverlay(_) => unreachable!("text is drawn by `apply_text_overlay`"),
+    }
+    let Rect { width, height, .. } = pixels.bounds();
+    data.truncate(width as usize * height as usize * 4);
+    (width, height)
+}
+
+/// Decodes an image into the sRGB working space and turns it upright according to its EXIF
+/// orientation. Floating point images, such as float TIFFs, are converted to 16 bits, so every
+/// edit runs on an integer kernel.
+pub(crate) fn decode(image_data: &[u8]) -> EditorResult<DynamicImage> {
+    let format = sniff_format(image_data)
+        .ok_or_else(|| EditorError::UnsupportedFormat("unrecognized image data".to_string()))?;
+    check_decodable(format)?;
+    #[cfg(feature = "tiff")]
+    let float = match format {
+        ImageFormat::Tiff => decode_float_tiff(image_data)?,
+        _ => None,
+    };
+    #[cfg(not(feature = "tiff"))]
+    let float = None;
+    let image = match float {
+        Some(image) => image,
+        None => image::load_from_memory_with_format(image_data, format)
+            .map_err(|e| match e {
+                image::ImageError::Unsupported(e) => EditorError::UnsupportedFormat(e.to_string()),
+                e => EditorError::Decode(e.to_string()),
+            })?,
+    };
+    let image = match image {
+        DynamicImage::ImageRgb32F(_) => DynamicImage::ImageRgb16(image.to_rgb16()),
+        DynamicImage::ImageRgba32F(_) => DynamicImage::ImageRgba16(image.to_rgba16()),
+        image => image,
+    };
+    let image = match read_icc_profile(image_data) {
+        Some(icc) => to_working_space(image, &icc),
+        None => image,
+    };
+    Ok(apply_orientation(image, orientation(image_data)))
+}
+
+/// Decodes a TIFF with 32-bit floating point RGB or RGBA samples, which `image` rejects. `None`
+/// for TIFFs with integer samples.
+#[cfg(feature = "tiff")]
+fn decode_float_tiff(image_data: &[u8]) -> EditorResult<Option<DynamicImage>> {
+    use tiff::decoder::{Decoder, DecodingResult};
+    use tiff::tags::{SampleFormat, Tag};
+
+    let error = |e: tiff::TiffError| EditorError::Decode(e.to_string());
+    let mut decoder = Decoder::new(std::io::Cursor::new(image_data)).map_err(error)?;
+    let float = decoder.find_tag_unsigned_vec::<u16>(Tag::SampleFormat).map_err(error)?
+        .is_some_and(|formats| formats.contains(&SampleFormat::IEEEFP.to_u16()));
+    if !float {
+        return Ok(None);
+    }
+    let (width, height) = decoder.dimensions().map_err(error)?;
+    let color = decoder.colortype().map_err(error)?;
+    let DecodingResult::F32(samples) = decoder.read_image().map_err(error)? else {
+        return Err(EditorError::UnsupportedFormat(format!("{color:?} floating point TIFF")));
+    };
+    let image = match color {
+        tiff::ColorType::RGB(32) => ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgb32F),
+        tiff::ColorType::RGBA(32) => ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgba32F),
+        color => return Err(EditorError::UnsupportedFormat(format!("{color:?} floating point TIFF"))),
+    };
+    image.map(Some).ok_or_else(|| EditorError::Decode("TIFF image data ends early".to_string()))
+}
+
+pub(crate) fn encode(image: &DynamicImage, format: ImageFormat) -> EditorResult<Vec<u8>> {
+    let mut bytes: Vec<u8> = Vec::new();
+    image
+        .write_to(&mut std::io::Cursor::new(&mut bytes), format)
+        .map_err(|e| match e {
+            image::ImageError::Unsupported(e) => EditorError::UnsupportedFormat(e.to_string()),
+            e => EditorError::Encode(e.to_string()),
+        })?;
+    Ok(bytes)
+}
+
+/// Whether `image` stores 16 bits per channel.
+pub(crate) fn is_16_bit(image: &DynamicImage) -> bool {
+    matches!(image, DynamicImage::ImageLuma16(_) | DynamicImage::ImageLumaA16(_) | DynamicImage::ImageRgb16(_) | DynamicImage::ImageRgba16(_))
+}
+
+/// Copies the pixels that drawing changed from `original` to `drawn` into `output`, so pixels the
+/// drawing did not touch keep their full precision.
+fn copy_changed<P: Pixel>(output: &mut ImageBuffer<P, Vec<P::Subpixel>>, original: &RgbaImage, drawn: &RgbaImage, convert: impl Fn(u8) -> P::Subpixel) {
+    for ((x, y, pixel), before) in drawn.enumerate_pixels().zip(original.pixels()) {
+        if pixel != before {
+            output.put_pixel(x, y, *P::from_slice(&pixel.0.map(&convert)));
+        }
+    }
 }
 
 impl Layer {
-    pub fn new(image_data: &[u8]) -> Result<Layer, String> {
-        let img = image::load_from_memory(image_data)
-            .map_err(|e| format!("Failed to load image: {}", e))?;
-        Ok(Layer { 
-            image: img,
-            image_data: image_data.to_vec(),
-        })
+    /// Decodes `image_data` into a layer with 8 or 16 bits per channel. Floating point images,
+    /// such as 32-bit float TIFFs, are stored at 16 bits: values outside 0 to 1 are clamped, so
+    /// HDR highlights are lost, and exports are at most 16-bit.
+    pub fn new(image_data: &[u8]) -> EditorResult<Layer> {
+        Ok(Self::from_image(decode(image_data)?, image_data.to_vec()))
     }
 
-    pub fn new_empty(width: u32, height: u32) -> Result<Self, String> {
+    pub fn new_empty(width: u32, height: u32) -> EditorResult<Self> {
         let image = DynamicImage::ImageRgba8(RgbaImage::new(width, height));
         let mut bytes = Vec::new();
         image.write_to(&mut std::io::Cursor::new(&mut bytes), ImageFormat::Png)
-            .map_err(|e| format!("Failed to encode empty image: {}", e))?;
-        
-        Ok(Layer { 
+            .map_err(|e| EditorError::Encode(e.to_string()))?;
+        Ok(Self::from_image(image, bytes))
+    }
+
+    /// A layer showing `image`. Edit stacks are replayed from `image_data`, which is empty for
+    /// images that were never encoded, such as animation frames.
+    pub(crate) fn from_image(image: DynamicImage, image_data: Vec<u8>) -> Layer {
+        let layout = image.color();
+        let image = match is_16_bit(&image) {
+            true => DynamicImage::ImageRgba16(image.into_rgba16()),
+            false => DynamicImage::ImageRgba8(image.into_rgba8()),
+        };
+        Layer {
             image,
-            image_data: bytes,
-        })
+            layout,
+            image_data,
+            id: 0,
+            name: String::new(),
+            visible: true,
+            locked: false,
+            x: 0,
+            y: 0,
+            opacity: 1.0,
+            mask: None,
+            clipped: false,
+            edits: Vec::new(),
+            next_edit_id: 0,
+        }
+    }
+
+    /// The rendered pixels, RGBA with 8 or 16 bits per channel.
+    pub fn image(&self) -> &DynamicImage {
+        &self.image
+    }
+
+    pub fn id(&self) -> LayerId {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
     }
 
-    fn apply_region_transformation(&mut self, region: &Region, transform: Box<dyn Fn(&mut DynamicImage)>) -> Result<(), String> {
-        let mut sub_image = self.image.crop_imm(
-            region.x,
-            region.y,
-            region.width,
-            region.height
-        );
-        
-        transform(&mut sub_image);
-        
-        // Convert both images to RGBA8 for pixel manipulation
-        let mut new_image = self.image.to_rgba8();
-        let sub_rgba = sub_image.to_rgba8();
-        
-        // Copy the transformed region back
-        for y in 0..region.height {
-            for x in 0..region.width {
-                let pixel = sub_rgba.get_pixel(x, y);
-                new_image.put_pixel(x + region.x, y + region.y, *pixel);
-            }
+    pub fn position(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+
+    pub fn opacity(&self) -> f32 {
+        self.opacity
+    }
+
+    pub fn mask(&self) -> Option<&LayerMask> {
+        self.mask.as_ref()
+    }
+
+    pub fn is_clipped(&self) -> bool {
+        self.clipped
+    }
+
+    /// The original bytes the layer was created from.
+    pub fn image_data(&self) -> &[u8] {
+        &self.image_data
+    }
+
+    /// Format of the original bytes, detected from their magic bytes. Empty layers are PNG.
+    pub fn source_format(&self) -> Option<ImageFormat> {
+        sniff_format(&self.image_data)
+    }
+
+    pub fn edits(&self) -> &[Edit] {
+        &self.edits
+    }
+
+    /// The enabled edits in order, exactly as `ImageInput::transformations` expects them.
+    pub fn transformations(&self) -> Vec<Transformation> {
+        self.edits.iter()
+            .filter(|edit| edit.enabled)
+            .map(|edit| edit.transformation.clone())
+            .collect()
+    }
+
+    /// Applies a transformation on top of the current image and records it as a new edit.
+    pub(crate) fn push_edit(&mut self, transformation: Transformation, fonts: &FontRegistry) -> EditorResult<EditId> {
+        self.transform(&transformation, fonts)?;
+        let id = self.next_edit_id;
+        self.next_edit_id += 1;
+        self.edits.push(Edit { id, transformation, enabled: true });
+        Ok(id)
+    }
+
+    /// Replaces the edit stack and renders the image again from the original bytes. Crops keep
+    /// the layer's pixels in place on the canvas. Fails without changing anything if an edit
+    /// cannot be applied or the result no longer fits the layer's mask.
+    pub(crate) fn set_edits(&mut self, edits: Vec<Edit>, fonts: &FontRegistry) -> EditorResult<()> {
+        let mut rendered = Layer::new(&self.image_data)?;
+        for edit in edits.iter().filter(|edit| edit.enabled) {
+            rendered.transform(&edit.transformation, fonts)?;
+        }
+        if let Some(mask) = &self.mask {
+            mask.check_size(rendered.image.width(), rendered.image.height())?;
         }
-        
-        self.image = DynamicImage::ImageRgba8(new_image);
+
+        let (old_x, old_y) = crop_offset(&self.edits);
+        self.x = self.x.saturating_sub(old_x).saturating_add(rendered.x);
+        self.y = self.y.saturating_sub(old_y).saturating_add(rendered.y);
+        self.image = rendered.image;
+        self.layout = rendered.layout;
+        self.edits = edits;
         Ok(())
     }
 
-    fn apply_transform_with_region(&mut self, region: Option<Region>, transform: Box<dyn Fn(&DynamicImage) -> DynamicImage>) -> Result<(), String> {
-        match region {
-            Some(region) => self.apply_region_transformation(&region, Box::new(move |img| {
-                *img = transform(img);
-            })),
-            None => {
-                self.image = transform(&self.image);
-                Ok(())
-            }
+    /// Approximate heap memory held by the layer.
+    pub(crate) fn memory(&self) -> usize {
+        self.image.as_bytes().len() + self.image_data.len() + self.mask.as_ref().map_or(0, LayerMask::memory)
+    }
+
+    fn map_mask(&mut self, operation: fn(&image::GrayImage) -> image::GrayImage) {
+        if let Some(mask) = &self.mask {
+            self.mask = Some(mask.map(operation));
         }
     }
 
-    pub fn apply_transformation(&mut self, transformation: Transformation) -> Result<(), String> {
-        match transformation {
-            Transformation::Grayscale { region } => {
-                self.apply_transform_with_region(region, Box::new(|img| img.grayscale()))
-            },
-            Transformation::FlipHorizontal { region } => {
-                self.apply_transform_with_region(region, Box::new(|img| img.fliph()))
+    /// Runs the kernel of `transformation` in place on `region`, or on the whole image. An edited
+    /// region leaves the layout RGBA, as editing a copy of the region and pasting it back did.
+    fn edit(&mut self, region: Option<&Region>, transformation: &Transformation) -> EditorResult<()> {
+        let rect = match region {
+            Some(region) => {
+                EditorError::check_region(region.x, region.y, region.width, region.height, self.image.width(), self.image.height())?;
+                Rect { x: region.x, y: region.y, width: region.width, height: region.height }
             },
-            Transformation::FlipVertical { region } => {
-                self.apply_transform_with_region(region, Box::new(|img| img.flipv()))
+            None => Rect { x: 0, y: 0, width: self.image.width(), height: self.image.height() },
+        };
+        self.run_kernel(rect, transformation);
+        if region.is_some() {
+            self.layout = rgba_layout(self.layout);
+        }
+        Ok(())
+    }
+
+    fn run_kernel(&mut self, rect: Rect, transformation: &Transformation) {
+        let alpha = self.layout.has_alpha();
+        let size = (self.image.width(), self.image.height());
+        let expect = "kernels keep the image inside its buffer";
+        self.image = match std::mem::take(&mut self.image) {
+            DynamicImage::ImageRgba8(buffer) => {
+                let mut data = buffer.into_raw();
+                let (width, height) = edit_buffer(&mut data, size, rect, transformation, alpha);
+                DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, data).expect(expect))
             },
-            Transformation::Rotate90 => {
-                self.image = self.image.rotate90();
-                Ok(())
+            DynamicImage::ImageRgba16(buffer) => {
+                let mut data = buffer.into_raw();
+                let (width, height) = edit_buffer(&mut data, size, rect, transformation, alpha);
+                DynamicImage::ImageRgba16(ImageBuffer::from_raw(width, height, data).expect(expect))
             },
-            Transformation::Rotate180 => {
-                self.image = self.image.rotate180();
+            _ => unreachable!("layers store RGBA images"),
+        };
+    }
+
+    pub fn apply_transformation(&mut self, transformation: Transformation) -> EditorResult<()> {
+        self.transform(&transformation, &FontRegistry::new())
+    }
+
+    pub fn apply_transformation_with_fonts(&mut self, transformation: Transformation, fonts: &FontRegistry) -> EditorResult<()> {
+        self.transform(&transformation, fonts)
+    }
+
+    /// Applies `transformation` to the pixels without recording it in the edit stack.
+    pub(crate) fn transform(&mut self, transformation: &Transformation, fonts: &FontRegistry) -> EditorResult<()> {
+        match transformation {
+            Transformation::Grayscale { region: None } => {
+                self.edit(None, transformation)?;
+                self.layout = gray_layout(self.layout);
                 Ok(())
             },
-            Transformation::Rotate270 => {
-                self.image = self.image.rotate270();
-                Ok(())
+            Transformation::FlipHorizontal { region: None } => {
+                self.map_mask(imageops::flip_horizontal);
+                self.edit(None, transformation)
             },
-            Transformation::Brighten { value, region } => {
-                self.apply_transform_with_region(region, Box::new(move |img| img.brighten(value)))
+            Transformation::FlipVertical { region: None } => {
+                self.map_mask(imageops::flip_vertical);
+                self.edit(None, transformation)
             },
-            Transformation::Contrast { contrast, region } => {
-                self.apply_transform_with_region(region, Box::new(move |img| img.adjust_contrast(contrast)))
+            Transformation::Rotate90 => {
+                self.map_mask(imageops::rotate90);
+                self.edit(None, transformation)
             },
-            Transformation::Blur { sigma, region } => {
-                self.apply_transform_with_region(region, Box::new(move |img| img.blur(sigma)))
+            Transformation::Rotate180 => {
+                self.map_mask(imageops::rotate180);
+                self.edit(None, transformation)
             },
-            Transformation::TextOverlay(params) => {
-                self.apply_text_overlay(&params)
-                    .map(|img| { self.image = img; })
+            Transformation::Rotate270 => {
+                self.map_mask(imageops::rotate270);
+                self.edit(None, transformation)
             },
+            Transformation::Grayscale { region }
+            | Transformation::FlipHorizontal { region }
+            | Transformation::FlipVertical { region }
+            | Transformation::Brighten { region, .. }
+            | Transformation::Contrast { region, .. }
+            | Transformation::Blur { region, .. } => self.edit(region.as_ref(), transformation),
+            Transformation::TextOverlay(params) => self.apply_text_overlay(params, fonts),
             Transformation::Crop(params) => {
-                self.image = self.image.crop_imm(
-                    params.x,
-                    params.y,
-                    params.width,
-                    params.height
-                );
+                EditorError::check_region(params.x, params.y, params.width, params.height, self.image.width(), self.image.height())?;
+                self.run_kernel(Rect { x: params.x, y: params.y, width: params.width, height: params.height }, transformation);
+                self.mask = self.mask.as_ref()
+                    .map(|mask| mask.crop(params.x, params.y, params.width, params.height));
+                // Keep the remaining pixels where they were on the canvas
+                self.x = self.x.saturating_add(params.x as i32);
+                self.y = self.y.saturating_add(params.y as i32);
                 Ok(())
             },
         }
     }
 
-    fn apply_text_overlay(&mut self, params: &TextOverlayParameters) -> Result<DynamicImage, String> {
-        let font_data = include_bytes!("../assets/impact.ttf");
-        let font = RusttypeFont::try_from_bytes(font_data)
-            .ok_or("Failed to load font")?;
-
-        let mut rgba_image = self.image.to_rgba8();
-        let scale = Scale::uniform(params.size as f32);
-        let color = Self::hex_to_rgba(&params.color)?;
-
-        let v_metrics = font.v_metrics(scale);
-        let glyphs: Vec<_> = font.layout(
-            &params.text,
-            scale,
-            point(params.x as f32, params.y as f32 + v_metrics.ascent)
-        ).collect();
-
-        let width = rgba_image.width() as i32;
-        let height = rgba_image.height() as i32;
-
-        for glyph in &glyphs {
-            if let Some(bb) = glyph.pixel_bounding_box() {
-                glyph.draw(|x, y, v| {
-                    let px = x as i32 + bb.min.x;
-                    let py = y as i32 + bb.min.y;
-                    
-                    if px >= 0 && px < width && py >= 0 && py < height {
-                        let alpha = (v * 255.0) as u8;
-                        if alpha > 0 {
-                            rgba_image.put_pixel(px as u32, py as u32, Rgba([
-                                color[0],
-                                color[1], 
-                                color[2],
-                                alpha
-                            ]));
-                        }
-                    }
-                });
-            }
+    /// Text is drawn in 8 bits. On 16-bit layers only the pixels it covers lose precision.
+    fn apply_text_overlay(&mut self, params: &TextOverlayParameters, fonts: &FontRegistry) -> EditorResult<()> {
+        if let DynamicImage::ImageRgba8(pixels) = &mut self.image {
+            // Fonts and colors are resolved before the first pixel is drawn, so a failed overlay
+            // leaves the image untouched
+            text::draw_text(pixels, fonts, params)?;
+        } else {
+            let original = self.image.to_rgba8();
+            let mut drawn = original.clone();
+            text::draw_text(&mut drawn, fonts, params)?;
+            let DynamicImage::ImageRgba16(pixels) = &mut self.image else {
+                unreachable!("layers store RGBA images")
+            };
+            copy_changed(pixels, &original, &drawn, |channel| channel as u16 * 257);
         }
-
-        Ok(DynamicImage::ImageRgba8(rgba_image))
+        self.layout = rgba_layout(self.layout);
+        Ok(())
     }
 
-    pub fn to_bytes(&self, format: ImageFormat, quality: Option<f32>) -> Result<Vec<u8>, String> {
-        let mut bytes: Vec<u8> = Vec::new();
-        match (format, quality) {
-            (ImageFormat::Jpeg, Some(q)) => q.clamp(0.0, 100.0) as u8,
-            (ImageFormat::Jpeg, None) => 90,
-            (ImageFormat::WebP, Some(q)) => (q.clamp(0.0, 100.0) * 100.0) as u8,
-            (ImageFormat::WebP, None) => 80,
-            _ => 100,
-        };
-
-        self.image
-            .write_to(&mut std::io::Cursor::new(&mut bytes), format)
-            .map_err(|e| format!("Failed to encode image: {}", e))?;
-        Ok(bytes)
+    /// The pixels in the layer's layout, as outputs are encoded.
+    fn output(&self) -> Cow<'_, DynamicImage> {
+        Cow::Owned(match self.layout {
+            ColorType::L8 => DynamicImage::ImageLuma8(self.image.to_luma8()),
+            ColorType::La8 => DynamicImage::ImageLumaA8(self.image.to_luma_alpha8()),
+            ColorType::Rgb8 => DynamicImage::ImageRgb8(self.image.to_rgb8()),
+            ColorType::L16 => DynamicImage::ImageLuma16(self.image.to_luma16()),
+            ColorType::La16 => DynamicImage::ImageLumaA16(self.image.to_luma_alpha16()),
+            ColorType::Rgb16 => DynamicImage::ImageRgb16(self.image.to_rgb16()),
+            _ => return Cow::Borrowed(&self.image),
+        })
     }
 
-    fn hex_to_rgba(hex: &str) -> Result<Rgba<u8>, String> {
-        let hex = hex.trim_start_matches('#');
-        if hex.len() != 6 {
-            return Err("Invalid hex color".to_string());
-        }
-        
-        let r = u8::from_str_radix(&hex[0..2], 16).map_err(|_| "Invalid red value")?;
-        let g = u8::from_str_radix(&hex[2..4], 16).map_err(|_| "Invalid green value")?;
-        let b = u8::from_str_radix(&hex[4..6], 16).map_err(|_| "Invalid blue value")?;
-        
-        Ok(Rgba([r, g, b, 255]))
+    /// Encodes the layer. 16-bit layers are written as 16-bit PNG and TIFF;
+    /// other formats store 8 bits per channel.
+    pub fn to_bytes(&self, format: ImageFormat, options: &EncodeOptions) -> EditorResult<Vec<u8>> {
+        encode_with_metadata(&self.output(), format, options, &self.image_data)
     }
 }
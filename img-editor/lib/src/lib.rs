@@ -1,12 +1,54 @@
 use alloy_sol_types::sol;
 
+mod animation;
+mod color;
+#[cfg(feature = "conformance")]
+mod conformance;
+mod encode;
+mod error;
+mod estimate;
+mod fonts;
+mod fixed;
+mod formats;
+pub mod hash;
+mod history;
+mod icc;
+mod kernels;
 mod project;
+#[cfg(feature = "host")]
+mod project_file;
 mod transformations;
 mod layer;
+mod mask;
+mod metadata;
+mod profile;
+mod text;
+mod tiles;
+#[cfg(feature = "shaping")]
+mod shaping;
 
-pub use project::ImageProject;
+pub use animation::{Animation, AnimationInput, Frame, FrameEdit, FrameRange};
+pub use color::ColorProfilePolicy;
+#[cfg(feature = "conformance")]
+pub use conformance::{check_known_answers, conformance_cases, run_conformance, ConformanceCase, ConformanceResult};
+pub use encode::{AlphaHandling, ChromaSubsampling, EncodeOptions, JpegOptions, OutputFormat, PngCompression, PngFilter, PngOptions};
+pub use error::{EditorError, EditorResult};
+pub use estimate::{estimate_proof, ProofEstimate, ProvingRates, StageEstimate};
+pub use fonts::{FontRegistry, BUNDLED_FONTS, DEFAULT_FONT};
+pub use formats::{can_decode, can_encode, format_name, input_formats, output_formats, parse_format, sniff_format};
+pub use history::DEFAULT_HISTORY_BUDGET;
+pub use project::{check_rendered_image, render_input, ImageProject, LayerInfo};
+#[cfg(feature = "host")]
+pub use project_file::PROJECT_FORMAT_VERSION;
 pub use transformations::*;
-pub use layer::Layer;
+pub use layer::{Edit, EditId, Layer, LayerId};
+pub use mask::{BrushStroke, LayerMask, Selection};
+pub use metadata::{kept_metadata, read_icc_profile, MetadataPolicy};
+#[cfg(feature = "host")]
+pub use metadata::{read_metadata, ImageMetadata, MetadataField};
+pub use profile::track;
+pub use text::{measure_text, TextBounds};
+pub use tiles::{full_frame_reason, full_frame_transformations, render_tiled, FullFrameReason, FullFrameTransformation};
 
 #[cfg(feature = "wasm")]
 pub mod wasm;
@@ -17,5 +59,10 @@ sol! {
         bytes32 transformed_image_hash;
         bytes32 signer_public_key;
         bool has_signature;
+        bytes32[] font_hashes;
+        bytes32[] layer_hashes;
+        string[] metadata_fields;
+        bytes32 output_profile_hash;
+        bytes32 frames_hash;
     }
 }
\ No newline at end of file
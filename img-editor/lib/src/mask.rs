@@ -0,0 +1,261 @@
+use image::{imageops, DynamicImage, GrayImage, ImageFormat};
+use serde::{Deserialize, Serialize};
+use crate::layer::encode;
+use crate::text::to_coverage;
+use crate::{EditorError, EditorResult, Region};
+
+/// Supersampling grid used to anti-alias selection edges.
+const SAMPLES: u32 = 4;
+
+/// An area of a layer, in layer pixel coordinates.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Selection {
+    All,
+    Rectangle(Region),
+    Ellipse(Region),
+    /// A closed polygon, filled with the even-odd rule.
+    Polygon { points: Vec<[f32; 2]> },
+}
+
+impl Selection {
+    fn contains(&self, x: f32, y: f32) -> bool {
+        match self {
+            Selection::All => true,
+            Selection::Rectangle(r) => {
+                x >= r.x as f32 && y >= r.y as f32
+                    && x < r.x as f32 + r.width as f32 && y < r.y as f32 + r.height as f32
+            },
+            Selection::Ellipse(r) => {
+                let (rx, ry) = (r.width as f32 / 2.0, r.height as f32 / 2.0);
+                if rx <= 0.0 || ry <= 0.0 {
+                    return false;
+                }
+                let dx = (x - r.x as f32 - rx) / rx;
+                let dy = (y - r.y as f32 - ry) / ry;
+                dx * dx + dy * dy <= 1.0
+            },
+            Selection::Polygon { points } => {
+                let mut inside = false;
+                let mut previous = match points.last() {
+                    Some(point) => *point,
+                    None => return false,
+                };
+                for &point in points {
+                    if (point[1] > y) != (previous[1] > y) {
+                        let cross = (previous[0] - point[0]) * (y - point[1]) / (previous[1] - point[1]) + point[0];
+                        if x < cross {
+                            inside = !inside;
+                        }
+                    }
+                    previous = point;
+                }
+                inside
+            },
+        }
+    }
+
+    /// Fraction of the pixel at `(x, y)` covered by the selection.
+    fn coverage(&self, x: u32, y: u32) -> f32 {
+        match self {
+            Selection::All => 1.0,
+            Selection::Rectangle(_) => {
+                if self.contains(x as f32 + 0.5, y as f32 + 0.5) { 1.0 } else { 0.0 }
+            },
+            _ => {
+                let step = 1.0 / SAMPLES as f32;
+                let mut hits = 0;
+                for sy in 0..SAMPLES {
+                    for sx in 0..SAMPLES {
+                        let px = x as f32 + (sx as f32 + 0.5) * step;
+                        let py = y as f32 + (sy as f32 + 0.5) * step;
+                        if self.contains(px, py) {
+                            hits += 1;
+                        }
+                    }
+                }
+                hits as f32 / (SAMPLES * SAMPLES) as f32
+            },
+        }
+    }
+}
+
+/// A brush stroke painted onto a mask. `value` 255 reveals the layer, 0 hides it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BrushStroke {
+    pub points: Vec<[f32; 2]>,
+    pub radius: f32,
+    /// Fraction of the radius painted at full strength before the edge fades out.
+    #[serde(default = "default_hardness")]
+    pub hardness: f32,
+    pub value: u8,
+}
+
+fn default_hardness() -> f32 {
+    1.0
+}
+
+fn distance_to_segment(p: [f32; 2], a: [f32; 2], b: [f32; 2]) -> f32 {
+    let (abx, aby) = (b[0] - a[0], b[1] - a[1]);
+    let length = abx * abx + aby * aby;
+    let t = if length > 0.0 {
+        (((p[0] - a[0]) * abx + (p[1] - a[1]) * aby) / length).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let (dx, dy) = (p[0] - a[0] - abx * t, p[1] - a[1] - aby * t);
+    (dx * dx + dy * dy).sqrt()
+}
+
+/// A grayscale mask the size of its layer. Each value scales the layer's coverage, so 255 shows
+/// the pixel and 0 hides it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LayerMask {
+    width: u32,
+    height: u32,
+    #[serde(with = "serde_bytes")]
+    data: Vec<u8>,
+}
+
+impl LayerMask {
+    /// A mask filled with `value`.
+    pub fn new(width: u32, height: u32, value: u8) -> Self {
+        Self { width, height, data: vec![value; width as usize * height as usize] }
+    }
+
+    /// A mask revealing the selection and hiding everything else.
+    pub fn from_selection(selection: &Selection, width: u32, height: u32) -> Self {
+        let mut mask = Self::new(width, height, 0);
+        mask.fill(selection, 255);
+        mask
+    }
+
+    /// Builds a mask from the luminance of any image.
+    pub fn from_image(image: &DynamicImage) -> Self {
+        Self::from_gray(image.to_luma8())
+    }
+
+    fn from_gray(image: GrayImage) -> Self {
+        Self { width: image.width(), height: image.height(), data: image.into_raw() }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub(crate) fn memory(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> u8 {
+        self.data[(y * self.width + x) as usize]
+    }
+
+    /// Coverage at a pixel as a fraction of `FULL_COVERAGE`.
+    pub(crate) fn alpha(&self, x: u32, y: u32) -> u32 {
+        to_coverage(self.get(x, y))
+    }
+
+    pub fn invert(&mut self) {
+        for value in self.data.iter_mut() {
+            *value = 255 - *value;
+        }
+    }
+
+    /// Moves every pixel in the selection towards `value`, proportionally to how much of it is
+    /// covered.
+    pub fn fill(&mut self, selection: &Selection, value: u8) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let coverage = selection.coverage(x, y);
+                self.mix(x, y, value, coverage);
+            }
+        }
+    }
+
+    pub fn paint(&mut self, stroke: &BrushStroke) -> EditorResult<()> {
+        if stroke.points.is_empty() || stroke.radius.is_nan() || stroke.radius <= 0.0 {
+            return Err(EditorError::InvalidArgument(
+                "a brush stroke needs at least one point and a positive radius".to_string(),
+            ));
+        }
+        let radius = stroke.radius;
+        let solid = radius * stroke.hardness.clamp(0.0, 1.0);
+
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+        for point in &stroke.points {
+            min_x = min_x.min(point[0] - radius);
+            min_y = min_y.min(point[1] - radius);
+            max_x = max_x.max(point[0] + radius);
+            max_y = max_y.max(point[1] + radius);
+        }
+        let left = min_x.floor().max(0.0) as u32;
+        let top = min_y.floor().max(0.0) as u32;
+        let right = (max_x.ceil().max(0.0) as u32).min(self.width);
+        let bottom = (max_y.ceil().max(0.0) as u32).min(self.height);
+
+        for y in top..bottom {
+            for x in left..right {
+                let center = [x as f32 + 0.5, y as f32 + 0.5];
+                let distance = match stroke.points.as_slice() {
+                    [point] => distance_to_segment(center, *point, *point),
+                    points => points.windows(2)
+                        .map(|segment| distance_to_segment(center, segment[0], segment[1]))
+                        .fold(f32::MAX, f32::min),
+                };
+                let coverage = if distance <= solid {
+                    1.0
+                } else if distance < radius {
+                    (radius - distance) / (radius - solid)
+                } else {
+                    0.0
+                };
+                self.mix(x, y, stroke.value, coverage);
+            }
+        }
+        Ok(())
+    }
+
+    fn mix(&mut self, x: u32, y: u32, value: u8, amount: f32) {
+        if amount <= 0.0 {
+            return;
+        }
+        let index = (y * self.width + x) as usize;
+        let current = self.data[index] as f32;
+        self.data[index] = (current + (value as f32 - current) * amount.min(1.0)).round() as u8;
+    }
+
+    /// Fails unless the mask matches a `width` x `height` layer. Masks from untrusted input are
+    /// checked before use.
+    pub(crate) fn check_size(&self, width: u32, height: u32) -> EditorResult<()> {
+        if self.width != width || self.height != height || self.data.len() != width as usize * height as usize {
+            return Err(EditorError::InvalidArgument(format!(
+                "mask is {}x{} but the layer is {}x{}",
+                self.width, self.height, width, height
+            )));
+        }
+        Ok(())
+    }
+
+    /// Applies a geometric operation so the mask keeps following its layer.
+    pub(crate) fn map(&self, operation: impl FnOnce(&GrayImage) -> GrayImage) -> Self {
+        Self::from_gray(operation(&self.to_image()))
+    }
+
+    pub(crate) fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Self {
+        self.map(|image| imageops::crop_imm(image, x, y, width, height).to_image())
+    }
+
+    pub fn to_image(&self) -> GrayImage {
+        GrayImage::from_raw(self.width, self.height, self.data.clone())
+            .expect("mask data matches its dimensions")
+    }
+
+    /// Encodes the mask as a grayscale PNG.
+    pub fn to_png(&self) -> EditorResult<Vec<u8>> {
+        encode(&DynamicImage::ImageLuma8(self.to_image()), ImageFormat::Png)
+    }
+}
@@ -0,0 +1,507 @@
+use exif::{Context, Exif, Field, In, Reader, Tag};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use image::{DynamicImage, ImageFormat};
+use serde::{Deserialize, Serialize};
+use std::io::{Cursor, Read, Write};
+#[cfg(feature = "host")]
+use crate::color::profile_description;
+use crate::{sniff_format, EditorError, EditorResult, ImageInput};
+
+const JPEG_EXIF_PREFIX: &[u8] = b"Exif\0\0";
+const JPEG_XMP_PREFIX: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const PNG_XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+const JPEG_ICC_PREFIX: &[u8] = b"ICC_PROFILE\0";
+/// Profile name written in PNG `iCCP` chunks.
+const PNG_ICC_NAME: &[u8] = b"ICC profile";
+/// Largest payload of a JPEG APP segment.
+const JPEG_SEGMENT_LIMIT: usize = 65533;
+
+/// Name of the kept XMP packet in `kept_metadata`.
+const XMP_FIELD: &str = "XMP";
+
+const TIFF_XMP: Tag = Tag(Context::Tiff, 700);
+const TIFF_ICC_PROFILE: Tag = Tag(Context::Tiff, 34675);
+
+/// Tags that describe how the source stored its pixels or point at other data. They never
+/// describe the output, so they are neither reported nor copied. Orientation is applied to the
+/// pixels on decode, so it is dropped as well.
+const STRUCTURAL_TAGS: &[Tag] = &[
+    Tag::ImageWidth,
+    Tag::ImageLength,
+    Tag::BitsPerSample,
+    Tag::Compression,
+    Tag::PhotometricInterpretation,
+    Tag::StripOffsets,
+    Tag::SamplesPerPixel,
+    Tag::RowsPerStrip,
+    Tag::StripByteCounts,
+    Tag::PlanarConfiguration,
+    Tag(Context::Tiff, 317), // Predictor
+    Tag(Context::Tiff, 322), // TileWidth
+    Tag(Context::Tiff, 323), // TileLength
+    Tag::TileOffsets,
+    Tag::TileByteCounts,
+    Tag(Context::Tiff, 338), // ExtraSamples
+    Tag(Context::Tiff, 339), // SampleFormat
+    TIFF_ICC_PROFILE,
+    TIFF_XMP,
+    Tag::JPEGInterchangeFormat,
+    Tag::JPEGInterchangeFormatLength,
+    Tag::ExifIFDPointer,
+    Tag::GPSInfoIFDPointer,
+    Tag::InteropIFDPointer,
+    Tag::Orientation,
+    Tag::PixelXDimension,
+    Tag::PixelYDimension,
+];
+
+/// An EXIF field of the main image.
+#[cfg(feature = "host")]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MetadataField {
+    /// `<group>.<tag>`, such as `Image.Make` or `GPS.GPSLatitude`. The groups are `Image`,
+    /// `Exif`, `GPS` and `Interop`; unnamed tags use their hex number.
+    pub name: String,
+    pub value: String,
+}
+
+/// Metadata found in an encoded image.
+#[cfg(feature = "host")]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ImageMetadata {
+    pub exif: Vec<MetadataField>,
+    /// EXIF orientation from 1 (upright) to 8. Decoding applies it, so layers are always upright.
+    pub orientation: u16,
+    pub xmp: Option<String>,
+    /// Description of the embedded ICC profile, such as `Display P3`. Decoding converts the
+    /// pixels of RGB profiles other than sRGB to sRGB.
+    pub color_profile: Option<String>,
+}
+
+/// Which metadata of the source image is written to an output. Only JPEG, PNG and WebP outputs
+/// carry metadata; other formats are always written without.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub enum MetadataPolicy {
+    /// Write no metadata, as outputs always were before metadata support.
+    #[default]
+    Strip,
+    /// Copy the EXIF fields and the XMP packet.
+    Preserve,
+    /// Copy the EXIF fields except those listed, by tag (`GPSLatitude`), by full name
+    /// (`GPS.GPSLatitude`) or by group (`GPS`). The XMP packet is dropped, since it can repeat
+    /// any EXIF field.
+    Redact { fields: Vec<String> },
+}
+
+impl MetadataPolicy {
+    fn keeps(&self, tag: Tag) -> bool {
+        match self {
+            MetadataPolicy::Strip => false,
+            MetadataPolicy::Preserve => true,
+            MetadataPolicy::Redact { fields } => {
+                let (group, tag_name, name) = (group_name(tag), tag.to_string(), field_name(tag));
+                !fields.iter().any(|field| {
+                    field.eq_ignore_ascii_case(group) || *field == tag_name || *field == name
+                })
+            },
+        }
+    }
+}
+
+fn group_name(tag: Tag) -> &'static str {
+    match tag.context() {
+        Context::Tiff => "Image",
+        Context::Exif => "Exif",
+        Context::Gps => "GPS",
+        Context::Interop => "Interop",
+        _ => "Unknown",
+    }
+}
+
+fn field_name(tag: Tag) -> String {
+    match tag.description() {
+        Some(_) => format!("{}.{}", group_name(tag), tag),
+        None => format!("{}.0x{:04x}", group_name(tag), tag.number()),
+    }
+}
+
+fn read_exif(image_data: &[u8]) -> Option<Exif> {
+    Reader::new().read_from_container(&mut Cursor::new(image_data)).ok()
+}
+
+/// Fields of the main image that describe its content rather than its storage.
+fn content_fields(exif: &Exif) -> impl Iterator<Item = &Field> {
+    exif.fields().filter(|field| field.ifd_num == In::PRIMARY && !STRUCTURAL_TAGS.contains(&field.tag))
+}
+
+/// Segments of a JPEG up to the start of the scan, as `(marker, payload)`.
+fn jpeg_segments(data: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut segments = Vec::new();
+    let mut offset = 2;
+    while offset + 4 <= data.len() && data[offset] == 0xFF {
+        let marker = data[offset + 1];
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        let length = u16::from_be_bytes([data[offset + 2], data[offset + 3]]) as usize;
+        if length < 2 || offset + 2 + length > data.len() {
+            break;
+        }
+        segments.push((marker, &data[offset + 4..offset + 2 + length]));
+        offset += 2 + length;
+    }
+    segments
+}
+
+/// Chunks of a PNG, as `(type, data)`.
+pub(crate) fn png_chunks(data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut chunks = Vec::new();
+    let mut offset = 8;
+    while offset + 12 <= data.len() {
+        let length = u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+        let kind = data[offset + 4..offset + 8].try_into().unwrap();
+        let Some(end) = (offset + 8).checked_add(length).filter(|end| end + 4 <= data.len()) else {
+            break;
+        };
+        chunks.push((kind, &data[offset + 8..end]));
+        offset = end + 4;
+    }
+    chunks
+}
+
+/// Chunks of a RIFF (WebP) file, as `(id, data)`.
+pub(crate) fn riff_chunks(data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut chunks = Vec::new();
+    let mut offset = 12;
+    while offset + 8 <= data.len() {
+        let id = data[offset..offset + 4].try_into().unwrap();
+        let length = u32::from_le_bytes(data[offset + 4..offset + 8].try_into().unwrap()) as usize;
+        let Some(end) = (offset + 8).checked_add(length).filter(|end| *end <= data.len()) else {
+            break;
+        };
+        chunks.push((id, &data[offset + 8..end]));
+        offset = end + (length & 1);
+    }
+    chunks
+}
+
+/// Reads an uncompressed PNG `iTXt` chunk holding XMP.
+fn png_xmp(data: &[u8]) -> Option<&[u8]> {
+    let rest = data.strip_prefix(PNG_XMP_KEYWORD)?.strip_prefix(b"\0")?;
+    let (&compressed, rest) = rest.split_first()?;
+    if compressed != 0 {
+        return None;
+    }
+    let rest = rest.get(1..)?;
+    let language_end = rest.iter().position(|&b| b == 0)?;
+    let rest = &rest[language_end + 1..];
+    let keyword_end = rest.iter().position(|&b| b == 0)?;
+    Some(&rest[keyword_end + 1..])
+}
+
+fn read_xmp(image_data: &[u8], format: ImageFormat, exif: Option<&Exif>) -> Option<Vec<u8>> {
+    match format {
+        ImageFormat::Jpeg => jpeg_segments(image_data).into_iter()
+            .find_map(|(marker, payload)| (marker == 0xE1).then(|| payload.strip_prefix(JPEG_XMP_PREFIX)).flatten())
+            .map(<[u8]>::to_vec),
+        ImageFormat::Png => png_chunks(image_data).into_iter()
+            .find_map(|(kind, data)| (&kind == b"iTXt").then(|| png_xmp(data)).flatten())
+            .map(<[u8]>::to_vec),
+        ImageFormat::WebP => riff_chunks(image_data).into_iter()
+            .find(|(id, _)| id == b"XMP ")
+            .map(|(_, data)| data.to_vec()),
+        ImageFormat::Tiff => tiff_bytes(exif?, TIFF_XMP),
+        _ => None,
+    }
+}
+
+fn tiff_bytes(exif: &Exif, tag: Tag) -> Option<Vec<u8>> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        exif::Value::Byte(bytes) | exif::Value::Undefined(bytes, _) => Some(bytes.clone()),
+        _ => None,
+    }
+}
+
+/// Reads a PNG `iCCP` chunk: name, null separator, compression method and zlib data.
+fn png_icc(data: &[u8]) -> Option<Vec<u8>> {
+    let name_end = data.iter().position(|&b| b == 0)?;
+    let compressed = data.get(name_end + 2..)?;
+    let mut icc = Vec::new();
+    ZlibDecoder::new(compressed).read_to_end(&mut icc).ok()?;
+    Some(icc)
+}
+
+/// Reads the ICC profile embedded in an encoded image. JPEG profiles may be split over several
+/// `APP2` segments, which are joined in sequence order.
+pub fn read_icc_profile(image_data: &[u8]) -> Option<Vec<u8>> {
+    match sniff_format(image_data)? {
+        ImageFormat::Jpeg => {
+            let mut chunks: Vec<(u8, &[u8])> = jpeg_segments(image_data).into_iter()
+                .filter(|(marker, _)| *marker == 0xE2)
+                .filter_map(|(_, payload)| payload.strip_prefix(JPEG_ICC_PREFIX))
+                .filter_map(|chunk| Some((*chunk.first()?, chunk.get(2..)?)))
+                .collect();
+            chunks.sort_by_key(|(sequence, _)| *sequence);
+            (!chunks.is_empty()).then(|| chunks.into_iter().flat_map(|(_, data)| data.iter().copied()).collect())
+        },
+        ImageFormat::Png => png_chunks(image_data).into_iter()
+            .find(|(kind, _)| kind == b"iCCP")
+            .and_then(|(_, data)| png_icc(data)),
+        ImageFormat::WebP => riff_chunks(image_data).into_iter()
+            .find(|(id, _)| id == b"ICCP")
+            .map(|(_, data)| data.to_vec()),
+        ImageFormat::Tiff => tiff_bytes(&read_exif(image_data)?, TIFF_ICC_PROFILE),
+        _ => None,
+    }
+}
+
+/// Reads the EXIF fields, orientation, XMP packet and color profile of an encoded image.
+#[cfg(feature = "host")]
+pub fn read_metadata(image_data: &[u8]) -> EditorResult<ImageMetadata> {
+    let format = sniff_format(image_data)
+        .ok_or_else(|| EditorError::UnsupportedFormat("unrecognized image data".to_string()))?;
+    let exif = read_exif(image_data);
+    let fields = exif.iter()
+        .flat_map(|exif| {
+            exif.fields()
+                .filter(|field| field.ifd_num == In::PRIMARY)
+                .filter(|field| !matches!(field.tag, Tag::ExifIFDPointer | Tag::GPSInfoIFDPointer | Tag::InteropIFDPointer))
+                .map(move |field| MetadataField {
+                    name: field_name(field.tag),
+                    value: field.display_value().with_unit(exif).to_string(),
+                })
+        })
+        .collect();
+    Ok(ImageMetadata {
+        exif: fields,
+        orientation: orientation(image_data),
+        xmp: read_xmp(image_data, format, exif.as_ref()).map(|xmp| String::from_utf8_lossy(&xmp).into_owned()),
+        color_profile: read_icc_profile(image_data).map(|icc| profile_description(&icc).unwrap_or_default()),
+    })
+}
+
+/// EXIF orientation of an encoded image, 1 when it has none.
+pub(crate) fn orientation(image_data: &[u8]) -> u16 {
+    read_exif(image_data)
+        .and_then(|exif| exif.get_field(Tag::Orientation, In::PRIMARY)?.value.get_uint(0))
+        .filter(|orientation| (1..=8).contains(orientation))
+        .map_or(1, |orientation| orientation as u16)
+}
+
+/// Turns an image stored with EXIF `orientation` upright.
+pub(crate) fn apply_orientation(image: DynamicImage, orientation: u16) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// Metadata to write into an output, already filtered by a policy.
+#[derive(Default)]
+struct OutputMetadata {
+    /// A TIFF structure, as stored in JPEG `APP1`, PNG `eXIf` and WebP `EXIF`.
+    exif: Option<Vec<u8>>,
+    xmp: Option<Vec<u8>>,
+    fields: Vec<String>,
+}
+
+fn output_metadata(policy: &MetadataPolicy, source: &[u8], format: ImageFormat) -> EditorResult<OutputMetadata> {
+    let carries_metadata = matches!(format, ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP);
+    let source_format = match sniff_format(source) {
+        Some(source_format) if carries_metadata && *policy != MetadataPolicy::Strip => source_format,
+        _ => return Ok(OutputMetadata::default()),
+    };
+
+    let exif = read_exif(source);
+    let kept: Vec<&Field> = exif.iter()
+        .flat_map(content_fields)
+        .filter(|field| policy.keeps(field.tag))
+        .collect();
+    let mut fields: Vec<String> = kept.iter().map(|field| field_name(field.tag)).collect();
+
+    let mut exif_data = None;
+    if let (Some(exif), false) = (&exif, kept.is_empty()) {
+        let mut writer = exif::experimental::Writer::new();
+        for field in &kept {
+            writer.push_field(field);
+        }
+        let mut buffer = Cursor::new(Vec::new());
+        writer.write(&mut buffer, exif.little_endian())
+            .map_err(|e| EditorError::Encode(format!("failed to write EXIF: {}", e)))?;
+        exif_data = Some(buffer.into_inner());
+    }
+
+    let xmp = match policy {
+        MetadataPolicy::Preserve => read_xmp(source, source_format, exif.as_ref()),
+        _ => None,
+    };
+    if xmp.is_some() {
+        fields.push(XMP_FIELD.to_string());
+    }
+    Ok(OutputMetadata { exif: exif_data, xmp, fields })
+}
+
+fn jpeg_segment(marker: u8, payload: &[&[u8]]) -> EditorResult<Vec<u8>> {
+    let length: usize = payload.iter().map(|part| part.len()).sum();
+    if length > JPEG_SEGMENT_LIMIT {
+        return Err(EditorError::Encode("metadata is too large for a JPEG segment".to_string()));
+    }
+    let mut segment = vec![0xFF, marker];
+    segment.extend_from_slice(&(length as u16 + 2).to_be_bytes());
+    for part in payload {
+        segment.extend_from_slice(part);
+    }
+    Ok(segment)
+}
+
+/// Inserts APP1 metadata and APP2 profile segments after the JFIF header.
+fn embed_jpeg(encoded: &[u8], metadata: &OutputMetadata, icc: Option<&[u8]>) -> EditorResult<Vec<u8>> {
+    let mut insert_at = 2;
+    if let Some((0xE0, payload)) = jpeg_segments(encoded).first() {
+        insert_at += 4 + payload.len();
+    }
+    let mut output = encoded[..insert_at].to_vec();
+    if let Some(exif) = &metadata.exif {
+        output.extend(jpeg_segment(0xE1, &[JPEG_EXIF_PREFIX, exif])?);
+    }
+    if let Some(xmp) = &metadata.xmp {
+        output.extend(jpeg_segment(0xE1, &[JPEG_XMP_PREFIX, xmp])?);
+    }
+    if let Some(icc) = icc {
+        // Each chunk carries its 1-based sequence number and the chunk count
+        let chunks: Vec<&[u8]> = icc.chunks(JPEG_SEGMENT_LIMIT - JPEG_ICC_PREFIX.len() - 2).collect();
+        let count = u8::try_from(chunks.len())
+            .map_err(|_| EditorError::Encode("the ICC profile is too large for a JPEG".to_string()))?;
+        for (index, chunk) in chunks.iter().enumerate() {
+            output.extend(jpeg_segment(0xE2, &[JPEG_ICC_PREFIX, &[index as u8 + 1, count], chunk])?);
+        }
+    }
+    output.extend_from_slice(&encoded[insert_at..]);
+    Ok(output)
+}
+
+fn png_chunk(output: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    output.extend_from_slice(kind);
+    output.extend_from_slice(data);
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    output.extend_from_slice(&crc.finalize().to_be_bytes());
+}
+
+/// Inserts `iCCP`, `eXIf` and `iTXt` chunks right after `IHDR`.
+fn embed_png(encoded: &[u8], metadata: &OutputMetadata, icc: Option<&[u8]>) -> EditorResult<Vec<u8>> {
+    // Signature, then IHDR: length, type, 13 bytes of data and the CRC
+    let insert_at = 8 + 12 + 13;
+    let mut output = encoded[..insert_at].to_vec();
+    if let Some(icc) = icc {
+        // Name, null separator and compression method 0, then the deflated profile
+        let mut encoder = ZlibEncoder::new([PNG_ICC_NAME, &[0, 0]].concat(), Compression::default());
+        encoder.write_all(icc).map_err(|e| EditorError::Encode(e.to_string()))?;
+        let data = encoder.finish().map_err(|e| EditorError::Encode(e.to_string()))?;
+        png_chunk(&mut output, b"iCCP", &data);
+    }
+    if let Some(exif) = &metadata.exif {
+        png_chunk(&mut output, b"eXIf", exif);
+    }
+    if let Some(xmp) = &metadata.xmp {
+        let mut data = PNG_XMP_KEYWORD.to_vec();
+        // Null separator, uncompressed, no language and no translated keyword
+        data.extend_from_slice(&[0, 0, 0, 0, 0]);
+        data.extend_from_slice(xmp);
+        png_chunk(&mut output, b"iTXt", &data);
+    }
+    output.extend_from_slice(&encoded[insert_at..]);
+    Ok(output)
+}
+
+fn riff_chunk(output: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    output.extend_from_slice(id);
+    output.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    output.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        output.push(0);
+    }
+}
+
+/// Rewrites a simple lossless WebP into the extended layout, which can hold metadata.
+fn embed_webp(encoded: &[u8], metadata: &OutputMetadata, icc: Option<&[u8]>) -> EditorResult<Vec<u8>> {
+    let chunks = riff_chunks(encoded);
+    let bitstream = match chunks.as_slice() {
+        [(id, data)] if id == b"VP8L" && data.len() >= 5 && data[0] == 0x2F => *data,
+        _ => return Err(EditorError::Encode("only simple lossless WebP files can hold metadata".to_string())),
+    };
+    // VP8L header: 14 bits width - 1, 14 bits height - 1, then the alpha hint
+    let header = u32::from_le_bytes(bitstream[1..5].try_into().unwrap());
+    let (width, height) = ((header & 0x3FFF) + 1, ((header >> 14) & 0x3FFF) + 1);
+    let has_alpha = header & (1 << 28) != 0;
+
+    let mut flags = 0u8;
+    if icc.is_some() {
+        flags |= 0x20;
+    }
+    if has_alpha {
+        flags |= 0x10;
+    }
+    if metadata.exif.is_some() {
+        flags |= 0x08;
+    }
+    if metadata.xmp.is_some() {
+        flags |= 0x04;
+    }
+    let mut vp8x = vec![flags, 0, 0, 0];
+    vp8x.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+    vp8x.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+
+    let mut body = b"WEBP".to_vec();
+    riff_chunk(&mut body, b"VP8X", &vp8x);
+    if let Some(icc) = icc {
+        riff_chunk(&mut body, b"ICCP", icc);
+    }
+    riff_chunk(&mut body, b"VP8L", bitstream);
+    if let Some(exif) = &metadata.exif {
+        riff_chunk(&mut body, b"EXIF", exif);
+    }
+    if let Some(xmp) = &metadata.xmp {
+        riff_chunk(&mut body, b"XMP ", xmp);
+    }
+    let mut output = b"RIFF".to_vec();
+    output.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    output.extend(body);
+    Ok(output)
+}
+
+/// Adds the metadata of `source` kept by `policy`, and the ICC profile `icc`, to an image
+/// freshly encoded as `format`.
+pub(crate) fn embed_metadata(encoded: Vec<u8>, format: ImageFormat, policy: &MetadataPolicy, icc: Option<&[u8]>, source: &[u8]) -> EditorResult<Vec<u8>> {
+    let metadata = output_metadata(policy, source, format)?;
+    if metadata.exif.is_none() && metadata.xmp.is_none() && icc.is_none() {
+        return Ok(encoded);
+    }
+    match format {
+        ImageFormat::Jpeg => embed_jpeg(&encoded, &metadata, icc),
+        ImageFormat::Png => embed_png(&encoded, &metadata, icc),
+        ImageFormat::WebP => embed_webp(&encoded, &metadata, icc),
+        _ => Ok(encoded),
+    }
+}
+
+/// Names of the metadata fields `render_input` copies from the source image into the proven
+/// output, as `<group>.<tag>` plus `XMP` for the XMP packet. The program commits this list, so
+/// a proof attests for example that no `GPS` fields were published. Animations carry none.
+pub fn kept_metadata(input: &ImageInput) -> EditorResult<Vec<String>> {
+    if input.animation.is_some() {
+        return Ok(Vec::new());
+    }
+    let policy = &input.encode_options.metadata;
+    Ok(output_metadata(policy, &input.image_data, input.output_format.into())?.fields)
+}
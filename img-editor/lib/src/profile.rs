@@ -0,0 +1,20 @@
+//! Markers for SP1's cycle tracker. Inside the zkVM each tracked stage prints the report markers
+//! the executor turns into `ExecutionReport::cycle_tracker` entries, summed per name, so a stage
+//! run once per band or frame reports its total. Elsewhere tracking does nothing.
+//!
+//! Stages are named `decode`, `transform_{index}` for the input's transformations,
+//! `frame_edit_{index}` for animation frame edits, `layer_{index}` and `composite` for extra
+//! layers and `encode`; the program adds its own hashing and signature stages.
+use std::fmt::Arguments;
+
+/// Runs `stage`, counting the cycles it takes under `name` when run in the zkVM.
+pub fn track<T>(name: Arguments, stage: impl FnOnce() -> T) -> T {
+    #[cfg(target_os = "zkvm")]
+    println!("cycle-tracker-report-start: {name}");
+    let result = stage();
+    #[cfg(target_os = "zkvm")]
+    println!("cycle-tracker-report-end: {name}");
+    #[cfg(not(target_os = "zkvm"))]
+    let _ = name;
+    result
+}
@@ -1,43 +1,826 @@
-use image::ImageFormat;
-use crate::{Layer, Transformation};
+use image::{DynamicImage, ImageFormat, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use crate::animation::render_animation;
+use crate::encode::encode_with_metadata;
+use crate::fixed::quantize;
+use crate::hash::{sha256, to_hex};
+use crate::history::{History, Operation, Snapshot};
+use crate::profile::track;
+use crate::text::{mul_coverage, to_coverage, FULL_COVERAGE};
+use crate::tiles::render_tiled;
+use crate::{
+    format_name, parse_format, text, BrushStroke, CanvasInput, Edit, EditId, EditorError, EditorResult, EncodeOptions, FontRegistry,
+    HorizontalAlign, ImageInput, Layer, LayerId, LayerInput, LayerMask, OutputFormat, Selection, TextBounds,
+    TextOverlayParameters, Transformation, VerticalAlign,
+};
 
+/// Summary of a layer for UIs that list the layer stack.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayerInfo {
+    pub id: LayerId,
+    pub name: String,
+    pub visible: bool,
+    pub locked: bool,
+    pub width: u32,
+    pub height: u32,
+    pub x: i32,
+    pub y: i32,
+    pub opacity: f32,
+    /// Position in the stack, 0 being the bottom.
+    pub z_index: usize,
+    pub has_mask: bool,
+    pub clipped: bool,
+    /// Format of the image the layer was created from, such as `jpeg` or `tiff`.
+    pub source_format: Option<String>,
+}
+
+/// PNG when no format is given.
+fn output_format(format: Option<&str>) -> EditorResult<ImageFormat> {
+    format.map_or(Ok(ImageFormat::Png), parse_format)
+}
+
+/// Flattens the visible layers onto a transparent `width` x `height` canvas, bottom first,
+/// honouring each layer's position, opacity, mask and clipping.
+pub(crate) fn composite(layers: &[Layer], width: u32, height: u32) -> RgbaImage {
+    let mut canvas = RgbaImage::new(width, height);
+    // Coverage of the current clipping base, filled only when a clipped layer follows it
+    let mut base_alpha: Vec<u32> = Vec::new();
+    let mut has_base = false;
+
+    for (index, layer) in layers.iter().enumerate() {
+        let clipped = layer.clipped && has_base;
+        let is_base = !clipped && layers.get(index + 1).map_or(false, |next| next.clipped);
+        has_base = true;
+        if is_base {
+            base_alpha.clear();
+            base_alpha.resize(width as usize * height as usize, 0);
+        }
+        // A hidden base leaves its coverage empty, which hides the layers clipped to it
+        if !layer.visible {
+            continue;
+        }
+
+        let pixels = layer.image.to_rgba8();
+        let opacity = quantize(layer.opacity, 16).clamp(0, FULL_COVERAGE as i64) as u32;
+        let left = layer.x.max(0);
+        let top = layer.y.max(0);
+        let right = (layer.x as i64 + pixels.width() as i64).min(width as i64) as i32;
+        let bottom = (layer.y as i64 + pixels.height() as i64).min(height as i64) as i32;
+        for y in top..bottom {
+            for x in left..right {
+                let (layer_x, layer_y) = ((x - layer.x) as u32, (y - layer.y) as u32);
+                let source = *pixels.get_pixel(layer_x, layer_y);
+                let mut coverage = layer.mask.as_ref().map_or(FULL_COVERAGE, |mask| mask.alpha(layer_x, layer_y));
+                let canvas_index = y as usize * width as usize + x as usize;
+                if is_base {
+                    base_alpha[canvas_index] = mul_coverage(to_coverage(source[3]), coverage);
+                }
+                if clipped {
+                    coverage = mul_coverage(coverage, base_alpha[canvas_index]);
+                }
+                text::blend(canvas.get_pixel_mut(x as u32, y as u32), source, mul_coverage(coverage, opacity));
+            }
+        }
+    }
+    canvas
+}
+
+impl LayerInput {
+    fn to_layer(&self, fonts: &FontRegistry) -> EditorResult<Layer> {
+        let mut layer = Layer::new(&self.image_data)?;
+        for transformation in &self.transformations {
+            layer.transform(transformation, fonts)?;
+        }
+        if let Some(mask) = &self.mask {
+            mask.check_size(layer.image.width(), layer.image.height())?;
+        }
+        if self.opacity.is_nan() {
+            return Err(EditorError::InvalidArgument("opacity must be a number".to_string()));
+        }
+        layer.x = self.x;
+        layer.y = self.y;
+        layer.opacity = self.opacity.clamp(0.0, 1.0);
+        layer.mask = self.mask.clone();
+        layer.clipped = self.clipped;
+        Ok(layer)
+    }
+}
+
+/// Renders a proof input: the base image with its transformations, then any extra layers
+/// composited on the input's canvas or else a canvas the size of the transformed base, encoded as the input's
+/// `output_format` with its `encode_options`. Inputs without extra layers encode the base layer
+/// directly, exactly as single-layer proofs always have. Inputs with `animation` render every
+/// frame, and inputs with `tile_rows` are rendered by `render_tiled`.
+pub fn render_input(input: &ImageInput, fonts: &FontRegistry) -> EditorResult<Vec<u8>> {
+    if let Some(animation) = &input.animation {
+        return render_animation(input, animation, fonts);
+    }
+    if input.tile_rows.is_some() {
+        let mut bytes = Vec::new();
+        render_tiled(input, fonts, &mut bytes)?;
+        return Ok(bytes);
+    }
+    let mut base = track(format_args!("decode"), || Layer::new(&input.image_data))?;
+    for (index, transformation) in input.transformations.iter().enumerate() {
+        track(format_args!("transform_{index}"), || base.transform(transformation, fonts))?;
+    }
+    if input.layers.is_empty() && input.canvas.is_none() {
+        return track(format_args!("encode"), || base.to_bytes(input.output_format.into(), &input.encode_options));
+    }
+
+    let (width, height) = match input.canvas {
+        Some(canvas) => {
+            if canvas.width == 0 || canvas.height == 0 {
+                return Err(EditorError::InvalidArgument(format!(
+                    "canvas size {}x{} must not be empty",
+                    canvas.width, canvas.height
+                )));
+            }
+            base.x = canvas.x;
+            base.y = canvas.y;
+            (canvas.width, canvas.height)
+        },
+        None => (base.image.width(), base.image.height()),
+    };
+    let mut layers = vec![base];
+    for (index, layer) in input.layers.iter().enumerate() {
+        layers.push(track(format_args!("layer_{index}"), || layer.to_layer(fonts))?);
+    }
+    let canvas = track(format_args!("composite"), || DynamicImage::ImageRgba8(composite(&layers, width, height)));
+    track(format_args!("encode"), || {
+        encode_with_metadata(&canvas, input.output_format.into(), &input.encode_options, &input.image_data)
+    })
+}
+
+/// Checks image bytes rendered on the host against the `transformed_image_hash` a proof of the
+/// same input committed, so an image is never handed out with a proof that does not cover it.
+pub fn check_rendered_image(image: &[u8], committed_hash: &[u8; 32]) -> EditorResult<()> {
+    let hash = sha256(image);
+    if hash != *committed_hash {
+        return Err(EditorError::ImageHashMismatch { expected: to_hex(committed_hash), actual: to_hex(&hash) });
+    }
+    Ok(())
+}
+
+fn edit_index(edits: &[Edit], id: EditId) -> EditorResult<usize> {
+    edits.iter()
+        .position(|edit| edit.id == id)
+        .ok_or(EditorError::EditNotFound(id))
+}
+
+/// A stack of layers, bottom first, placed on a fixed-size canvas. Layers may be any size and
+/// are clipped to the canvas when composited.
+///
+/// Every edit is recorded in a journal, so it can be undone and redone. Fonts are resources
+/// rather than edits and are not journaled; removing a font that an undone text overlay used
+/// makes redoing it fail.
 pub struct ImageProject {
     pub(crate) layers: Vec<Layer>,
+    pub(crate) fonts: FontRegistry,
+    pub(crate) next_layer_id: LayerId,
+    pub(crate) canvas_width: u32,
+    pub(crate) canvas_height: u32,
+    history: History,
 }
 
 impl ImageProject {
+    /// Creates a project whose canvas takes the size of the first layer added.
     pub fn new() -> Self {
-        Self { layers: Vec::new() }
+        let mut project = Self {
+            layers: Vec::new(),
+            fonts: FontRegistry::new(),
+            next_layer_id: 0,
+            canvas_width: 0,
+            canvas_height: 0,
+            history: History::default(),
+        };
+        project.clear_history();
+        project
+    }
+
+    pub fn with_canvas(width: u32, height: u32) -> EditorResult<Self> {
+        let mut project = Self::new();
+        project.set_canvas_size(width, height)?;
+        project.clear_history();
+        Ok(project)
+    }
+
+    pub fn canvas_size(&self) -> (u32, u32) {
+        (self.canvas_width, self.canvas_height)
     }
 
-    pub fn add_layer(&mut self, image_data: &[u8]) -> Result<(), String> {
-        let layer = Layer::new(image_data)?;
+    /// Resizes the canvas without moving any layer; content outside the new bounds is kept but
+    /// not composited.
+    pub fn set_canvas_size(&mut self, width: u32, height: u32) -> EditorResult<()> {
+        self.perform(Operation::SetCanvasSize { width, height })
+    }
+
+    fn next_id(&mut self) -> LayerId {
+        let id = self.next_layer_id;
+        self.next_layer_id += 1;
+        id
+    }
+
+    fn push_layer(&mut self, mut layer: Layer) {
+        let id = self.next_id();
+        layer.id = id;
+        if layer.name.is_empty() {
+            layer.name = format!("Layer {}", id + 1);
+        }
+        if self.canvas_width == 0 || self.canvas_height == 0 {
+            self.canvas_width = layer.image.width();
+            self.canvas_height = layer.image.height();
+        }
         self.layers.push(layer);
+    }
+
+    fn index_of(&self, id: LayerId) -> EditorResult<usize> {
+        self.layers.iter()
+            .position(|layer| layer.id == id)
+            .ok_or(EditorError::LayerNotFound(id))
+    }
+
+    pub fn layer(&self, id: LayerId) -> EditorResult<&Layer> {
+        self.index_of(id).map(|index| &self.layers[index])
+    }
+
+    fn layer_mut(&mut self, id: LayerId) -> EditorResult<&mut Layer> {
+        let index = self.index_of(id)?;
+        Ok(&mut self.layers[index])
+    }
+
+    /// Like `layer_mut`, but refuses locked layers.
+    fn unlocked_layer_mut(&mut self, id: LayerId) -> EditorResult<&mut Layer> {
+        let layer = self.layer_mut(id)?;
+        if layer.locked {
+            return Err(EditorError::LayerLocked(id));
+        }
+        Ok(layer)
+    }
+
+    fn layer_mask_mut(&mut self, id: LayerId) -> EditorResult<&mut LayerMask> {
+        self.unlocked_layer_mut(id)?
+            .mask
+            .as_mut()
+            .ok_or_else(|| EditorError::InvalidArgument(format!("layer {} has no mask", id)))
+    }
+
+    /// Changes a copy of the layer's edit stack and re-renders the layer from it.
+    fn edit_stack(&mut self, id: LayerId, change: impl FnOnce(&mut Vec<Edit>) -> EditorResult<()>) -> EditorResult<()> {
+        let index = self.index_of(id)?;
+        let layer = &mut self.layers[index];
+        if layer.locked {
+            return Err(EditorError::LayerLocked(id));
+        }
+        let mut edits = layer.edits.clone();
+        change(&mut edits)?;
+        layer.set_edits(edits, &self.fonts)
+    }
+
+    /// Applies an edit and records it in the journal, discarding anything that could be redone.
+    /// Failed edits leave the project and the journal untouched.
+    fn perform(&mut self, operation: Operation) -> EditorResult<()> {
+        self.apply(&operation)?;
+        if self.history.record(operation) {
+            let snapshot = self.snapshot();
+            self.history.push_keyframe(snapshot);
+        }
+        self.history.enforce_budget();
         Ok(())
     }
 
-    pub fn transform_layer(&mut self, index: usize, transformation: &Transformation) -> Result<(), String> {
-        let layer = self.layers.get_mut(index)
-            .ok_or_else(|| "Layer index out of bounds".to_string())?;
-        layer.apply_transformation(transformation.clone())
+    /// Applies an edit without journaling it. Every edit validates before mutating anything.
+    fn apply(&mut self, operation: &Operation) -> EditorResult<()> {
+        match operation {
+            Operation::AddLayer { layer } => {
+                self.push_layer(Layer::clone(layer));
+            },
+            Operation::AddEmptyLayer { width, height } => {
+                let empty_layer = Layer::new_empty(*width, *height)?;
+                self.push_layer(empty_layer);
+            },
+            Operation::TransformLayer { id, transformation } => {
+                let index = self.index_of(*id)?;
+                let layer = &mut self.layers[index];
+                if layer.locked {
+                    return Err(EditorError::LayerLocked(*id));
+                }
+                layer.push_edit(transformation.clone(), &self.fonts)?;
+            },
+            Operation::SetEditEnabled { id, edit, enabled } => {
+                self.edit_stack(*id, |edits| {
+                    let index = edit_index(edits, *edit)?;
+                    edits[index].enabled = *enabled;
+                    Ok(())
+                })?;
+            },
+            Operation::MoveEdit { id, edit, position } => {
+                self.edit_stack(*id, |edits| {
+                    let moved = edits.remove(edit_index(edits, *edit)?);
+                    let position = (*position).min(edits.len());
+                    edits.insert(position, moved);
+                    Ok(())
+                })?;
+            },
+            Operation::UpdateEdit { id, edit, transformation } => {
+                self.edit_stack(*id, |edits| {
+                    let index = edit_index(edits, *edit)?;
+                    edits[index].transformation = transformation.clone();
+                    Ok(())
+                })?;
+            },
+            Operation::RemoveEdit { id, edit } => {
+                self.edit_stack(*id, |edits| {
+                    edits.remove(edit_index(edits, *edit)?);
+                    Ok(())
+                })?;
+            },
+            Operation::RemoveLayer { id } => {
+                let index = self.index_of(*id)?;
+                if self.layers[index].locked {
+                    return Err(EditorError::LayerLocked(*id));
+                }
+                self.layers.remove(index);
+            },
+            Operation::MoveLayer { id, position } => {
+                let index = self.index_of(*id)?;
+                if self.layers[index].locked {
+                    return Err(EditorError::LayerLocked(*id));
+                }
+                let layer = self.layers.remove(index);
+                let position = (*position).min(self.layers.len());
+                self.layers.insert(position, layer);
+            },
+            Operation::ReorderLayers { ids } => {
+                let mut reordered = Vec::with_capacity(self.layers.len());
+                for &id in ids {
+                    reordered.push(self.index_of(id)?);
+                }
+                let mut seen = reordered.clone();
+                seen.sort_unstable();
+                seen.dedup();
+                if seen.len() != self.layers.len() || reordered.len() != self.layers.len() {
+                    return Err(EditorError::InvalidArgument(
+                        "layer order must list every layer exactly once".to_string(),
+                    ));
+                }
+                // Locked layers keep their place in the stack
+                let moved_locked = reordered.iter().enumerate()
+                    .find(|&(position, &index)| position != index && self.layers[index].locked);
+                if let Some((_, &index)) = moved_locked {
+                    return Err(EditorError::LayerLocked(self.layers[index].id));
+                }
+
+                let mut layers: Vec<Option<Layer>> = self.layers.drain(..).map(Some).collect();
+                self.layers = reordered.into_iter()
+                    .filter_map(|index| layers[index].take())
+                    .collect();
+            },
+            Operation::DuplicateLayer { id } => {
+                let index = self.index_of(*id)?;
+                let mut copy = self.layers[index].clone();
+                copy.name = format!("{} copy", copy.name);
+                copy.locked = false;
+                copy.id = self.next_id();
+                self.layers.insert(index + 1, copy);
+            },
+            Operation::RenameLayer { id, name } => {
+                self.layer_mut(*id)?.name = name.clone();
+            },
+            Operation::SetLayerVisible { id, visible } => {
+                self.layer_mut(*id)?.visible = *visible;
+            },
+            Operation::SetLayerLocked { id, locked } => {
+                self.layer_mut(*id)?.locked = *locked;
+            },
+            Operation::SetLayerOpacity { id, opacity } => {
+                if opacity.is_nan() {
+                    return Err(EditorError::InvalidArgument("opacity must be a number".to_string()));
+                }
+                self.unlocked_layer_mut(*id)?.opacity = opacity.clamp(0.0, 1.0);
+            },
+            Operation::SetLayerPosition { id, x, y } => {
+                let layer = self.unlocked_layer_mut(*id)?;
+                layer.x = *x;
+                layer.y = *y;
+            },
+            Operation::TranslateLayer { id, dx, dy } => {
+                let layer = self.unlocked_layer_mut(*id)?;
+                layer.x = layer.x.saturating_add(*dx);
+                layer.y = layer.y.saturating_add(*dy);
+            },
+            Operation::AlignLayer { id, horizontal, vertical } => {
+                let (canvas_width, canvas_height) = (self.canvas_width as i64, self.canvas_height as i64);
+                let layer = self.unlocked_layer_mut(*id)?;
+                let (width, height) = (layer.image.width() as i64, layer.image.height() as i64);
+                if let Some(horizontal) = horizontal {
+                    layer.x = match horizontal {
+                        HorizontalAlign::Left => 0,
+                        HorizontalAlign::Center => ((canvas_width - width) / 2) as i32,
+                        HorizontalAlign::Right => (canvas_width - width) as i32,
+                    };
+                }
+                if let Some(vertical) = vertical {
+                    layer.y = match vertical {
+                        VerticalAlign::Top => 0,
+                        VerticalAlign::Middle => ((canvas_height - height) / 2) as i32,
+                        VerticalAlign::Bottom => (canvas_height - height) as i32,
+                    };
+                }
+            },
+            Operation::SetLayerMask { id, mask } => {
+                let layer = self.unlocked_layer_mut(*id)?;
+                if let Some(mask) = mask {
+                    mask.check_size(layer.image.width(), layer.image.height())?;
+                }
+                layer.mask = mask.clone();
+            },
+            Operation::AddLayerMask { id, selection } => {
+                let layer = self.unlocked_layer_mut(*id)?;
+                let (width, height) = (layer.image.width(), layer.image.height());
+                let selection = selection.as_ref().unwrap_or(&Selection::All);
+                layer.mask = Some(LayerMask::from_selection(selection, width, height));
+            },
+            Operation::InvertLayerMask { id } => {
+                self.layer_mask_mut(*id)?.invert();
+            },
+            Operation::FillLayerMask { id, selection, value } => {
+                self.layer_mask_mut(*id)?.fill(selection, *value);
+            },
+            Operation::PaintLayerMask { id, stroke } => {
+                self.layer_mask_mut(*id)?.paint(stroke)?;
+            },
+            Operation::SetLayerClipped { id, clipped } => {
+                self.unlocked_layer_mut(*id)?.clipped = *clipped;
+            },
+            Operation::SetCanvasSize { width, height } => {
+                if *width == 0 || *height == 0 {
+                    return Err(EditorError::InvalidArgument(format!("canvas size {}x{} must not be empty", width, height)));
+                }
+                self.canvas_width = *width;
+                self.canvas_height = *height;
+            },
+        }
+        Ok(())
     }
 
-    pub fn get_layer(&self, index: usize, format: Option<String>) -> Result<Vec<u8>, String> {
-        let layer = self.layers.get(index)
-            .ok_or_else(|| "Layer index out of bounds".to_string())?;
-        
-        let format = match format.as_deref() {
-            Some("jpeg") => ImageFormat::Jpeg,
-            Some("webp") => ImageFormat::WebP,
-            _ => ImageFormat::Png
-        };
-        
-        layer.to_bytes(format, None)
+    /// Adds a layer decoded from `image_data` on top of the stack. Floating point images are
+    /// kept at 16 bits per channel, as described on `Layer::new`.
+    pub fn add_layer(&mut self, image_data: &[u8]) -> EditorResult<LayerId> {
+        let id = self.next_layer_id;
+        self.perform(Operation::AddLayer { layer: Arc::new(Layer::new(image_data)?) })?;
+        Ok(id)
+    }
+
+    pub fn add_empty_layer(&mut self, width: u32, height: u32) -> EditorResult<LayerId> {
+        let id = self.next_layer_id;
+        self.perform(Operation::AddEmptyLayer { width, height })?;
+        Ok(id)
+    }
+
+    /// Applies a transformation to a layer and appends it to the layer's edit stack.
+    pub fn transform_layer(&mut self, id: LayerId, transformation: &Transformation) -> EditorResult<EditId> {
+        self.perform(Operation::TransformLayer { id, transformation: transformation.clone() })?;
+        let layer = self.layer(id)?;
+        Ok(layer.edits[layer.edits.len() - 1].id)
+    }
+
+    /// Turns an edit on or off and re-renders the layer.
+    pub fn set_edit_enabled(&mut self, id: LayerId, edit: EditId, enabled: bool) -> EditorResult<()> {
+        self.perform(Operation::SetEditEnabled { id, edit, enabled })
+    }
+
+    /// Moves an edit to `position` in the layer's edit stack, where 0 is applied first.
+    pub fn move_edit(&mut self, id: LayerId, edit: EditId, position: usize) -> EditorResult<()> {
+        self.perform(Operation::MoveEdit { id, edit, position })
+    }
+
+    /// Replaces the transformation of an existing edit in place.
+    pub fn update_edit(&mut self, id: LayerId, edit: EditId, transformation: &Transformation) -> EditorResult<()> {
+        self.perform(Operation::UpdateEdit { id, edit, transformation: transformation.clone() })
+    }
+
+    pub fn remove_edit(&mut self, id: LayerId, edit: EditId) -> EditorResult<()> {
+        self.perform(Operation::RemoveEdit { id, edit })
+    }
+
+    /// The layers that show up in the composite, bottom first: visible layers, less those clipped
+    /// to a hidden base, which `composite` hides along with it.
+    fn shown_layers(&self) -> impl Iterator<Item = &Layer> {
+        let mut base_visible = false;
+        self.layers.iter().enumerate().filter(move |(index, layer)| {
+            let clipped = layer.clipped && *index > 0;
+            if !clipped {
+                base_visible = layer.visible;
+            }
+            layer.visible && base_visible
+        })
+        .map(|(_, layer)| layer)
+    }
+
+    /// Builds the proof input for the shown layers on the project's canvas, so the proven image
+    /// is the one `export(format, options)` writes. Only the formats of `OutputFormat` can be
+    /// proven; PNG when no format is given. The bottom shown layer is the base image and the layers
+    /// above it become `ImageInput::layers`, all at their positions on the canvas. Hidden layers
+    /// and the layers clipped to a hidden base are left out. Custom fonts are included so the
+    /// prover renders text identically.
+    pub fn proof_input(&self, format: Option<String>, options: &EncodeOptions) -> EditorResult<ImageInput> {
+        let output_format = format.as_deref().map_or(Ok(OutputFormat::Png), str::parse)?;
+        let mut visible = self.shown_layers();
+        let base = visible.next()
+            .ok_or_else(|| EditorError::InvalidArgument("the project has no visible layers".to_string()))?;
+        if base.mask.is_some() || base.opacity < 1.0 {
+            return Err(EditorError::InvalidArgument(
+                "the bottom layer cannot have a mask or reduced opacity in a proof".to_string(),
+            ));
+        }
+
+        let layers = visible
+            .map(|layer| LayerInput {
+                image_data: layer.image_data().to_vec(),
+                transformations: layer.transformations(),
+                x: layer.x,
+                y: layer.y,
+                opacity: layer.opacity,
+                mask: layer.mask.clone(),
+                clipped: layer.clipped,
+            })
+            .collect();
+
+        Ok(ImageInput {
+            image_data: base.image_data().to_vec(),
+            transformations: base.transformations(),
+            signature_data: None,
+            fonts: self.fonts.custom_fonts().cloned().collect(),
+            layers,
+            output_format,
+            animation: None,
+            tile_rows: None,
+            encode_options: options.clone(),
+            canvas: Some(CanvasInput { width: self.canvas_width, height: self.canvas_height, x: base.x, y: base.y }),
+        })
+    }
+
+    pub fn get_layer(&self, id: LayerId, format: Option<String>, options: &EncodeOptions) -> EditorResult<Vec<u8>> {
+        let layer = self.layer(id)?;
+        layer.to_bytes(output_format(format.as_deref())?, options)
+    }
+
+    /// EXIF fields, orientation and XMP of the image the layer was created from.
+    #[cfg(feature = "host")]
+    pub fn layer_metadata(&self, id: LayerId) -> EditorResult<crate::ImageMetadata> {
+        crate::read_metadata(self.layer(id)?.image_data())
+    }
+
+    /// Flattens every visible layer onto a transparent 8-bit canvas, bottom first.
+    pub fn composite(&self) -> RgbaImage {
+        composite(&self.layers, self.canvas_width, self.canvas_height)
+    }
+
+    /// Encodes the composited canvas. JPEG has no alpha channel, so transparent areas become
+    /// black unless `options.alpha` flattens them onto a background. Metadata kept by
+    /// `options.metadata` comes from the bottom shown layer.
+    pub fn export(&self, format: Option<String>, options: &EncodeOptions) -> EditorResult<Vec<u8>> {
+        let format = output_format(format.as_deref())?;
+        let source = self.shown_layers().next().map_or(&[][..], Layer::image_data);
+        encode_with_metadata(&DynamicImage::ImageRgba8(self.composite()), format, options, source)
     }
 
-    pub fn add_empty_layer(&mut self, width: u32, height: u32) -> Result<(), String> {
-        let empty_layer = Layer::new_empty(width, height)?;
-        self.layers.push(empty_layer);
+    pub fn remove_layer(&mut self, id: LayerId) -> EditorResult<()> {
+        self.perform(Operation::RemoveLayer { id })
+    }
+
+    /// Moves a layer to `position` in the stack (its z-order), where 0 is the bottom. Positions past the top
+    /// are clamped. Locked layers cannot be moved.
+    pub fn move_layer(&mut self, id: LayerId, position: usize) -> EditorResult<()> {
+        self.perform(Operation::MoveLayer { id, position })
+    }
+
+    /// Reorders the whole stack. `ids` must list every layer exactly once, bottom first, and keep
+    /// locked layers where they are.
+    pub fn reorder_layers(&mut self, ids: &[LayerId]) -> EditorResult<()> {
+        self.perform(Operation::ReorderLayers { ids: ids.to_vec() })
+    }
+
+    /// Copies a layer and places the copy directly above the original.
+    pub fn duplicate_layer(&mut self, id: LayerId) -> EditorResult<LayerId> {
+        let copy_id = self.next_layer_id;
+        self.perform(Operation::DuplicateLayer { id })?;
+        Ok(copy_id)
+    }
+
+    pub fn rename_layer(&mut self, id: LayerId, name: &str) -> EditorResult<()> {
+        self.perform(Operation::RenameLayer { id, name: name.to_string() })
+    }
+
+    pub fn set_layer_visible(&mut self, id: LayerId, visible: bool) -> EditorResult<()> {
+        self.perform(Operation::SetLayerVisible { id, visible })
+    }
+
+    /// Locks or unlocks a layer. Nothing that changes how a locked layer draws is allowed: its
+    /// edits, mask, position, opacity and clipping stay as they are, and it cannot be moved in
+    /// the stack or removed. Those fail with `LayerLocked`. Renaming it, hiding or showing it and
+    /// duplicating it still work, and the copy starts unlocked.
+    pub fn set_layer_locked(&mut self, id: LayerId, locked: bool) -> EditorResult<()> {
+        self.perform(Operation::SetLayerLocked { id, locked })
+    }
+
+    /// Sets the layer opacity; values are clamped to `0.0..=1.0`.
+    pub fn set_layer_opacity(&mut self, id: LayerId, opacity: f32) -> EditorResult<()> {
+        self.perform(Operation::SetLayerOpacity { id, opacity })
+    }
+
+    /// Places the layer's top-left corner at `(x, y)` on the canvas.
+    pub fn set_layer_position(&mut self, id: LayerId, x: i32, y: i32) -> EditorResult<()> {
+        self.perform(Operation::SetLayerPosition { id, x, y })
+    }
+
+    /// Moves the layer by `(dx, dy)` canvas pixels.
+    pub fn translate_layer(&mut self, id: LayerId, dx: i32, dy: i32) -> EditorResult<()> {
+        self.perform(Operation::TranslateLayer { id, dx, dy })
+    }
+
+    /// Aligns the layer to the canvas edges or centre. `None` leaves that axis untouched.
+    pub fn align_layer(&mut self, id: LayerId, horizontal: Option<HorizontalAlign>, vertical: Option<VerticalAlign>) -> EditorResult<()> {
+        self.perform(Operation::AlignLayer { id, horizontal, vertical })
+    }
+
+    /// Replaces the layer's mask; `None` removes it. The mask must match the layer size.
+    pub fn set_layer_mask(&mut self, id: LayerId, mask: Option<LayerMask>) -> EditorResult<()> {
+        self.perform(Operation::SetLayerMask { id, mask })
+    }
+
+    /// Adds a mask revealing `selection`, or the whole layer when no selection is given.
+    pub fn add_layer_mask(&mut self, id: LayerId, selection: Option<&Selection>) -> EditorResult<()> {
+        self.perform(Operation::AddLayerMask { id, selection: selection.cloned() })
+    }
+
+    pub fn invert_layer_mask(&mut self, id: LayerId) -> EditorResult<()> {
+        self.perform(Operation::InvertLayerMask { id })
+    }
+
+    pub fn fill_layer_mask(&mut self, id: LayerId, selection: &Selection, value: u8) -> EditorResult<()> {
+        self.perform(Operation::FillLayerMask { id, selection: selection.clone(), value })
+    }
+
+    pub fn paint_layer_mask(&mut self, id: LayerId, stroke: &BrushStroke) -> EditorResult<()> {
+        self.perform(Operation::PaintLayerMask { id, stroke: stroke.clone() })
+    }
+
+    /// Clips the layer to the alpha of the nearest unclipped layer below it. Clipping the bottom
+    /// layer has no effect.
+    pub fn set_layer_clipped(&mut self, id: LayerId, clipped: bool) -> EditorResult<()> {
+        self.perform(Operation::SetLayerClipped { id, clipped })
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        self.history.snapshot(&self.layers, self.next_layer_id, self.canvas_width, self.canvas_height)
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.layers = snapshot.layers.iter().map(|layer| Layer::clone(layer)).collect();
+        self.next_layer_id = snapshot.next_layer_id;
+        self.canvas_width = snapshot.canvas_width;
+        self.canvas_height = snapshot.canvas_height;
+    }
+
+    /// Brings the project to the state after the first `position` journal entries: restores the
+    /// nearest keyframe and replays the edits after it. If replaying fails the project is left
+    /// at that keyframe.
+    fn seek(&mut self, position: usize) -> EditorResult<()> {
+        let (keyframe, snapshot) = self.history.keyframe_before(position);
+        self.restore(snapshot.clone());
+        self.history.cursor = keyframe;
+
+        let history = std::mem::take(&mut self.history);
+        let replayed = history.entries[keyframe..position].iter()
+            .try_for_each(|operation| self.apply(operation));
+        self.history = history;
+        replayed?;
+        self.history.cursor = position;
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Reverts the last edit. Returns `false` when there is nothing to undo.
+    pub fn undo(&mut self) -> EditorResult<bool> {
+        if !self.can_undo() {
+            return Ok(false);
+        }
+        self.seek(self.history.cursor - 1)?;
+        Ok(true)
+    }
+
+    /// Reapplies the last undone edit. Returns `false` when there is nothing to redo.
+    pub fn redo(&mut self) -> EditorResult<bool> {
+        if !self.can_redo() {
+            return Ok(false);
+        }
+        let history = std::mem::take(&mut self.history);
+        let redone = self.apply(&history.entries[history.cursor]);
+        self.history = history;
+        redone?;
+        self.history.cursor += 1;
+        Ok(true)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.history.cursor > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.history.cursor < self.history.entries.len()
+    }
+
+    /// Names the current state so it can be restored later. Reusing a name moves the checkpoint.
+    pub fn checkpoint(&mut self, name: &str) {
+        self.history.checkpoints.insert(name.to_string(), self.history.cursor);
+    }
+
+    /// Returns to a named checkpoint. The edits after it stay available to `redo` until a new
+    /// edit is made.
+    pub fn restore_checkpoint(&mut self, name: &str) -> EditorResult<()> {
+        let position = *self.history.checkpoints.get(name)
+            .ok_or_else(|| EditorError::InvalidArgument(format!("unknown checkpoint '{}'", name)))?;
+        self.seek(position)
+    }
+
+    /// Checkpoints that can still be restored, oldest first.
+    pub fn checkpoint_names(&self) -> Vec<String> {
+        let mut checkpoints: Vec<(&String, &usize)> = self.history.checkpoints.iter().collect();
+        checkpoints.sort_by_key(|(_, position)| **position);
+        checkpoints.into_iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// Limits the memory held by the journal and its keyframes. The oldest history is dropped
+    /// first; the current state and the latest keyframe are always kept.
+    pub fn set_history_budget(&mut self, bytes: usize) {
+        self.history.budget = bytes;
+        self.history.enforce_budget();
+    }
+
+    /// Sets how many edits may be replayed before a new keyframe is taken. Lower values make
+    /// undo faster and use more memory.
+    pub fn set_keyframe_interval(&mut self, edits: usize) {
+        self.history.keyframe_interval = edits.max(1);
+    }
+
+    /// Forgets all history, making the current state the oldest one.
+    pub fn clear_history(&mut self) {
+        let snapshot = self.snapshot();
+        self.history.reset(snapshot);
+    }
+
+    /// Layer IDs, bottom first.
+    pub fn layer_ids(&self) -> Vec<LayerId> {
+        self.layers.iter().map(|layer| layer.id).collect()
+    }
+
+    /// The layer stack, bottom first.
+    pub fn layers(&self) -> Vec<LayerInfo> {
+        self.layers.iter()
+            .enumerate()
+            .map(|(z_index, layer)| LayerInfo {
+                id: layer.id,
+                name: layer.name.clone(),
+                visible: layer.visible,
+                locked: layer.locked,
+                width: layer.image.width(),
+                height: layer.image.height(),
+                x: layer.x,
+                y: layer.y,
+                opacity: layer.opacity,
+                z_index,
+                has_mask: layer.mask.is_some(),
+                clipped: layer.clipped,
+                source_format: layer.source_format().map(format_name).map(str::to_string),
+            })
+            .collect()
+    }
+
+    pub fn add_font(&mut self, name: &str, data: &[u8]) -> EditorResult<()> {
+        self.fonts.register(name, data.to_vec())
+    }
+
+    pub fn remove_font(&mut self, name: &str) -> bool {
+        self.fonts.remove(name)
+    }
+
+    pub fn font_names(&self) -> Vec<String> {
+        self.fonts.names()
+    }
+
+    pub fn fonts(&self) -> &FontRegistry {
+        &self.fonts
+    }
+
+    pub fn measure_text(&self, params: &TextOverlayParameters) -> EditorResult<TextBounds> {
+        text::measure_text(params, &self.fonts)
+    }
+}
+
+impl Default for ImageProject {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,196 @@
+use ciborium::Value;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use std::collections::{HashMap, HashSet};
+use crate::{Edit, EditId, EditorError, EditorResult, FontData, ImageProject, Layer, LayerId, LayerMask};
+
+/// Identifies project files, so other CBOR documents are rejected with a clear error.
+const FORMAT: &str = "img-editor-project";
+
+/// Version written by `ImageProject::save`. Bump it whenever the layout below changes and add
+/// a migration from the previous version to `MIGRATIONS`.
+pub const PROJECT_FORMAT_VERSION: u32 = 1;
+
+/// `MIGRATIONS[n - 1]` upgrades a version `n` document to version `n + 1`. Version 1 is the
+/// first project file layout.
+const MIGRATIONS: &[fn(Value) -> EditorResult<Value>] = &[];
+
+#[derive(Serialize, Deserialize)]
+struct ProjectFile {
+    format: String,
+    version: u32,
+    manifest: Manifest,
+    /// Original image bytes, shared by layers created from the same image.
+    sources: Vec<ByteBuf>,
+    /// Layers bottom first.
+    layers: Vec<LayerRecord>,
+    fonts: Vec<FontData>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    /// Zero when the canvas has not been sized yet.
+    canvas_width: u32,
+    canvas_height: u32,
+    next_layer_id: LayerId,
+    #[serde(default)]
+    generator: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LayerRecord {
+    id: LayerId,
+    name: String,
+    visible: bool,
+    locked: bool,
+    x: i32,
+    y: i32,
+    opacity: f32,
+    clipped: bool,
+    source: usize,
+    edits: Vec<Edit>,
+    next_edit_id: EditId,
+    mask: Option<LayerMask>,
+}
+
+fn invalid(message: impl std::fmt::Display) -> EditorError {
+    EditorError::InvalidProject(message.to_string())
+}
+
+fn field<'a>(map: &'a [(Value, Value)], name: &str) -> Option<&'a Value> {
+    map.iter()
+        .find(|(key, _)| key.as_text() == Some(name))
+        .map(|(_, value)| value)
+}
+
+fn document_version(document: &Value) -> EditorResult<u32> {
+    match document {
+        Value::Map(map) => {
+            if field(map, "format").and_then(Value::as_text) != Some(FORMAT) {
+                return Err(invalid("not an img-editor project file"));
+            }
+            field(map, "version")
+                .and_then(Value::as_integer)
+                .and_then(|version| u32::try_from(version).ok())
+                .filter(|version| *version >= 1)
+                .ok_or_else(|| invalid("the project file has no valid version"))
+        },
+        _ => Err(invalid("not an img-editor project file")),
+    }
+}
+
+impl ImageProject {
+    /// Serializes the layers, their original images, edit stacks and masks, the canvas and the
+    /// custom fonts into a CBOR project file. Undo history is not saved.
+    pub fn save(&self) -> EditorResult<Vec<u8>> {
+        let mut sources: Vec<ByteBuf> = Vec::new();
+        let mut source_index: HashMap<&[u8], usize> = HashMap::new();
+        let mut layers = Vec::with_capacity(self.layers.len());
+        for layer in &self.layers {
+            let source = *source_index.entry(layer.image_data()).or_insert_with(|| {
+                sources.push(ByteBuf::from(layer.image_data().to_vec()));
+                sources.len() - 1
+            });
+            layers.push(LayerRecord {
+                id: layer.id,
+                name: layer.name.clone(),
+                visible: layer.visible,
+                locked: layer.locked,
+                x: layer.x,
+                y: layer.y,
+                opacity: layer.opacity,
+                clipped: layer.clipped,
+                source,
+                edits: layer.edits.clone(),
+                next_edit_id: layer.next_edit_id,
+                mask: layer.mask.clone(),
+            });
+        }
+
+        let (canvas_width, canvas_height) = self.canvas_size();
+        let file = ProjectFile {
+            format: FORMAT.to_string(),
+            version: PROJECT_FORMAT_VERSION,
+            manifest: Manifest {
+                canvas_width,
+                canvas_height,
+                next_layer_id: self.next_layer_id,
+                generator: format!("img-editor-lib {}", env!("CARGO_PKG_VERSION")),
+            },
+            sources,
+            layers,
+            fonts: self.fonts.custom_fonts().cloned().collect(),
+        };
+
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&file, &mut bytes).map_err(|e| EditorError::Encode(e.to_string()))?;
+        Ok(bytes)
+    }
+
+    /// Opens a project file written by `save`, migrating files from older versions. Layers are
+    /// re-rendered from their original images and edit stacks, and the undo history starts empty.
+    pub fn load(data: &[u8]) -> EditorResult<Self> {
+        let mut document: Value = ciborium::from_reader(data)
+            .map_err(|_| invalid("not an img-editor project file"))?;
+        let version = document_version(&document)?;
+        if version > PROJECT_FORMAT_VERSION {
+            return Err(invalid(format!(
+                "project version {} is newer than the supported version {}",
+                version, PROJECT_FORMAT_VERSION
+            )));
+        }
+        for migrate in &MIGRATIONS[version as usize - 1..] {
+            document = migrate(document)?;
+        }
+        let file: ProjectFile = document.deserialized().map_err(invalid)?;
+
+        let mut ids = HashSet::new();
+        if !file.layers.iter().all(|record| ids.insert(record.id)) {
+            return Err(invalid("the project file contains duplicate layer ids"));
+        }
+
+        let mut project = ImageProject::new();
+        for font in file.fonts {
+            project.fonts.register(&font.name, font.data)?;
+        }
+        for record in file.layers {
+            let source = file.sources.get(record.source)
+                .ok_or_else(|| invalid(format!("layer {} refers to a missing source image", record.id)))?;
+            let mut layer = Layer::new(source)?;
+            layer.set_edits(record.edits, &project.fonts)?;
+            if let Some(mask) = &record.mask {
+                mask.check_size(layer.image.width(), layer.image.height())?;
+            }
+            layer.id = record.id;
+            layer.name = record.name;
+            layer.visible = record.visible;
+            layer.locked = record.locked;
+            layer.x = record.x;
+            layer.y = record.y;
+            if record.opacity.is_nan() {
+                return Err(invalid(format!("layer {} has an invalid opacity", record.id)));
+            }
+            layer.opacity = record.opacity.clamp(0.0, 1.0);
+            layer.clipped = record.clipped;
+            let highest_edit = layer.edits.iter().map(|edit| edit.id + 1).max().unwrap_or(0);
+            layer.next_edit_id = record.next_edit_id.max(highest_edit);
+            layer.mask = record.mask;
+            project.layers.push(layer);
+        }
+
+        let highest_id = project.layers.iter().map(|layer| layer.id + 1).max().unwrap_or(0);
+        project.next_layer_id = file.manifest.next_layer_id.max(highest_id);
+        match (file.manifest.canvas_width, file.manifest.canvas_height, project.layers.first()) {
+            (0, _, Some(layer)) | (_, 0, Some(layer)) => {
+                project.canvas_width = layer.image.width();
+                project.canvas_height = layer.image.height();
+            },
+            (width, height, _) => {
+                project.canvas_width = width;
+                project.canvas_height = height;
+            },
+        }
+        project.clear_history();
+        Ok(project)
+    }
+}
@@ -0,0 +1,82 @@
+use rustybuzz::{Direction, UnicodeBuffer};
+use rusttype::GlyphId;
+use std::ops::Range;
+use unicode_bidi::BidiInfo;
+use crate::fonts::{face_for_char, FontFace};
+use crate::text::{ShapedGlyph, ShapedLine};
+
+/// Joiners and variation selectors belong to the cluster before them and must never start a
+/// run in a different font.
+fn extends_previous(c: char) -> bool {
+    matches!(c, '\u{200C}' | '\u{200D}' | '\u{FE00}'..='\u{FE0F}' | '\u{E0100}'..='\u{E01EF}')
+}
+
+/// Shapes one line: resolves bidi levels, reorders the runs visually, splits each run where the
+/// font fallback changes and shapes every piece with rustybuzz.
+pub(crate) fn shape_line<'a>(chain: &[FontFace<'a>], size: u32, line: &str, letter_spacing: i64) -> ShapedLine<'a> {
+    let mut glyphs = Vec::new();
+    let mut caret = 0;
+    if line.is_empty() {
+        return ShapedLine { glyphs, width: caret };
+    }
+
+    // Lines are split at paragraph separators, but a lone `\r` still ends a bidi paragraph
+    let bidi = BidiInfo::new(line, None);
+    let runs = bidi.paragraphs.iter().flat_map(|paragraph| {
+        let (levels, runs) = bidi.visual_runs(paragraph, paragraph.range.clone());
+        runs.into_iter().map(move |run| (levels[run.start].is_rtl(), run))
+    });
+
+    for (rtl, run) in runs {
+        let mut segments: Vec<(usize, Range<usize>)> = Vec::new();
+        for (offset, c) in line[run.clone()].char_indices() {
+            let start = run.start + offset;
+            let end = start + c.len_utf8();
+            let face_index = match segments.last() {
+                Some((previous, _)) if extends_previous(c) => *previous,
+                Some((previous, _)) => face_for_char(chain, c).unwrap_or(*previous),
+                None => face_for_char(chain, c).unwrap_or(0),
+            };
+            match segments.last_mut() {
+                Some((previous, range)) if *previous == face_index => range.end = end,
+                _ => segments.push((face_index, start..end)),
+            }
+        }
+        if rtl {
+            segments.reverse();
+        }
+
+        for (face_index, range) in segments {
+            let face = chain[face_index];
+            let Some(hb_face) = face.shaper else {
+                continue;
+            };
+
+            let mut buffer = UnicodeBuffer::new();
+            buffer.push_str(&line[range]);
+            buffer.set_direction(if rtl { Direction::RightToLeft } else { Direction::LeftToRight });
+            buffer.guess_segment_properties();
+            let output = rustybuzz::shape(hb_face, &[], buffer);
+
+            // rustybuzz works in font units
+            let subpixels = |units: i32| face.subpixels(units as i64, size);
+
+            let mut previous_cluster = None;
+            for (info, position) in output.glyph_infos().iter().zip(output.glyph_positions()) {
+                if !glyphs.is_empty() && previous_cluster != Some(info.cluster) {
+                    caret += letter_spacing;
+                }
+                previous_cluster = Some(info.cluster);
+                glyphs.push(ShapedGlyph {
+                    face,
+                    id: GlyphId(info.glyph_id as u16),
+                    x: caret + subpixels(position.x_offset),
+                    y: -subpixels(position.y_offset),
+                });
+                caret += subpixels(position.x_advance);
+            }
+        }
+    }
+
+    ShapedLine { glyphs, width: caret }
+}
@@ -0,0 +1,728 @@
+use image::{Rgba, RgbaImage};
+use rusttype::{GlyphId, OutlineBuilder};
+use serde::{Deserialize, Serialize};
+use crate::fixed::{div_round, isqrt, quantize, sin_cos, TRIG_BITS};
+use crate::fonts::{FontFace, SUBPIXELS};
+use crate::{Anchor, EditorError, EditorResult, FontRegistry, HorizontalAlign, TextOverlayParameters, VerticalAlign};
+
+/// Coverage and opacity are Q16 fractions; this one is fully opaque.
+pub(crate) const FULL_COVERAGE: u32 = 1 << 16;
+
+/// Scales an 8-bit alpha to a coverage fraction.
+pub(crate) fn to_coverage(alpha: u8) -> u32 {
+    (alpha as u32 * FULL_COVERAGE + 127) / 255
+}
+
+/// The product of two coverage fractions.
+pub(crate) fn mul_coverage(a: u32, b: u32) -> u32 {
+    ((a as u64 * b as u64 + (FULL_COVERAGE as u64 / 2)) / FULL_COVERAGE as u64) as u32
+}
+
+/// Rotated coordinates are kept in 1/65536 pixels.
+const FINE_BITS: u32 = 16;
+const HALF_PIXEL: i64 = 1 << (FINE_BITS - 1);
+
+/// Where a text overlay ends up on the image. `x`, `y`, `width` and `height` describe the
+/// unrotated text block; `corners` are its top-left, top-right, bottom-right and bottom-left
+/// corners after rotation around the anchor.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TextBounds {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub rotation: f32,
+    pub corners: [[f32; 2]; 4],
+    pub line_count: u32,
+}
+
+/// A glyph at its final position, in 1/256 pixels on the image.
+struct PlacedGlyph<'a> {
+    face: FontFace<'a>,
+    id: GlyphId,
+    x: i64,
+    y: i64,
+}
+
+/// Text is laid out in 1/256 pixels with integer arithmetic only, so every target places the
+/// glyphs identically.
+struct TextLayout<'a> {
+    glyphs: Vec<PlacedGlyph<'a>>,
+    origin: (i64, i64),
+    width: i64,
+    height: i64,
+    line_count: u32,
+}
+
+fn to_pixels(value: i64, bits: u32) -> f32 {
+    value as f32 / (1u64 << bits) as f32
+}
+
+pub fn measure_text(params: &TextOverlayParameters, fonts: &FontRegistry) -> EditorResult<TextBounds> {
+    let chain = fonts.fallback_chain(params.font.as_deref())?;
+    let layout = layout_text(&chain, params);
+    let (x, y) = layout.origin;
+    let rotation = Rotation::new(params);
+    let corners = [
+        (x, y),
+        (x + layout.width, y),
+        (x + layout.width, y + layout.height),
+        (x, y + layout.height),
+    ]
+    .map(|(cx, cy)| {
+        let (rx, ry) = rotation.apply(cx << 8, cy << 8);
+        [to_pixels(rx, FINE_BITS), to_pixels(ry, FINE_BITS)]
+    });
+
+    Ok(TextBounds {
+        x: to_pixels(x, 8),
+        y: to_pixels(y, 8),
+        width: to_pixels(layout.width, 8),
+        height: to_pixels(layout.height, 8),
+        rotation: params.rotation,
+        corners,
+        line_count: layout.line_count,
+    })
+}
+
+/// Strokes and shadow blurs are at most this many pixels, and no wider than the text is tall,
+/// so that drawing an overlay never takes more than a moment.
+const MAX_EFFECT: u32 = 256;
+
+pub(crate) fn draw_text(image: &mut RgbaImage, fonts: &FontRegistry, params: &TextOverlayParameters) -> EditorResult<()> {
+    let chain = fonts.fallback_chain(params.font.as_deref())?;
+    let fill_color = parse_color(&params.color)?;
+    let effect_limit = params.size.min(MAX_EFFECT);
+    let stroke = match &params.stroke {
+        Some(stroke) if !(0.0..=effect_limit as f32).contains(&stroke.width) => {
+            return Err(EditorError::InvalidArgument(format!(
+                "stroke width {} must be between 0 and {} pixels",
+                stroke.width, effect_limit
+            )));
+        },
+        Some(stroke) if quantize(stroke.width, 16) > 0 => Some((quantize(stroke.width, 16), parse_color(&stroke.color)?)),
+        _ => None,
+    };
+    let shadow = match &params.shadow {
+        Some(shadow) => Some((shadow, parse_color(&shadow.color)?)),
+        None => None,
+    };
+    if let Some((shadow, _)) = shadow {
+        let max_offset = image.width().max(image.height());
+        let fits = |offset: i32| offset.unsigned_abs() <= max_offset;
+        if !fits(shadow.offset_x) || !fits(shadow.offset_y) {
+            return Err(EditorError::InvalidArgument(format!(
+                "shadow offset ({}, {}) must be at most {} pixels either way",
+                shadow.offset_x, shadow.offset_y, max_offset
+            )));
+        }
+        if shadow.blur > effect_limit {
+            return Err(EditorError::InvalidArgument(format!(
+                "shadow blur {} must be at most {} pixels",
+                shadow.blur, effect_limit
+            )));
+        }
+    }
+
+    let layout = layout_text(&chain, params);
+    let outlines: Vec<Vec<Edge>> = layout.glyphs.iter()
+        .map(|glyph| glyph.edges(params.size))
+        .filter(|edges| !edges.is_empty())
+        .collect();
+    let stroke_margin = stroke.map_or(0, |(width, _)| ((width + 0xFFFF) >> 16) + 1);
+    let shadow_margin = shadow.map_or(0, |(shadow, _)| {
+        shadow.offset_x.unsigned_abs().max(shadow.offset_y.unsigned_abs()) as i64 + shadow.blur as i64
+    });
+    let Some(fill) = Coverage::rasterize(&outlines, stroke_margin + shadow_margin)? else {
+        return Ok(());
+    };
+
+    // Layers are composited bottom to top: shadow, stroke, fill
+    let stroke_mask = stroke.map(|(width, color)| (fill.dilate(width), color));
+    let shadow_mask = shadow.map(|(shadow, color)| {
+        let silhouette = match &stroke_mask {
+            Some((stroke_mask, _)) => stroke_mask.union(&fill),
+            None => fill.clone(),
+        };
+        (silhouette.shift(shadow.offset_x, shadow.offset_y).box_blur(shadow.blur), color)
+    });
+    let mut layers = Vec::new();
+    layers.extend(shadow_mask);
+    layers.extend(stroke_mask);
+    layers.push((fill, fill_color));
+
+    let width = image.width() as i32;
+    let height = image.height() as i32;
+    let mask = &layers[0].0;
+    let (mask_x, mask_y) = (mask.x, mask.y);
+
+    let rotation = Rotation::new(params);
+    if rotation.is_identity() {
+        let (mask_width, mask_height) = (mask.width, mask.height);
+        for py in mask_y.max(0)..(mask_y + mask_height).min(height) {
+            for px in mask_x.max(0)..(mask_x + mask_width).min(width) {
+                let pixel = image.get_pixel_mut(px as u32, py as u32);
+                for (layer, color) in &layers {
+                    blend(pixel, *color, layer.get(px - mask_x, py - mask_y));
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    // Walk the destination pixels covered by the rotated mask and sample it backwards
+    let (min_x, min_y, max_x, max_y) = mask.rotated_bounds(&rotation);
+    let inverse = rotation.inverse();
+    let (mask_x_fine, mask_y_fine) = ((mask_x as i64) << FINE_BITS, (mask_y as i64) << FINE_BITS);
+    for py in min_y.max(0)..=max_y.min(height - 1) {
+        for px in min_x.max(0)..=max_x.min(width - 1) {
+            let (sx, sy) = inverse.apply(((px as i64) << FINE_BITS) + HALF_PIXEL, ((py as i64) << FINE_BITS) + HALF_PIXEL);
+            let (sx, sy) = (sx - HALF_PIXEL - mask_x_fine, sy - HALF_PIXEL - mask_y_fine);
+            let pixel = image.get_pixel_mut(px as u32, py as u32);
+            for (layer, color) in &layers {
+                blend(pixel, *color, layer.sample(sx, sy));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parses `#RRGGBB` or `#RRGGBBAA`.
+pub(crate) fn parse_color(color: &str) -> EditorResult<Rgba<u8>> {
+    let invalid = || EditorError::InvalidColor(color.to_string());
+    let hex = color.trim_start_matches('#');
+    if (hex.len() != 6 && hex.len() != 8) || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+    let alpha = if hex.len() == 8 { channel(6)? } else { 255 };
+    Ok(Rgba([channel(0)?, channel(2)?, channel(4)?, alpha]))
+}
+
+/// Source-over compositing of `color`, scaled by `coverage` out of `FULL_COVERAGE`, onto a
+/// straight-alpha pixel. Computed exactly in integers and rounded once.
+pub(crate) fn blend(pixel: &mut Rgba<u8>, color: Rgba<u8>, coverage: u32) {
+    // Alphas are fractions of ONE
+    const ONE: u64 = 255 * FULL_COVERAGE as u64;
+    let src_alpha = color[3] as u64 * coverage.min(FULL_COVERAGE) as u64;
+    if src_alpha == 0 {
+        return;
+    }
+    let dst_alpha = pixel[3] as u64 * FULL_COVERAGE as u64;
+    // A fraction of ONE * ONE
+    let out_alpha = src_alpha * ONE + dst_alpha * (ONE - src_alpha);
+    for c in 0..3 {
+        let value = color[c] as u64 * src_alpha * ONE + pixel[c] as u64 * dst_alpha * (ONE - src_alpha);
+        pixel[c] = ((2 * value + out_alpha) / (2 * out_alpha)) as u8;
+    }
+    pixel[3] = ((2 * 255 * out_alpha + ONE * ONE) / (2 * ONE * ONE)) as u8;
+}
+
+/// A glyph placed relative to the start of its line and the baseline, in 1/256 pixels.
+pub(crate) struct ShapedGlyph<'a> {
+    pub(crate) face: FontFace<'a>,
+    pub(crate) id: GlyphId,
+    pub(crate) x: i64,
+    pub(crate) y: i64,
+}
+
+pub(crate) struct ShapedLine<'a> {
+    pub(crate) glyphs: Vec<ShapedGlyph<'a>>,
+    pub(crate) width: i64,
+}
+
+#[cfg(feature = "shaping")]
+use crate::shaping::shape_line;
+
+/// Maps characters to glyphs one by one, applying pair kerning and picking each glyph from the
+/// first font in the chain that has it. No reordering or contextual shaping is done.
+#[cfg(not(feature = "shaping"))]
+fn shape_line<'a>(chain: &[FontFace<'a>], size: u32, line: &str, letter_spacing: i64) -> ShapedLine<'a> {
+    let mut glyphs = Vec::new();
+    let mut caret = 0;
+    let mut previous: Option<(usize, GlyphId)> = None;
+    for c in line.chars() {
+        let face_index = crate::fonts::face_for_char(chain, c).unwrap_or(0);
+        let face = chain[face_index];
+        let id = face.font.glyph(c).id();
+        if let Some((previous_face, previous_id)) = previous {
+            if previous_face == face_index {
+                caret += face.subpixels(face.kerning(previous_id, id), size);
+            }
+            caret += letter_spacing;
+        }
+        previous = Some((face_index, id));
+        glyphs.push(ShapedGlyph { face, id, x: caret, y: 0 });
+        caret += face.subpixels(face.advance(id), size);
+    }
+    ShapedLine { glyphs, width: caret }
+}
+
+fn layout_text<'a>(chain: &[FontFace<'a>], params: &TextOverlayParameters) -> TextLayout<'a> {
+    let primary = chain[0];
+    let metrics = primary.metrics();
+    let ascent = primary.subpixels(metrics.ascent, params.size);
+    let glyph_height = primary.subpixels(metrics.ascent - metrics.descent, params.size);
+    let line_gap = primary.subpixels(metrics.line_gap, params.size);
+    let line_height = quantize(params.line_height, 16) as i128;
+    let line_advance = (((glyph_height + line_gap) as i128 * line_height + (1 << 15)) >> 16) as i64;
+    let leading = line_advance - glyph_height;
+    let letter_spacing = quantize(params.letter_spacing, 8);
+
+    let lines: Vec<ShapedLine> = wrap_lines(chain, letter_spacing, params).iter()
+        .map(|line| shape_line(chain, params.size, line, letter_spacing))
+        .collect();
+    let width = lines.iter().map(|line| line.width).fold(0, i64::max);
+    let height = line_advance * lines.len() as i64;
+
+    // Anchors in halves of the block
+    let (anchor_x, anchor_y) = match params.anchor {
+        Anchor::TopLeft => (0, 0),
+        Anchor::Top => (1, 0),
+        Anchor::TopRight => (2, 0),
+        Anchor::Left => (0, 1),
+        Anchor::Center => (1, 1),
+        Anchor::Right => (2, 1),
+        Anchor::BottomLeft => (0, 2),
+        Anchor::Bottom => (1, 2),
+        Anchor::BottomRight => (2, 2),
+    };
+    let origin_x = params.x as i64 * SUBPIXELS - div_round(anchor_x * width, 2);
+    let origin_y = params.y as i64 * SUBPIXELS - div_round(anchor_y * height, 2);
+
+    let baseline_offset = ascent + match params.vertical_align {
+        VerticalAlign::Top => 0,
+        VerticalAlign::Middle => div_round(leading, 2),
+        VerticalAlign::Bottom => leading,
+    };
+
+    let mut glyphs = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let line_x = origin_x + match params.align {
+            HorizontalAlign::Left => 0,
+            HorizontalAlign::Center => div_round(width - line.width, 2),
+            HorizontalAlign::Right => width - line.width,
+        };
+        let baseline = origin_y + i as i64 * line_advance + baseline_offset;
+
+        for glyph in &line.glyphs {
+            glyphs.push(PlacedGlyph { face: glyph.face, id: glyph.id, x: line_x + glyph.x, y: baseline + glyph.y });
+        }
+    }
+
+    TextLayout {
+        glyphs,
+        origin: (origin_x, origin_y),
+        width,
+        height,
+        line_count: lines.len() as u32,
+    }
+}
+
+/// Characters that end a paragraph in the Unicode bidirectional algorithm, except `\r`, which
+/// only ends a line together with the `\n` after it.
+fn is_paragraph_separator(c: char) -> bool {
+    matches!(c, '\n' | '\u{1C}'..='\u{1E}' | '\u{85}' | '\u{2029}')
+}
+
+/// Splits the text on newlines and paragraph separators and, when `max_width` is set, greedily
+/// wraps each paragraph at spaces. Words that are wider than `max_width` on their own are broken between characters.
+fn wrap_lines(chain: &[FontFace], letter_spacing: i64, params: &TextOverlayParameters) -> Vec<String> {
+    let fits = |text: &str| match params.max_width {
+        Some(max_width) => shape_line(chain, params.size, text, letter_spacing).width <= max_width as i64 * SUBPIXELS,
+        None => true,
+    };
+
+    let mut lines = Vec::new();
+    for paragraph in params.text.split(is_paragraph_separator) {
+        let paragraph = paragraph.trim_end_matches('\r');
+        if params.max_width.is_none() {
+            lines.push(paragraph.to_string());
+            continue;
+        }
+
+        let mut current = String::new();
+        for word in paragraph.split(' ') {
+            let candidate = if current.is_empty() {
+                word.to_string()
+            } else {
+                format!("{} {}", current, word)
+            };
+            if fits(&candidate) {
+                current = candidate;
+                continue;
+            }
+            if !current.is_empty() {
+                lines.push(std::mem::take(&mut current));
+            }
+            for c in word.chars() {
+                current.push(c);
+                if !fits(&current) && current.chars().count() > 1 {
+                    current.pop();
+                    lines.push(std::mem::replace(&mut current, c.to_string()));
+                }
+            }
+        }
+        lines.push(current);
+    }
+    lines
+}
+
+/// Rotation of the text around its anchor point by `rotation` degrees, taken in 1/256 degree
+/// steps. Points are in 1/65536 pixels.
+struct Rotation {
+    sin: i64,
+    cos: i64,
+    pivot: (i64, i64),
+}
+
+impl Rotation {
+    fn new(params: &TextOverlayParameters) -> Rotation {
+        let (sin, cos) = sin_cos(quantize(params.rotation, 8));
+        Rotation { sin, cos, pivot: ((params.x as i64) << FINE_BITS, (params.y as i64) << FINE_BITS) }
+    }
+
+    fn is_identity(&self) -> bool {
+        self.sin == 0 && self.cos == 1 << TRIG_BITS
+    }
+
+    fn inverse(&self) -> Rotation {
+        Rotation { sin: -self.sin, cos: self.cos, pivot: self.pivot }
+    }
+
+    fn apply(&self, x: i64, y: i64) -> (i64, i64) {
+        let (dx, dy) = ((x - self.pivot.0) as i128, (y - self.pivot.1) as i128);
+        let (sin, cos) = (self.sin as i128, self.cos as i128);
+        let round = 1i128 << (TRIG_BITS - 1);
+        (
+            self.pivot.0 + ((dx * cos - dy * sin + round) >> TRIG_BITS) as i64,
+            self.pivot.1 + ((dx * sin + dy * cos + round) >> TRIG_BITS) as i64,
+        )
+    }
+}
+
+/// A straight piece of a glyph outline, in 1/256 pixels on the image.
+#[derive(Clone, Copy)]
+struct Edge {
+    x0: i64,
+    y0: i64,
+    x1: i64,
+    y1: i64,
+}
+
+impl PlacedGlyph<'_> {
+    /// The glyph's outline flattened to edges. Glyphs without an outline, like spaces, have
+    /// none.
+    fn edges(&self, size: u32) -> Vec<Edge> {
+        let metrics = self.face.metrics();
+        let mut flattener = Flattener {
+            size: size as i64,
+            units: (metrics.ascent - metrics.descent).max(1) * 64,
+            origin: (self.x, self.y),
+            edges: Vec::new(),
+            start: (0, 0),
+            current: (0, 0),
+        };
+        self.face.outline(self.id, &mut flattener);
+        flattener.close();
+        flattener.edges
+    }
+}
+
+/// Collects a glyph outline as straight edges, splitting its curves into segments that stay
+/// within 1/64 pixel of them.
+struct Flattener {
+    size: i64,
+    /// Ascent minus descent in 1/64 font units, which text of `size` pixels spans
+    units: i64,
+    origin: (i64, i64),
+    edges: Vec<Edge>,
+    start: (i64, i64),
+    current: (i64, i64),
+}
+
+impl Flattener {
+    /// Converts a point in font units, which CFF outlines give with fractions, to 1/256 pixels.
+    fn point(&self, x: f32, y: f32) -> (i64, i64) {
+        let scale = |value: f32| div_round(quantize(value, 6) * self.size * SUBPIXELS, self.units);
+        (self.origin.0 + scale(x), self.origin.1 + scale(y))
+    }
+
+    fn line(&mut self, to: (i64, i64)) {
+        let from = self.current;
+        if from.1 != to.1 {
+            self.edges.push(Edge { x0: from.0, y0: from.1, x1: to.0, y1: to.1 });
+        }
+        self.current = to;
+    }
+
+    /// Enough segments that a curve whose control points deviate from a straight line by
+    /// `deviation` is approximated within 1/64 pixel.
+    fn segments(deviation: i64) -> i64 {
+        (1 + isqrt(deviation.unsigned_abs() / 16) as i64).min(64)
+    }
+}
+
+impl OutlineBuilder for Flattener {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.close();
+        self.start = self.point(x, y);
+        self.current = self.start;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let to = self.point(x, y);
+        self.line(to);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (p0, p1, p2) = (self.current, self.point(x1, y1), self.point(x, y));
+        let deviation = (p0.0 - 2 * p1.0 + p2.0).abs().max((p0.1 - 2 * p1.1 + p2.1).abs());
+        let n = Self::segments(deviation);
+        for i in 1..=n {
+            let (a, b, c) = ((n - i) * (n - i), 2 * i * (n - i), i * i);
+            let to = (
+                div_round(a * p0.0 + b * p1.0 + c * p2.0, n * n),
+                div_round(a * p0.1 + b * p1.1 + c * p2.1, n * n),
+            );
+            self.line(to);
+        }
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (p0, p1, p2, p3) = (self.current, self.point(x1, y1), self.point(x2, y2), self.point(x, y));
+        let deviation = [
+            p0.0 - 2 * p1.0 + p2.0,
+            p0.1 - 2 * p1.1 + p2.1,
+            p1.0 - 2 * p2.0 + p3.0,
+            p1.1 - 2 * p2.1 + p3.1,
+        ]
+        .iter()
+        .map(|value| value.abs())
+        .max()
+        .unwrap_or(0);
+        let n = Self::segments(2 * deviation);
+        for i in 1..=n {
+            let j = n - i;
+            let (a, b, c, d) = (j * j * j, 3 * j * j * i, 3 * j * i * i, i * i * i);
+            let to = (
+                div_round(a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0, n * n * n),
+                div_round(a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1, n * n * n),
+            );
+            self.line(to);
+        }
+    }
+
+    fn close(&mut self) {
+        let start = self.start;
+        self.line(start);
+    }
+}
+
+/// Glyph coverage of the whole text block in unrotated image coordinates, as Q16 fractions.
+#[derive(Clone)]
+struct Coverage {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    values: Vec<u32>,
+}
+
+/// Samples per pixel along each axis when rasterizing glyphs.
+const SAMPLES: i64 = 16;
+
+/// Adds the samples from `from` up to `to`, counted along a row, to the pixels they fall in.
+fn add_samples(counts: &mut [u32], left: i64, from: i64, to: i64) {
+    let (from, to) = (from.max(left * SAMPLES), to.min((left + counts.len() as i64) * SAMPLES));
+    let mut sample = from;
+    while sample < to {
+        let pixel = sample.div_euclid(SAMPLES);
+        let next = ((pixel + 1) * SAMPLES).min(to);
+        counts[(pixel - left) as usize] += (next - sample) as u32;
+        sample = next;
+    }
+}
+
+impl Coverage {
+    /// Rasterizes the glyph outlines into a mask with `margin` empty pixels on every side,
+    /// leaving room for strokes and shadows. Fails when the mask would not fit in memory.
+    fn rasterize(outlines: &[Vec<Edge>], margin: i64) -> EditorResult<Option<Coverage>> {
+        let edges = || outlines.iter().flatten();
+        let (Some(min_x), Some(min_y), Some(max_x), Some(max_y)) = (
+            edges().map(|edge| edge.x0.min(edge.x1)).min(),
+            edges().map(|edge| edge.y0.min(edge.y1)).min(),
+            edges().map(|edge| edge.x0.max(edge.x1)).max(),
+            edges().map(|edge| edge.y0.max(edge.y1)).max(),
+        ) else {
+            return Ok(None);
+        };
+        let too_large = || EditorError::InvalidArgument("text overlay is too large to draw".to_string());
+        let to_i32 = |value: i64| i32::try_from(value).map_err(|_| too_large());
+        let (min_x, min_y) = (to_i32(min_x.div_euclid(SUBPIXELS) - margin)?, to_i32(min_y.div_euclid(SUBPIXELS) - margin)?);
+        let max_x = to_i32(ceil_div(max_x, SUBPIXELS) + margin)?;
+        let max_y = to_i32(ceil_div(max_y, SUBPIXELS) + margin)?;
+        let (width, height) = (max_x.checked_sub(min_x).ok_or_else(too_large)?, max_y.checked_sub(min_y).ok_or_else(too_large)?);
+        let area = width.checked_mul(height).and_then(|area| usize::try_from(area).ok()).ok_or_else(too_large)?;
+
+        let mut mask = Coverage { x: min_x, y: min_y, width, height, values: vec![0; area] };
+        for edges in outlines {
+            mask.fill(edges);
+        }
+        Ok(Some(mask))
+    }
+
+    /// Rasterizes one glyph with the non-zero winding rule by counting which of the
+    /// `SAMPLES` x `SAMPLES` points of each pixel lie inside it. Where glyphs overlap the
+    /// larger coverage wins.
+    fn fill(&mut self, edges: &[Edge]) {
+        const STEP: i64 = SUBPIXELS / SAMPLES;
+        let top = edges.iter().map(|edge| edge.y0.min(edge.y1)).min().unwrap_or(0).div_euclid(SUBPIXELS);
+        let bottom = ceil_div(edges.iter().map(|edge| edge.y0.max(edge.y1)).max().unwrap_or(0), SUBPIXELS);
+        let left = edges.iter().map(|edge| edge.x0.min(edge.x1)).min().unwrap_or(0).div_euclid(SUBPIXELS);
+        let right = ceil_div(edges.iter().map(|edge| edge.x0.max(edge.x1)).max().unwrap_or(0), SUBPIXELS);
+
+        let mut counts = vec![0u32; (right - left).max(0) as usize];
+        let mut active: Vec<&Edge> = Vec::new();
+        let mut crossings = Vec::new();
+        for row in top..bottom {
+            let row_top = row * SUBPIXELS;
+            active.clear();
+            active.extend(edges.iter().filter(|edge| {
+                edge.y0.min(edge.y1) < row_top + SUBPIXELS && edge.y0.max(edge.y1) > row_top
+            }));
+            counts.fill(0);
+            for sample in 0..SAMPLES {
+                let y = row_top + sample * STEP + STEP / 2;
+                crossings.clear();
+                for edge in &active {
+                    let (low, high, direction) = match edge.y0 < edge.y1 {
+                        true => ((edge.x0, edge.y0), (edge.x1, edge.y1), 1),
+                        false => ((edge.x1, edge.y1), (edge.x0, edge.y0), -1),
+                    };
+                    if y < low.1 || y >= high.1 {
+                        continue;
+                    }
+                    let x = low.0 + ((high.0 - low.0) * (y - low.1)).div_euclid(high.1 - low.1);
+                    crossings.push((x, direction));
+                }
+                crossings.sort_unstable();
+
+                // A sample is inside when its centre lies between a crossing where the winding
+                // number leaves zero and the one where it returns to zero
+                let mut winding = 0;
+                let mut start = 0;
+                for &(x, direction) in &crossings {
+                    if winding == 0 {
+                        start = x;
+                    }
+                    winding += direction;
+                    if winding == 0 {
+                        add_samples(&mut counts, left, ceil_div(start - STEP / 2, STEP), ceil_div(x - STEP / 2, STEP));
+                    }
+                }
+            }
+
+            let y = row as i32 - self.y;
+            for (offset, &count) in counts.iter().enumerate() {
+                let x = (left + offset as i64) as i32 - self.x;
+                if count > 0 && (0..self.width).contains(&x) && (0..self.height).contains(&y) {
+                    let value = &mut self.values[(y * self.width + x) as usize];
+                    *value = (*value).max(count * (FULL_COVERAGE / (SAMPLES * SAMPLES) as u32));
+                }
+            }
+        }
+    }
+
+    fn with_values(&self, values: Vec<u32>) -> Coverage {
+        Coverage { x: self.x, y: self.y, width: self.width, height: self.height, values }
+    }
+
+    fn map<F: Fn(i32, i32) -> u32>(&self, f: F) -> Coverage {
+        let values = (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .map(|(x, y)| f(x, y))
+            .collect();
+        self.with_values(values)
+    }
+
+    /// Grows the mask by `radius` pixels, given in Q16, with an anti-aliased round edge.
+    fn dilate(&self, radius: i64) -> Coverage {
+        let reach = ((radius + 0xFFFF) >> 16) as i32 + 1;
+        let mut kernel = Vec::new();
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                let distance = isqrt(((dx * dx + dy * dy) as u64) << 32) as i64;
+                let weight = (radius + (1 << 16) - distance).clamp(0, 1 << 16);
+                if weight > 0 {
+                    kernel.push((dx, dy, weight as u64));
+                }
+            }
+        }
+        self.map(|x, y| {
+            kernel.iter()
+                .map(|&(dx, dy, weight)| ((self.get(x + dx, y + dy) as u64 * weight + (1 << 15)) >> 16) as u32)
+                .fold(0, u32::max)
+        })
+    }
+
+    fn union(&self, other: &Coverage) -> Coverage {
+        self.map(|x, y| self.get(x, y).max(other.get(x, y)))
+    }
+
+    fn shift(&self, dx: i32, dy: i32) -> Coverage {
+        self.map(|x, y| self.get(x - dx, y - dy))
+    }
+
+    /// Separable box blur, run as horizontal then vertical passes.
+    fn box_blur(&self, radius: u32) -> Coverage {
+        if radius == 0 {
+            return self.clone();
+        }
+        let radius = radius as i32;
+        let area = (2 * radius + 1) as u64;
+        let average = |sum: u64| ((sum + area / 2) / area) as u32;
+        let horizontal = self.map(|x, y| average((-radius..=radius).map(|d| self.get(x + d, y) as u64).sum()));
+        horizontal.map(|x, y| average((-radius..=radius).map(|d| horizontal.get(x, y + d) as u64).sum()))
+    }
+
+    fn get(&self, x: i32, y: i32) -> u32 {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return 0;
+        }
+        self.values[(y * self.width + x) as usize]
+    }
+
+    /// Bilinear sample at mask-local coordinates in 1/65536 pixels.
+    fn sample(&self, x: i64, y: i64) -> u32 {
+        const ONE: u64 = 1 << FINE_BITS;
+        let (x0, y0) = ((x >> FINE_BITS) as i32, (y >> FINE_BITS) as i32);
+        let (fx, fy) = ((x as u64) & (ONE - 1), (y as u64) & (ONE - 1));
+        let top = self.get(x0, y0) as u64 * (ONE - fx) + self.get(x0 + 1, y0) as u64 * fx;
+        let bottom = self.get(x0, y0 + 1) as u64 * (ONE - fx) + self.get(x0 + 1, y0 + 1) as u64 * fx;
+        ((top * (ONE - fy) + bottom * fy + (1 << (2 * FINE_BITS - 1))) >> (2 * FINE_BITS)) as u32
+    }
+
+    fn rotated_bounds(&self, rotation: &Rotation) -> (i32, i32, i32, i32) {
+        let (x0, y0) = ((self.x as i64) << FINE_BITS, (self.y as i64) << FINE_BITS);
+        let (x1, y1) = (((self.x + self.width) as i64) << FINE_BITS, ((self.y + self.height) as i64) << FINE_BITS);
+        let corners = [(x0, y0), (x1, y0), (x1, y1), (x0, y1)]
+            .map(|(x, y)| rotation.apply(x, y));
+        let min_x = corners.iter().map(|c| c.0).min().unwrap_or(0);
+        let min_y = corners.iter().map(|c| c.1).min().unwrap_or(0);
+        let max_x = corners.iter().map(|c| c.0).max().unwrap_or(0);
+        let max_y = corners.iter().map(|c| c.1).max().unwrap_or(0);
+        let one = 1 << FINE_BITS;
+        (
+            min_x.div_euclid(one) as i32,
+            min_y.div_euclid(one) as i32,
+            ceil_div(max_x, one) as i32,
+            ceil_div(max_y, one) as i32,
+        )
+    }
+}
+
+fn ceil_div(numerator: i64, denominator: i64) -> i64 {
+    -(-numerator).div_euclid(denominator)
+}
@@ -0,0 +1,317 @@
+use image::{DynamicImage, ImageFormat, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{Cursor, Write};
+use crate::color::working_space_transform;
+use crate::icc::Transform;
+use crate::layer::decode;
+use crate::metadata::orientation;
+use crate::profile::track;
+use crate::text::{blend, parse_color, FULL_COVERAGE};
+use crate::{
+    read_icc_profile, sniff_format, AlphaHandling, ColorProfilePolicy, EditorError, EditorResult, FontRegistry, ImageInput,
+    Layer, MetadataPolicy, OutputFormat, PngCompression, PngFilter, Region, Transformation,
+};
+
+/// Why a transformation needs the whole image instead of one band of rows at a time.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FullFrameReason {
+    /// Rotations and vertical flips move pixels from one row to another.
+    MovesRows,
+    /// Blurs read the pixels around each one.
+    ReadsNeighbours,
+    /// Text is laid out over the whole image.
+    DrawsText,
+}
+
+impl fmt::Display for FullFrameReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FullFrameReason::MovesRows => write!(f, "it moves pixels between rows"),
+            FullFrameReason::ReadsNeighbours => write!(f, "it reads neighbouring pixels"),
+            FullFrameReason::DrawsText => write!(f, "it draws text over the whole image"),
+        }
+    }
+}
+
+/// A transformation of a list that tiled rendering cannot apply.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FullFrameTransformation {
+    pub index: usize,
+    pub reason: FullFrameReason,
+}
+
+/// Why `transformation` needs the whole image, or `None` when it can be applied band by band.
+/// Color adjustments, horizontal flips and crops are tile-local.
+pub fn full_frame_reason(transformation: &Transformation) -> Option<FullFrameReason> {
+    match transformation {
+        Transformation::Crop(_)
+        | Transformation::Grayscale { .. }
+        | Transformation::FlipHorizontal { .. }
+        | Transformation::Brighten { .. }
+        | Transformation::Contrast { .. } => None,
+        Transformation::Rotate90
+        | Transformation::Rotate180
+        | Transformation::Rotate270
+        | Transformation::FlipVertical { .. } => Some(FullFrameReason::MovesRows),
+        Transformation::Blur { .. } => Some(FullFrameReason::ReadsNeighbours),
+        Transformation::TextOverlay(_) => Some(FullFrameReason::DrawsText),
+    }
+}
+
+/// The transformations that keep a list from being rendered tile by tile, in order.
+pub fn full_frame_transformations(transformations: &[Transformation]) -> Vec<FullFrameTransformation> {
+    transformations.iter()
+        .enumerate()
+        .filter_map(|(index, transformation)| {
+            full_frame_reason(transformation).map(|reason| FullFrameTransformation { index, reason })
+        })
+        .collect()
+}
+
+fn decode_error(e: impl fmt::Display) -> EditorError {
+    EditorError::Decode(e.to_string())
+}
+
+fn encode_error(e: impl fmt::Display) -> EditorError {
+    EditorError::Encode(e.to_string())
+}
+
+/// Rows of the source image, read as RGBA bands.
+enum Source<'a> {
+    /// Non-interlaced 8-bit PNGs are decoded row by row, so they are never held whole.
+    Png {
+        reader: Box<png::Reader<Cursor<&'a [u8]>>>,
+        color: png::ColorType,
+        /// Conversion into the working space when the PNG is tagged with a color profile.
+        transform: Option<Transform>,
+    },
+    /// Anything else is decoded at once like `Layer::new` does, then handed out in bands.
+    Decoded(RgbaImage),
+}
+
+impl<'a> Source<'a> {
+    fn open(image_data: &'a [u8]) -> EditorResult<(Self, u32, u32)> {
+        if sniff_format(image_data) == Some(ImageFormat::Png) && orientation(image_data) == 1 {
+            let mut decoder = png::Decoder::new(Cursor::new(image_data));
+            decoder.set_transformations(png::Transformations::EXPAND);
+            let reader = decoder.read_info().map_err(decode_error)?;
+            let (color, depth) = reader.output_color_type();
+            if depth == png::BitDepth::Eight && !reader.info().interlaced {
+                let (width, height) = reader.info().size();
+                let transform = read_icc_profile(image_data).and_then(|icc| working_space_transform(&icc));
+                return Ok((Source::Png { reader: Box::new(reader), color, transform }, width, height));
+            }
+        }
+        let image = decode(image_data)?.into_rgba8();
+        let (width, height) = image.dimensions();
+        Ok((Source::Decoded(image), width, height))
+    }
+
+    /// Reads rows `y` to `y + rows` of a `width` pixel wide image.
+    fn band(&mut self, y: u32, rows: u32, width: u32) -> EditorResult<RgbaImage> {
+        match self {
+            Source::Png { reader, color, transform } => {
+                let mut band = RgbaImage::new(width, rows);
+                for pixels in band.chunks_exact_mut(width as usize * 4) {
+                    let row = reader.next_row()
+                        .map_err(decode_error)?
+                        .ok_or_else(|| decode_error("image data ends early"))?;
+                    expand_row(row.data(), *color, pixels)?;
+                }
+                if let Some(transform) = transform {
+                    transform.apply(&mut band, 4);
+                }
+                Ok(band)
+            },
+            Source::Decoded(image) => Ok(image::imageops::crop_imm(image, 0, y, width, rows).to_image()),
+        }
+    }
+}
+
+/// Converts one expanded 8-bit PNG row to RGBA, as `DynamicImage::to_rgba8` would.
+fn expand_row(row: &[u8], color: png::ColorType, pixels: &mut [u8]) -> EditorResult<()> {
+    let channels = match color {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        png::ColorType::Indexed => return Err(decode_error("palette was not expanded")),
+    };
+    for (source, pixel) in row.chunks_exact(channels).zip(pixels.chunks_exact_mut(4)) {
+        let rgba = match *source {
+            [gray] => [gray, gray, gray, 255],
+            [gray, alpha] => [gray, gray, gray, alpha],
+            [red, green, blue] => [red, green, blue, 255],
+            [red, green, blue, alpha] => [red, green, blue, alpha],
+            _ => unreachable!("chunks hold one to four channels"),
+        };
+        pixel.copy_from_slice(&rgba);
+    }
+    Ok(())
+}
+
+/// Rows `y` onwards of the image as transformed so far.
+struct Band {
+    y: u32,
+    image: RgbaImage,
+}
+
+/// The part of `region` inside `band`, in band coordinates.
+fn band_region(region: &Region, band: &Band) -> Option<Region> {
+    let top = region.y.max(band.y);
+    let bottom = (region.y + region.height).min(band.y + band.image.height());
+    (top < bottom).then(|| Region { x: region.x, y: top - band.y, width: region.width, height: bottom - top })
+}
+
+/// Applies a tile-local transformation to one band. `None` when a crop removed all its rows.
+fn apply(band: Band, transformation: &Transformation, fonts: &FontRegistry) -> EditorResult<Option<Band>> {
+    let local = |region: &Option<Region>| match region {
+        Some(region) => band_region(region, &band).map(Some),
+        None => Some(None),
+    };
+    let transformation = match transformation {
+        Transformation::Crop(params) => {
+            let top = params.y.max(band.y);
+            let bottom = (params.y + params.height).min(band.y + band.image.height());
+            if top >= bottom {
+                return Ok(None);
+            }
+            let image = image::imageops::crop_imm(&band.image, params.x, top - band.y, params.width, bottom - top).to_image();
+            return Ok(Some(Band { y: top - params.y, image }));
+        },
+        Transformation::Grayscale { region } => local(region).map(|region| Transformation::Grayscale { region }),
+        Transformation::FlipHorizontal { region } => local(region).map(|region| Transformation::FlipHorizontal { region }),
+        Transformation::Brighten { value, region } => {
+            local(region).map(|region| Transformation::Brighten { value: *value, region })
+        },
+        Transformation::Contrast { contrast, region } => {
+            local(region).map(|region| Transformation::Contrast { contrast: *contrast, region })
+        },
+        _ => unreachable!("full-frame transformations are rejected before rendering"),
+    };
+    let Some(transformation) = &transformation else {
+        return Ok(Some(band));
+    };
+
+    // The same kernels as full-frame rendering, so every pixel matches
+    let mut layer = Layer::from_image(DynamicImage::ImageRgba8(band.image), Vec::new());
+    layer.transform(transformation, fonts)?;
+    Ok(Some(Band { y: band.y, image: layer.image.into_rgba8() }))
+}
+
+/// Checks that `input` can be rendered tile by tile and returns the size of the output.
+fn plan(input: &ImageInput, width: u32, height: u32) -> EditorResult<(u32, u32)> {
+    if let Some(full_frame) = full_frame_transformations(&input.transformations).first() {
+        return Err(EditorError::InvalidArgument(format!(
+            "transformation {} cannot be rendered in tiles because {}",
+            full_frame.index, full_frame.reason
+        )));
+    }
+    if !input.layers.is_empty() || input.canvas.is_some() || input.animation.is_some() {
+        return Err(EditorError::InvalidArgument("tiled rendering takes a single still image".to_string()));
+    }
+    if input.output_format != OutputFormat::Png {
+        return Err(EditorError::UnsupportedFormat("tiled rendering only writes png".to_string()));
+    }
+    let options = &input.encode_options;
+    if options.metadata != MetadataPolicy::Strip || options.color_profile != ColorProfilePolicy::Srgb {
+        return Err(EditorError::InvalidArgument("tiled rendering cannot copy metadata or color profiles".to_string()));
+    }
+
+    let (mut width, mut height) = (width, height);
+    for transformation in &input.transformations {
+        match transformation {
+            Transformation::Crop(params) => {
+                EditorError::check_region(params.x, params.y, params.width, params.height, width, height)?;
+                (width, height) = (params.width, params.height);
+            },
+            Transformation::Grayscale { region: Some(region) }
+            | Transformation::FlipHorizontal { region: Some(region) }
+            | Transformation::Brighten { region: Some(region), .. }
+            | Transformation::Contrast { region: Some(region), .. } => {
+                EditorError::check_region(region.x, region.y, region.width, region.height, width, height)?;
+            },
+            _ => {},
+        }
+    }
+    Ok((width, height))
+}
+
+/// Renders `input` in bands of `ImageInput::tile_rows` rows and streams the PNG to `output`, so
+/// neither the decoded image nor the encoded output is ever held whole. Only tile-local
+/// transformations are accepted; `full_frame_transformations` lists the others. The output is
+/// always 8 bits per channel, RGBA unless `encode_options.alpha` removes the alpha channel, so its
+/// pixels match `render_input` but its bytes usually do not.
+pub fn render_tiled(input: &ImageInput, fonts: &FontRegistry, output: impl Write) -> EditorResult<()> {
+    let rows = match input.tile_rows {
+        Some(0) | None => return Err(EditorError::InvalidArgument("tile_rows must be at least 1".to_string())),
+        Some(rows) => rows,
+    };
+    let (mut source, width, height) = Source::open(&input.image_data)?;
+    let (output_width, output_height) = plan(input, width, height)?;
+
+    let background = match &input.encode_options.alpha {
+        AlphaHandling::Flatten { background } => {
+            let mut background = parse_color(background)?;
+            background[3] = 255;
+            Some(background)
+        },
+        _ => None,
+    };
+    let keep_alpha = input.encode_options.alpha == AlphaHandling::Keep;
+
+    let mut encoder = png::Encoder::new(output, output_width, output_height);
+    encoder.set_color(if keep_alpha { png::ColorType::Rgba } else { png::ColorType::Rgb });
+    encoder.set_depth(png::BitDepth::Eight);
+    let png_options = &input.encode_options.png;
+    encoder.set_compression(match png_options.compression {
+        PngCompression::Fast => png::Compression::Fast,
+        PngCompression::Balanced => png::Compression::Default,
+        PngCompression::Best => png::Compression::Best,
+    });
+    match png_options.filter {
+        PngFilter::Adaptive => encoder.set_adaptive_filter(png::AdaptiveFilterType::Adaptive),
+        filter => encoder.set_filter(match filter {
+            PngFilter::Sub => png::FilterType::Sub,
+            PngFilter::Up => png::FilterType::Up,
+            PngFilter::Avg => png::FilterType::Avg,
+            PngFilter::Paeth => png::FilterType::Paeth,
+            _ => png::FilterType::NoFilter,
+        }),
+    }
+    let mut png = encoder.write_header().map_err(encode_error)?;
+    let mut writer = png.stream_writer().map_err(encode_error)?;
+
+    for y in (0..height).step_by(rows as usize) {
+        let band_rows = rows.min(height - y);
+        let image = track(format_args!("decode"), || source.band(y, band_rows, width))?;
+        let mut band = Some(Band { y, image });
+        for (index, transformation) in input.transformations.iter().enumerate() {
+            band = match band {
+                Some(band) => track(format_args!("transform_{index}"), || apply(band, transformation, fonts))?,
+                None => break,
+            };
+        }
+        let Some(band) = band else { continue };
+
+        let bytes = match (keep_alpha, background) {
+            (true, _) => band.image.into_raw(),
+            (false, background) => band.image.pixels()
+                .flat_map(|&pixel| {
+                    let mut pixel = pixel;
+                    if let Some(background) = background {
+                        let mut flattened = background;
+                        blend(&mut flattened, pixel, FULL_COVERAGE);
+                        pixel = flattened;
+                    }
+                    [pixel[0], pixel[1], pixel[2]]
+                })
+                .collect(),
+        };
+        // Includes hashing when the output is streamed into a hasher
+        track(format_args!("encode"), || writer.write_all(&bytes)).map_err(encode_error)?;
+    }
+    writer.finish().map_err(encode_error)?;
+    png.finish().map_err(encode_error)
+}
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use crate::{AnimationInput, EncodeOptions, LayerMask, OutputFormat};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CropParameters {
@@ -23,6 +24,58 @@ pub struct ContrastParameters {
     pub contrast: f32,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HorizontalAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// Placement of a line inside its line box when `line_height` adds or removes leading.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VerticalAlign {
+    #[default]
+    Top,
+    Middle,
+    Bottom,
+}
+
+/// The point of the text block that is pinned to `(x, y)` and used as the rotation pivot.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+/// Outline drawn around the glyphs, underneath the fill. `width` is at most the font size and
+/// 256 pixels.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TextStroke {
+    pub width: f32,
+    pub color: String,
+}
+
+/// Drop shadow drawn underneath the text. Offsets are in the text's own coordinate space, so the
+/// shadow rotates with the text. Each offset is at most the image's longer side, and `blur` at
+/// most the font size and 256 pixels.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TextShadow {
+    pub offset_x: i32,
+    pub offset_y: i32,
+    #[serde(default)]
+    pub blur: u32,
+    pub color: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TextOverlayParameters {
     pub text: String,
@@ -30,6 +83,35 @@ pub struct TextOverlayParameters {
     pub y: u32,
     pub size: u32,
     pub color: String,
+    /// Lines wider than this many pixels are wrapped at word boundaries.
+    #[serde(default)]
+    pub max_width: Option<u32>,
+    /// Multiplier applied to the font's natural line height.
+    #[serde(default = "default_line_height")]
+    pub line_height: f32,
+    #[serde(default)]
+    pub align: HorizontalAlign,
+    #[serde(default)]
+    pub vertical_align: VerticalAlign,
+    #[serde(default)]
+    pub anchor: Anchor,
+    /// Clockwise rotation in degrees around the anchor point.
+    #[serde(default)]
+    pub rotation: f32,
+    /// Extra pixels inserted between consecutive glyphs.
+    #[serde(default)]
+    pub letter_spacing: f32,
+    #[serde(default)]
+    pub stroke: Option<TextStroke>,
+    #[serde(default)]
+    pub shadow: Option<TextShadow>,
+    /// Name of a bundled or registered font. Defaults to the bundled Impact font.
+    #[serde(default)]
+    pub font: Option<String>,
+}
+
+fn default_line_height() -> f32 {
+    1.0
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -55,17 +137,106 @@ pub enum Transformation {
     TextOverlay(TextOverlayParameters),
 }
 
+impl Transformation {
+    /// The variant name, as it appears in JSON.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transformation::Crop(_) => "Crop",
+            Transformation::Grayscale { .. } => "Grayscale",
+            Transformation::Rotate90 => "Rotate90",
+            Transformation::Rotate180 => "Rotate180",
+            Transformation::Rotate270 => "Rotate270",
+            Transformation::FlipVertical { .. } => "FlipVertical",
+            Transformation::FlipHorizontal { .. } => "FlipHorizontal",
+            Transformation::Brighten { .. } => "Brighten",
+            Transformation::Contrast { .. } => "Contrast",
+            Transformation::Blur { .. } => "Blur",
+            Transformation::TextOverlay(_) => "TextOverlay",
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SignatureData {
     pub signature: Vec<u8>,
     pub public_key: Vec<u8>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FontData {
+    pub name: String,
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ImageInput {
     pub image_data: Vec<u8>,
     pub transformations: Vec<Transformation>,
     pub signature_data: Option<SignatureData>,
+    /// Custom fonts referenced by text overlays. The program commits the hash of each one.
+    #[serde(default)]
+    pub fonts: Vec<FontData>,
+    /// Extra layers composited above the transformed image, bottom first. The program commits
+    /// the hash of each layer's image.
+    #[serde(default)]
+    pub layers: Vec<LayerInput>,
+    /// Format of the image the program renders and hashes. Pick the format that will be
+    /// published, so the committed hash matches the published file byte for byte.
+    #[serde(default)]
+    pub output_format: OutputFormat,
+    /// Encoder settings for `output_format`.
+    #[serde(default)]
+    pub encode_options: EncodeOptions,
+    /// Proves every frame of an animated image, written as an animated GIF or PNG. Without it
+    /// only the first frame is rendered.
+    #[serde(default)]
+    pub animation: Option<AnimationInput>,
+    /// Renders the image in bands of this many rows, so large images fit in the zkVM's memory.
+    /// Only PNG output of tile-local transformations is supported; see `render_tiled`.
+    #[serde(default)]
+    pub tile_rows: Option<u32>,
+    /// Canvas the layers are composited on. Without it the canvas is the transformed base image.
+    #[serde(default)]
+    pub canvas: Option<CanvasInput>,
+}
+
+/// The canvas of a layered proof when it is not the transformed base image, as in projects
+/// whose canvas was resized or whose bottom layer was moved: its size and where the base image
+/// sits on it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CanvasInput {
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub x: i32,
+    #[serde(default)]
+    pub y: i32,
+}
+
+/// A layer stacked above the base image in a multi-layer proof. Its position is on the canvas,
+/// which is the transformed base image unless `ImageInput::canvas` is set.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LayerInput {
+    #[serde(with = "serde_bytes")]
+    pub image_data: Vec<u8>,
+    #[serde(default)]
+    pub transformations: Vec<Transformation>,
+    #[serde(default)]
+    pub x: i32,
+    #[serde(default)]
+    pub y: i32,
+    #[serde(default = "default_opacity")]
+    pub opacity: f32,
+    /// Applied after the layer's own transformations, so it must match the transformed size.
+    #[serde(default)]
+    pub mask: Option<LayerMask>,
+    #[serde(default)]
+    pub clipped: bool,
+}
+
+fn default_opacity() -> f32 {
+    1.0
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -1,6 +1,39 @@
 use wasm_bindgen::prelude::*;
 use serde_wasm_bindgen;
-use crate::{ImageProject, ImageInput, Transformation};
+use image::{DynamicImage, ImageFormat};
+use crate::layer::{decode, encode};
+use crate::{
+    parse_format, Animation, BrushStroke, EditId, EditorError, EncodeOptions, FontRegistry, FrameEdit, FrameRange,
+    HorizontalAlign, ImageInput, ImageProject, LayerId, LayerMask, OutputFormat, ProvingRates, Selection,
+    TextOverlayParameters, Transformation, VerticalAlign,
+};
+
+/// Reads optional encoder settings; undefined means the defaults.
+fn encode_options(options: JsValue) -> Result<EncodeOptions, JsValue> {
+    let options: Option<EncodeOptions> = serde_wasm_bindgen::from_value(options)
+        .map_err(|e| to_js_error(EditorError::InvalidArgument(e.to_string())))?;
+    Ok(options.unwrap_or_default())
+}
+
+/// Reads optional proving rates; undefined means the defaults.
+fn proving_rates(rates: JsValue) -> Result<ProvingRates, JsValue> {
+    let rates: Option<ProvingRates> = serde_wasm_bindgen::from_value(rates)
+        .map_err(|e| to_js_error(EditorError::InvalidArgument(e.to_string())))?;
+    Ok(rates.unwrap_or_default())
+}
+
+/// Converts a result for JS. Failing to serialize it is an `ENCODE` error.
+fn to_js<T: serde::Serialize + ?Sized>(value: &T) -> Result<JsValue, JsValue> {
+    serde_wasm_bindgen::to_value(value).map_err(|e| to_js_error(EditorError::Encode(e.to_string())))
+}
+
+/// Converts an `EditorError` into a JS `Error` whose `code` property carries the stable error code.
+fn to_js_error(error: EditorError) -> JsValue {
+    let js_error = js_sys::Error::new(&error.to_string());
+    js_error.set_name("EditorError");
+    let _ = js_sys::Reflect::set(&js_error, &JsValue::from_str("code"), &JsValue::from_str(error.code()));
+    js_error.into()
+}
 
 #[wasm_bindgen]
 pub struct WasmImageProject(ImageProject);
@@ -12,30 +45,525 @@ impl WasmImageProject {
         Self(ImageProject::new())
     }
 
+    /// Opens a project file produced by `save`.
+    #[wasm_bindgen]
+    pub fn load(data: &[u8]) -> Result<WasmImageProject, JsValue> {
+        ImageProject::load(data)
+            .map(Self)
+            .map_err(to_js_error)
+    }
+
+    /// Serializes the whole document, including original images, edit stacks, masks and fonts.
     #[wasm_bindgen]
-    pub fn add_layer(&mut self, image_data: &[u8]) -> Result<(), JsValue> {
+    pub fn save(&self) -> Result<Vec<u8>, JsValue> {
+        self.0.save()
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn add_layer(&mut self, image_data: &[u8]) -> Result<LayerId, JsValue> {
         self.0.add_layer(image_data)
-            .map_err(|e| JsValue::from_str(&e))
+            .map_err(to_js_error)
     }
 
     #[wasm_bindgen]
-    pub fn transform_layer(&mut self, index: usize, transformation: &JsValue) -> Result<(), JsValue> {
+    pub fn transform_layer(&mut self, id: LayerId, transformation: &JsValue) -> Result<EditId, JsValue> {
         let transformation: Transformation = serde_wasm_bindgen::from_value(transformation.clone())
-            .map_err(|e| JsValue::from_str(&format!("Invalid transformation: {}", e)))?;
+            .map_err(|e| to_js_error(EditorError::InvalidTransformation(e.to_string())))?;
         
-        self.0.transform_layer(index, &transformation)
-            .map_err(|e| JsValue::from_str(&e))
+        self.0.transform_layer(id, &transformation)
+            .map_err(to_js_error)
+    }
+
+    /// The layer's edit stack as an array of `{ id, transformation, enabled }` objects.
+    #[wasm_bindgen]
+    pub fn layer_edits(&self, id: LayerId) -> Result<JsValue, JsValue> {
+        let layer = self.0.layer(id).map_err(to_js_error)?;
+        to_js(layer.edits())
+    }
+
+    #[wasm_bindgen]
+    pub fn set_edit_enabled(&mut self, id: LayerId, edit: EditId, enabled: bool) -> Result<(), JsValue> {
+        self.0.set_edit_enabled(id, edit, enabled)
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn move_edit(&mut self, id: LayerId, edit: EditId, position: usize) -> Result<(), JsValue> {
+        self.0.move_edit(id, edit, position)
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn update_edit(&mut self, id: LayerId, edit: EditId, transformation: &JsValue) -> Result<(), JsValue> {
+        let transformation: Transformation = serde_wasm_bindgen::from_value(transformation.clone())
+            .map_err(|e| to_js_error(EditorError::InvalidTransformation(e.to_string())))?;
+        self.0.update_edit(id, edit, &transformation)
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn remove_edit(&mut self, id: LayerId, edit: EditId) -> Result<(), JsValue> {
+        self.0.remove_edit(id, edit)
+            .map_err(to_js_error)
+    }
+
+    /// The enabled edits of a layer, in the form the prover's `transformations` field expects.
+    #[wasm_bindgen]
+    pub fn layer_transformations(&self, id: LayerId) -> Result<JsValue, JsValue> {
+        let layer = self.0.layer(id).map_err(to_js_error)?;
+        to_js(&layer.transformations())
+    }
+
+    /// The `ImageInput` that proves the visible layers on the project's canvas, as `export` writes
+    /// them with the same `format` and `options`.
+    #[wasm_bindgen]
+    pub fn proof_input(&self, format: Option<String>, options: JsValue) -> Result<JsValue, JsValue> {
+        let input = self.0.proof_input(format, &encode_options(options)?).map_err(to_js_error)?;
+        to_js(&input)
+    }
+
+    /// Estimates what proving `proof_input(format, options)` costs, as a `ProofEstimate` object,
+    /// without running anything. `rates` are optional `ProvingRates`.
+    #[wasm_bindgen]
+    pub fn estimate_proof(&self, format: Option<String>, options: JsValue, rates: JsValue) -> Result<JsValue, JsValue> {
+        let input = self.0.proof_input(format, &encode_options(options)?).map_err(to_js_error)?;
+        let estimate = crate::estimate_proof(&input, &proving_rates(rates)?).map_err(to_js_error)?;
+        to_js(&estimate)
+    }
+
+    /// The layer's source metadata as an `ImageMetadata` object.
+    #[wasm_bindgen]
+    pub fn layer_metadata(&self, id: LayerId) -> Result<JsValue, JsValue> {
+        let metadata = self.0.layer_metadata(id).map_err(to_js_error)?;
+        to_js(&metadata)
     }
 
+    /// Encodes one layer. `options` holds `EncodeOptions` and may be undefined.
     #[wasm_bindgen]
-    pub fn get_layer(&self, index: usize, format: Option<String>) -> Result<Vec<u8>, JsValue> {
-        self.0.get_layer(index, format)
-            .map_err(|e| JsValue::from_str(&e))
+    pub fn get_layer(&self, id: LayerId, format: Option<String>, options: JsValue) -> Result<Vec<u8>, JsValue> {
+        self.0.get_layer(id, format, &encode_options(options)?)
+            .map_err(to_js_error)
     }
 
     #[wasm_bindgen]
-    pub fn add_empty_layer(&mut self, width: u32, height: u32) -> Result<(), JsValue> {
+    pub fn add_empty_layer(&mut self, width: u32, height: u32) -> Result<LayerId, JsValue> {
         self.0.add_empty_layer(width, height)
-            .map_err(|e| JsValue::from_str(&e))
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn remove_layer(&mut self, id: LayerId) -> Result<(), JsValue> {
+        self.0.remove_layer(id)
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn move_layer(&mut self, id: LayerId, position: usize) -> Result<(), JsValue> {
+        self.0.move_layer(id, position)
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn reorder_layers(&mut self, ids: Vec<LayerId>) -> Result<(), JsValue> {
+        self.0.reorder_layers(&ids)
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn duplicate_layer(&mut self, id: LayerId) -> Result<LayerId, JsValue> {
+        self.0.duplicate_layer(id)
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn rename_layer(&mut self, id: LayerId, name: &str) -> Result<(), JsValue> {
+        self.0.rename_layer(id, name)
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn set_layer_visible(&mut self, id: LayerId, visible: bool) -> Result<(), JsValue> {
+        self.0.set_layer_visible(id, visible)
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn set_layer_locked(&mut self, id: LayerId, locked: bool) -> Result<(), JsValue> {
+        self.0.set_layer_locked(id, locked)
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn set_layer_opacity(&mut self, id: LayerId, opacity: f32) -> Result<(), JsValue> {
+        self.0.set_layer_opacity(id, opacity)
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn set_layer_position(&mut self, id: LayerId, x: i32, y: i32) -> Result<(), JsValue> {
+        self.0.set_layer_position(id, x, y)
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn translate_layer(&mut self, id: LayerId, dx: i32, dy: i32) -> Result<(), JsValue> {
+        self.0.translate_layer(id, dx, dy)
+            .map_err(to_js_error)
+    }
+
+    /// `horizontal` is `"Left"`, `"Center"`, `"Right"` or undefined; `vertical` is `"Top"`,
+    /// `"Middle"`, `"Bottom"` or undefined.
+    #[wasm_bindgen]
+    pub fn align_layer(&mut self, id: LayerId, horizontal: JsValue, vertical: JsValue) -> Result<(), JsValue> {
+        let horizontal: Option<HorizontalAlign> = serde_wasm_bindgen::from_value(horizontal)
+            .map_err(|e| to_js_error(EditorError::InvalidArgument(e.to_string())))?;
+        let vertical: Option<VerticalAlign> = serde_wasm_bindgen::from_value(vertical)
+            .map_err(|e| to_js_error(EditorError::InvalidArgument(e.to_string())))?;
+        self.0.align_layer(id, horizontal, vertical)
+            .map_err(to_js_error)
+    }
+
+    /// Adds a mask revealing `selection`, or the whole layer when `selection` is undefined.
+    #[wasm_bindgen]
+    pub fn add_layer_mask(&mut self, id: LayerId, selection: JsValue) -> Result<(), JsValue> {
+        let selection: Option<Selection> = serde_wasm_bindgen::from_value(selection)
+            .map_err(|e| to_js_error(EditorError::InvalidArgument(e.to_string())))?;
+        self.0.add_layer_mask(id, selection.as_ref())
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn remove_layer_mask(&mut self, id: LayerId) -> Result<(), JsValue> {
+        self.0.set_layer_mask(id, None)
+            .map_err(to_js_error)
+    }
+
+    /// Replaces the mask with the luminance of an encoded image the size of the layer.
+    #[wasm_bindgen]
+    pub fn set_layer_mask(&mut self, id: LayerId, image_data: &[u8]) -> Result<(), JsValue> {
+        let image = decode(image_data).map_err(to_js_error)?;
+        self.0.set_layer_mask(id, Some(LayerMask::from_image(&image)))
+            .map_err(to_js_error)
+    }
+
+    /// The layer's mask as a grayscale PNG, or undefined when it has none.
+    #[wasm_bindgen]
+    pub fn get_layer_mask(&self, id: LayerId) -> Result<Option<Vec<u8>>, JsValue> {
+        let layer = self.0.layer(id).map_err(to_js_error)?;
+        layer.mask()
+            .map(|mask| mask.to_png())
+            .transpose()
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn invert_layer_mask(&mut self, id: LayerId) -> Result<(), JsValue> {
+        self.0.invert_layer_mask(id)
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn fill_layer_mask(&mut self, id: LayerId, selection: JsValue, value: u8) -> Result<(), JsValue> {
+        let selection: Selection = serde_wasm_bindgen::from_value(selection)
+            .map_err(|e| to_js_error(EditorError::InvalidArgument(e.to_string())))?;
+        self.0.fill_layer_mask(id, &selection, value)
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn paint_layer_mask(&mut self, id: LayerId, stroke: JsValue) -> Result<(), JsValue> {
+        let stroke: BrushStroke = serde_wasm_bindgen::from_value(stroke)
+            .map_err(|e| to_js_error(EditorError::InvalidArgument(e.to_string())))?;
+        self.0.paint_layer_mask(id, &stroke)
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn set_layer_clipped(&mut self, id: LayerId, clipped: bool) -> Result<(), JsValue> {
+        self.0.set_layer_clipped(id, clipped)
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn set_canvas_size(&mut self, width: u32, height: u32) -> Result<(), JsValue> {
+        self.0.set_canvas_size(width, height)
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn canvas_width(&self) -> u32 {
+        self.0.canvas_size().0
+    }
+
+    #[wasm_bindgen]
+    pub fn canvas_height(&self) -> u32 {
+        self.0.canvas_size().1
+    }
+
+    /// Composites all visible layers onto the canvas and encodes the result.
+    #[wasm_bindgen]
+    pub fn export(&self, format: Option<String>, options: JsValue) -> Result<Vec<u8>, JsValue> {
+        self.0.export(format, &encode_options(options)?)
+            .map_err(to_js_error)
     }
-}
\ No newline at end of file
+
+    /// Reverts the last edit; resolves to `false` when there is nothing to undo.
+    #[wasm_bindgen]
+    pub fn undo(&mut self) -> Result<bool, JsValue> {
+        self.0.undo()
+            .map_err(to_js_error)
+    }
+
+    /// Reapplies the last undone edit; resolves to `false` when there is nothing to redo.
+    #[wasm_bindgen]
+    pub fn redo(&mut self) -> Result<bool, JsValue> {
+        self.0.redo()
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn can_undo(&self) -> bool {
+        self.0.can_undo()
+    }
+
+    #[wasm_bindgen]
+    pub fn can_redo(&self) -> bool {
+        self.0.can_redo()
+    }
+
+    #[wasm_bindgen]
+    pub fn checkpoint(&mut self, name: &str) {
+        self.0.checkpoint(name)
+    }
+
+    #[wasm_bindgen]
+    pub fn restore_checkpoint(&mut self, name: &str) -> Result<(), JsValue> {
+        self.0.restore_checkpoint(name)
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn checkpoint_names(&self) -> Vec<String> {
+        self.0.checkpoint_names()
+    }
+
+    /// Limits the memory, in bytes, used by the undo history.
+    #[wasm_bindgen]
+    pub fn set_history_budget(&mut self, bytes: usize) {
+        self.0.set_history_budget(bytes)
+    }
+
+    #[wasm_bindgen]
+    pub fn set_keyframe_interval(&mut self, edits: usize) {
+        self.0.set_keyframe_interval(edits)
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_history(&mut self) {
+        self.0.clear_history()
+    }
+
+    #[wasm_bindgen]
+    pub fn layer_ids(&self) -> Vec<LayerId> {
+        self.0.layer_ids()
+    }
+
+    /// Returns the layer stack, bottom first, as an array of `LayerInfo` objects.
+    #[wasm_bindgen]
+    pub fn layers(&self) -> Result<JsValue, JsValue> {
+        to_js(&self.0.layers())
+    }
+
+    #[wasm_bindgen]
+    pub fn add_font(&mut self, name: &str, data: &[u8]) -> Result<(), JsValue> {
+        self.0.add_font(name, data)
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn remove_font(&mut self, name: &str) -> bool {
+        self.0.remove_font(name)
+    }
+
+    #[wasm_bindgen]
+    pub fn font_names(&self) -> Vec<String> {
+        self.0.font_names()
+    }
+
+    /// Returns the `TextBounds` the given text overlay parameters will occupy when rendered.
+    #[wasm_bindgen]
+    pub fn measure_text(&self, params: &JsValue) -> Result<JsValue, JsValue> {
+        let params: TextOverlayParameters = serde_wasm_bindgen::from_value(params.clone())
+            .map_err(|e| to_js_error(EditorError::InvalidTransformation(e.to_string())))?;
+        let bounds = self.0.measure_text(&params).map_err(to_js_error)?;
+        to_js(&bounds)
+    }
+}
+
+impl Default for WasmImageProject {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Frames of an animated GIF, PNG or WebP, edited all at once or by frame range.
+#[wasm_bindgen]
+pub struct WasmAnimation {
+    animation: Animation,
+    fonts: FontRegistry,
+}
+
+#[wasm_bindgen]
+impl WasmAnimation {
+    #[wasm_bindgen(constructor)]
+    pub fn new(image_data: &[u8]) -> Result<WasmAnimation, JsValue> {
+        let animation = Animation::new(image_data).map_err(to_js_error)?;
+        Ok(Self { animation, fonts: FontRegistry::new() })
+    }
+
+    #[wasm_bindgen]
+    pub fn frame_count(&self) -> usize {
+        self.animation.frame_count()
+    }
+
+    #[wasm_bindgen]
+    pub fn width(&self) -> u32 {
+        self.animation.size().0
+    }
+
+    #[wasm_bindgen]
+    pub fn height(&self) -> u32 {
+        self.animation.size().1
+    }
+
+    /// How many times the animation plays, 0 meaning forever.
+    #[wasm_bindgen]
+    pub fn loop_count(&self) -> u32 {
+        self.animation.loop_count()
+    }
+
+    #[wasm_bindgen]
+    pub fn frame_delays(&self) -> Vec<u32> {
+        self.animation.frames().iter().map(|frame| frame.delay_ms).collect()
+    }
+
+    /// One frame as a PNG, for previews.
+    #[wasm_bindgen]
+    pub fn frame(&self, index: usize) -> Result<Vec<u8>, JsValue> {
+        let frame = self.animation.frames().get(index)
+            .ok_or_else(|| to_js_error(EditorError::InvalidArgument(format!("frame {} does not exist", index))))?;
+        encode(&DynamicImage::ImageRgba8(frame.image.clone()), ImageFormat::Png)
+            .map_err(to_js_error)
+    }
+
+    #[wasm_bindgen]
+    pub fn add_font(&mut self, name: &str, data: &[u8]) -> Result<(), JsValue> {
+        self.fonts.register(name, data.to_vec())
+            .map_err(to_js_error)
+    }
+
+    /// Applies a transformation to the frames from `start` up to `end`, or to every frame when
+    /// both are undefined.
+    #[wasm_bindgen]
+    pub fn transform(&mut self, transformation: &JsValue, start: Option<usize>, end: Option<usize>) -> Result<(), JsValue> {
+        let transformation: Transformation = serde_wasm_bindgen::from_value(transformation.clone())
+            .map_err(|e| to_js_error(EditorError::InvalidTransformation(e.to_string())))?;
+        let frames = match (start, end) {
+            (None, None) => None,
+            (start, end) => Some(FrameRange {
+                start: start.unwrap_or(0),
+                end: end.unwrap_or(self.animation.frame_count()),
+            }),
+        };
+        self.animation.apply(FrameEdit { transformation, frames }, &self.fonts)
+            .map_err(to_js_error)
+    }
+
+    /// Encodes the animation as `gif` or `png` (APNG).
+    #[wasm_bindgen]
+    pub fn export(&self, format: &str) -> Result<Vec<u8>, JsValue> {
+        let format = parse_format(format).map_err(to_js_error)?;
+        self.animation.encode(format)
+            .map_err(to_js_error)
+    }
+
+    /// The `ImageInput` that proves the edits so far, written as an animated `gif` or `png`.
+    /// Fonts added with `add_font` must be added to its `fonts` by the caller.
+    #[wasm_bindgen]
+    pub fn proof_input(&self, format: &str) -> Result<JsValue, JsValue> {
+        let format: OutputFormat = format.parse().map_err(to_js_error)?;
+        to_js(&self.animation.proof_input(format))
+    }
+}
+
+/// Formats `add_layer` accepts in this build.
+#[wasm_bindgen]
+pub fn input_formats() -> Vec<String> {
+    crate::input_formats().into_iter().map(str::to_string).collect()
+}
+
+/// Formats `get_layer` and `export` can write in this build.
+#[wasm_bindgen]
+pub fn output_formats() -> Vec<String> {
+    crate::output_formats().into_iter().map(str::to_string).collect()
+}
+
+/// Detects the format of encoded image data, or undefined when it is not recognized.
+#[wasm_bindgen]
+pub fn detect_format(image_data: &[u8]) -> Option<String> {
+    crate::sniff_format(image_data).map(|format| crate::format_name(format).to_string())
+}
+
+/// The transformations of an array that tiled proofs cannot apply, as `{ index, reason }` objects.
+#[wasm_bindgen]
+pub fn full_frame_transformations(transformations: &JsValue) -> Result<JsValue, JsValue> {
+    let transformations: Vec<Transformation> = serde_wasm_bindgen::from_value(transformations.clone())
+        .map_err(|e| to_js_error(EditorError::InvalidTransformation(e.to_string())))?;
+    to_js(&crate::full_frame_transformations(&transformations))
+}
+
+/// Reads the EXIF fields, orientation and XMP of encoded image data as an `ImageMetadata` object.
+#[wasm_bindgen]
+pub fn read_metadata(image_data: &[u8]) -> Result<JsValue, JsValue> {
+    let metadata = crate::read_metadata(image_data).map_err(to_js_error)?;
+    to_js(&metadata)
+}
+
+/// Renders the conformance cases in this build and returns a `{ name, expected_hash,
+/// actual_hash, passed }` object for each, to check the preview matches the prover. Only in
+/// builds with the `conformance` feature.
+#[cfg(feature = "conformance")]
+#[wasm_bindgen]
+pub fn run_conformance() -> Result<JsValue, JsValue> {
+    let results = crate::run_conformance().map_err(to_js_error)?;
+    to_js(&results)
+}
+
+/// SHA-256 of `data`, exactly as the program hashes images, fonts and layers.
+#[wasm_bindgen]
+pub fn sha256(data: &[u8]) -> Vec<u8> {
+    crate::hash::sha256(data).to_vec()
+}
+
+#[wasm_bindgen]
+pub fn keccak256(data: &[u8]) -> Vec<u8> {
+    crate::hash::keccak256(data).to_vec()
+}
+
+/// The hash a wallet signs for `personal_sign` of `message` as lowercase hex, which the program
+/// checks owner signatures of the original image hash against.
+#[wasm_bindgen]
+pub fn ethereum_message_hash(message: &[u8]) -> Vec<u8> {
+    crate::hash::ethereum_message_hash(message).to_vec()
+}
+
+/// Estimates what proving an `ImageInput`, such as an animation's `proof_input`, costs as a
+/// `ProofEstimate` object. `rates` are optional `ProvingRates`.
+#[wasm_bindgen]
+pub fn estimate_proof(input: &JsValue, rates: JsValue) -> Result<JsValue, JsValue> {
+    let input: ImageInput = serde_wasm_bindgen::from_value(input.clone())
+        .map_err(|e| to_js_error(EditorError::InvalidArgument(e.to_string())))?;
+    let estimate = crate::estimate_proof(&input, &proving_rates(rates)?).map_err(to_js_error)?;
+    to_js(&estimate)
+}
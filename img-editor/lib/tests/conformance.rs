@@ -0,0 +1,16 @@
+//! Runs the conformance cases natively under `cargo test`, and in a WASM runtime with
+//! `wasm-pack test --node lib`. The script's `conformance` binary executes the same cases in the
+//! zkVM.
+use img_editor_lib::run_conformance;
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn conformance_cases_match_their_hashes() {
+    let failures: Vec<String> = run_conformance()
+        .expect("conformance cases render")
+        .into_iter()
+        .filter(|result| !result.passed)
+        .map(|result| format!("{}: got {}, expected {}", result.name, result.actual_hash, result.expected_hash))
+        .collect();
+    assert!(failures.is_empty(), "conformance failures:\n{}", failures.join("\n"));
+}
@@ -0,0 +1,28 @@
+//! Flattening mixes every pixel with the background in integers, at the depth of the image.
+use image::{DynamicImage, ImageBuffer, ImageFormat, Rgb, Rgba};
+use img_editor_lib::{AlphaHandling, EncodeOptions, ImageProject};
+use std::io::Cursor;
+
+fn flatten_options(background: &str) -> EncodeOptions {
+    EncodeOptions { alpha: AlphaHandling::Flatten { background: background.to_string() }, ..EncodeOptions::default() }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn sixteen_bit_images_flatten_at_sixteen_bits() {
+    let pixels = [[65535, 0, 1000, 32768], [4321, 65535, 0, 0], [100, 200, 300, 65535], [65535, 65535, 65535, 1]];
+    let image = ImageBuffer::from_fn(4, 1, |x, _| Rgba::<u16>(pixels[x as usize]));
+    let mut png = Vec::new();
+    DynamicImage::ImageRgba16(image).write_to(&mut Cursor::new(&mut png), ImageFormat::Png).unwrap();
+
+    let mut project = ImageProject::new();
+    let id = project.add_layer(&png).unwrap();
+    let flattened = project.get_layer(id, None, &flatten_options("#0000ff")).unwrap();
+    let flattened = image::load_from_memory(&flattened).unwrap();
+    let DynamicImage::ImageRgb16(flattened) = flattened else {
+        panic!("expected a 16-bit RGB image, got {:?}", flattened.color());
+    };
+    let expected = [[32768, 0, 33267], [0, 0, 65535], [100, 200, 300], [1, 1, 65535]];
+    let expected: Vec<Rgb<u16>> = expected.into_iter().map(Rgb).collect();
+    assert_eq!(flattened.pixels().copied().collect::<Vec<_>>(), expected);
+}
@@ -0,0 +1,47 @@
+//! Custom fonts draw exactly like the bundled font they were copied from.
+use img_editor_lib::{
+    Anchor, FontRegistry, HorizontalAlign, Layer, TextOverlayParameters, Transformation, VerticalAlign, DEFAULT_FONT,
+};
+
+const IMPACT: &[u8] = include_bytes!("../assets/impact.ttf");
+
+fn draw(font: Option<&str>, fonts: &FontRegistry) -> Vec<u8> {
+    let params = TextOverlayParameters {
+        text: "Fonts ﬁne".to_string(),
+        x: 4,
+        y: 4,
+        size: 20,
+        color: "#ff8000".to_string(),
+        max_width: None,
+        line_height: 1.0,
+        align: HorizontalAlign::default(),
+        vertical_align: VerticalAlign::default(),
+        anchor: Anchor::default(),
+        rotation: 0.0,
+        letter_spacing: 0.0,
+        stroke: None,
+        shadow: None,
+        font: font.map(str::to_string),
+    };
+    let mut layer = Layer::new_empty(128, 32).unwrap();
+    layer.apply_transformation_with_fonts(Transformation::TextOverlay(params), fonts).unwrap();
+    layer.image().as_bytes().to_vec()
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn registered_fonts_draw_like_the_bundled_font() {
+    let bundled = draw(None, &FontRegistry::new());
+    let mut fonts = FontRegistry::new();
+    fonts.register("copy", IMPACT.to_vec()).unwrap();
+    assert_eq!(draw(Some("copy"), &fonts), bundled);
+    assert_eq!(fonts.custom_fonts().map(|font| font.data.as_slice()).collect::<Vec<_>>(), [IMPACT]);
+
+    // Shadowing the bundled name and removing the font again
+    fonts.register(DEFAULT_FONT, IMPACT.to_vec()).unwrap();
+    assert_eq!(draw(None, &fonts), bundled);
+    assert!(fonts.remove(DEFAULT_FONT));
+    assert!(fonts.remove("copy"));
+    assert_eq!(draw(None, &fonts), bundled);
+    assert!(fonts.register("broken", IMPACT[..64].to_vec()).is_err());
+}
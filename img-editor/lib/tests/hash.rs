@@ -0,0 +1,25 @@
+//! Checks every published hash test vector, natively under `cargo test` and in a WASM
+//! runtime with `wasm-pack test --node lib`.
+use img_editor_lib::check_known_answers;
+use img_editor_lib::hash::{sha256, Sha256};
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn hashes_match_their_known_answers() {
+    let results = check_known_answers();
+    assert!(!results.is_empty());
+    for result in results {
+        assert!(result.passed, "{}: expected {}, got {}", result.name, result.expected_hash, result.actual_hash);
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn sha256_fed_in_pieces_matches_one_call() {
+    let data = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq".repeat(3);
+    let mut hasher = Sha256::new();
+    for piece in data.chunks(7) {
+        hasher.update(piece);
+    }
+    assert_eq!(hasher.finalize(), sha256(&data));
+}
@@ -0,0 +1,112 @@
+//! Undo, redo and checkpoints rebuild exactly the states they return to.
+use image::{ImageFormat, Rgba, RgbaImage};
+use img_editor_lib::{EncodeOptions, ImageProject, Transformation};
+use std::io::Cursor;
+
+fn png(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [u8; 4]) -> Vec<u8> {
+    let image = RgbaImage::from_fn(width, height, |x, y| Rgba(pixel(x, y)));
+    let mut bytes = Vec::new();
+    image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png).expect("PNG encodes");
+    bytes
+}
+
+fn export(project: &ImageProject) -> Vec<u8> {
+    project.export(None, &EncodeOptions::default()).unwrap()
+}
+
+/// Runs a mix of pixel, layer and stack edits on two layers and returns the export after each.
+fn edit(project: &mut ImageProject, edits: usize) -> Vec<Vec<u8>> {
+    let background = project.add_layer(&png(16, 16, |x, y| [(x * 16) as u8, (y * 16) as u8, 90, 255])).unwrap();
+    let mut states = vec![export(project)];
+    let mut layers = vec![background];
+    for step in 0..edits {
+        let id = layers[step % layers.len()];
+        match step % 5 {
+            0 => { project.transform_layer(id, &Transformation::Brighten { value: 12, region: None }).unwrap(); },
+            1 => layers.push(project.add_layer(&png(8, 8, |x, _| [30, 200, 40, (x * 32) as u8])).unwrap()),
+            2 => project.translate_layer(id, 1, 2).unwrap(),
+            3 => { project.transform_layer(id, &Transformation::Rotate90).unwrap(); },
+            _ => project.set_layer_opacity(id, 0.5).unwrap(),
+        }
+        states.push(export(project));
+    }
+    states
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn undo_and_redo_return_to_every_state() {
+    for interval in [1, 3, 10] {
+        let mut project = ImageProject::new();
+        project.set_keyframe_interval(interval);
+        let states = edit(&mut project, 12);
+
+        for state in states.iter().rev().skip(1) {
+            assert!(project.undo().unwrap());
+            assert_eq!(&export(&project), state, "undo with a keyframe every {interval} edits");
+        }
+        // Back to before the first layer was added
+        assert!(project.undo().unwrap());
+        assert!(!project.can_undo());
+        assert!(project.redo().unwrap());
+        assert_eq!(export(&project), states[0]);
+        for state in &states[1..] {
+            assert!(project.redo().unwrap());
+            assert_eq!(&export(&project), state, "redo with a keyframe every {interval} edits");
+        }
+        assert!(!project.can_redo());
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn checkpoints_restore_across_keyframes() {
+    let mut project = ImageProject::new();
+    project.set_keyframe_interval(2);
+    let before = edit(&mut project, 3);
+    project.checkpoint("middle");
+    let after = edit(&mut project, 7);
+
+    project.restore_checkpoint("middle").unwrap();
+    assert_eq!(export(&project), before[3]);
+    assert!(project.can_redo());
+    project.redo().unwrap();
+    assert_eq!(export(&project), after[0]);
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn a_small_budget_forgets_the_oldest_edits() {
+    let mut project = ImageProject::new();
+    project.set_keyframe_interval(3);
+    let states = edit(&mut project, 9);
+    project.checkpoint("latest");
+    project.set_history_budget(0);
+
+    // Only the edit after the latest keyframe, at position 9, can still be undone
+    assert!(project.undo().unwrap());
+    assert_eq!(export(&project), states[8]);
+    assert!(!project.can_undo());
+    project.restore_checkpoint("latest").unwrap();
+    assert_eq!(export(&project), states[9]);
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn keyframes_share_the_layers_they_did_not_change() {
+    let mut project = ImageProject::new();
+    project.set_keyframe_interval(1);
+    // A megabyte of pixels that a copy in every keyframe would quickly run over budget with
+    project.add_layer(&png(512, 512, |x, y| [x as u8, y as u8, 0, 255])).unwrap();
+    let small = project.add_layer(&png(4, 4, |_, _| [0, 0, 255, 255])).unwrap();
+    project.set_history_budget(4 * 1024 * 1024);
+    for _ in 0..20 {
+        project.translate_layer(small, 1, 1).unwrap();
+    }
+
+    let mut undone = 0;
+    while project.undo().unwrap() {
+        undone += 1;
+    }
+    assert_eq!(undone, 22);
+}
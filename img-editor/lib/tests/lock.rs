@@ -0,0 +1,80 @@
+//! Locked layers refuse every change to how they draw, and only those.
+use image::{ImageFormat, Rgba, RgbaImage};
+use img_editor_lib::{
+    EditorError, EditorResult, EncodeOptions, HorizontalAlign, ImageProject, LayerId, Selection, Transformation,
+};
+use std::io::Cursor;
+
+fn png(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [u8; 4]) -> Vec<u8> {
+    let image = RgbaImage::from_fn(width, height, |x, y| Rgba(pixel(x, y)));
+    let mut bytes = Vec::new();
+    image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png).expect("PNG encodes");
+    bytes
+}
+
+fn export(project: &ImageProject) -> Vec<u8> {
+    project.export(None, &EncodeOptions::default()).unwrap()
+}
+
+/// A change to the project, given the background and the locked layer.
+type Change = fn(&mut ImageProject, LayerId, LayerId) -> EditorResult<()>;
+
+/// A background and a locked layer with one edit and a mask above it.
+fn locked_project() -> (ImageProject, LayerId, LayerId) {
+    let mut project = ImageProject::new();
+    let background = project.add_layer(&png(16, 16, |x, y| [(x * 16) as u8, (y * 16) as u8, 100, 255])).unwrap();
+    let locked = project.add_layer(&png(8, 8, |x, _| [240, 20, 20, (x * 32) as u8])).unwrap();
+    project.transform_layer(locked, &Transformation::Rotate90).unwrap();
+    project.add_layer_mask(locked, None).unwrap();
+    project.set_layer_locked(locked, true).unwrap();
+    (project, background, locked)
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn locked_layers_refuse_changes_to_how_they_draw() {
+    let changes: &[(&str, Change)] = &[
+        ("transform", |project, _, id| project.transform_layer(id, &Transformation::Rotate180).map(|_| ())),
+        ("disable edit", |project, _, id| project.set_edit_enabled(id, 0, false)),
+        ("update edit", |project, _, id| project.update_edit(id, 0, &Transformation::Rotate270)),
+        ("remove edit", |project, _, id| project.remove_edit(id, 0)),
+        ("opacity", |project, _, id| project.set_layer_opacity(id, 0.5)),
+        ("position", |project, _, id| project.set_layer_position(id, 3, 3)),
+        ("translate", |project, _, id| project.translate_layer(id, 1, 0)),
+        ("align", |project, _, id| project.align_layer(id, Some(HorizontalAlign::Right), None)),
+        ("clip", |project, _, id| project.set_layer_clipped(id, true)),
+        ("replace mask", |project, _, id| project.add_layer_mask(id, Some(&Selection::All))),
+        ("remove mask", |project, _, id| project.set_layer_mask(id, None)),
+        ("invert mask", |project, _, id| project.invert_layer_mask(id)),
+        ("move", |project, _, id| project.move_layer(id, 0)),
+        ("reorder", |project, background, id| project.reorder_layers(&[id, background])),
+        ("remove", |project, _, id| project.remove_layer(id)),
+    ];
+    for (name, change) in changes {
+        let (mut project, background, locked) = locked_project();
+        let before = export(&project);
+        match change(&mut project, background, locked) {
+            Err(EditorError::LayerLocked(id)) => assert_eq!(id, locked, "{name}"),
+            other => panic!("{name}: expected LayerLocked, got {other:?}"),
+        }
+        assert_eq!(export(&project), before, "{name}");
+        project.undo().unwrap();
+        assert!(!project.layers()[1].locked, "{name} must not be journaled");
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn locked_layers_can_be_renamed_hidden_duplicated_and_unlocked() {
+    let (mut project, _, locked) = locked_project();
+    project.rename_layer(locked, "Logo").unwrap();
+    project.set_layer_visible(locked, false).unwrap();
+    let copy = project.duplicate_layer(locked).unwrap();
+    project.translate_layer(copy, 2, 2).unwrap();
+    project.set_layer_locked(locked, false).unwrap();
+    project.set_layer_opacity(locked, 0.5).unwrap();
+
+    let layers = project.layers();
+    assert_eq!((layers[1].name.as_str(), layers[1].visible, layers[1].locked), ("Logo", false, false));
+    assert_eq!((layers[2].name.as_str(), layers[2].locked, layers[2].x), ("Logo copy", false, 2));
+}
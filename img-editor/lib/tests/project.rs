@@ -0,0 +1,60 @@
+//! Projects prove exactly what they export.
+use image::{ImageFormat, Rgba, RgbaImage};
+use img_editor_lib::{render_input, AlphaHandling, EncodeOptions, ImageProject, JpegOptions, OutputFormat};
+use std::io::Cursor;
+
+fn png(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [u8; 4]) -> Vec<u8> {
+    let image = RgbaImage::from_fn(width, height, |x, y| Rgba(pixel(x, y)));
+    let mut bytes = Vec::new();
+    image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png).expect("PNG encodes");
+    bytes
+}
+
+/// An opaque background, a half transparent clipping base and a layer clipped to it.
+fn clipping_project() -> (ImageProject, u32) {
+    let mut project = ImageProject::new();
+    project.add_layer(&png(16, 16, |x, y| [200, (x * 16) as u8, (y * 16) as u8, 255])).unwrap();
+    let base = project.add_layer(&png(8, 8, |x, _| [0, 0, 0, if x < 4 { 255 } else { 0 }])).unwrap();
+    project.set_layer_position(base, 4, 4).unwrap();
+    let clipped = project.add_layer(&png(16, 16, |_, _| [20, 40, 250, 255])).unwrap();
+    project.set_layer_clipped(clipped, true).unwrap();
+    (project, base)
+}
+
+fn assert_proof_matches_export(project: &ImageProject, format: Option<&str>, options: &EncodeOptions) {
+    let format = format.map(str::to_string);
+    let input = project.proof_input(format.clone(), options).unwrap();
+    let proven = render_input(&input, project.fonts()).unwrap();
+    assert_eq!(proven, project.export(format, options).unwrap());
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn proof_of_a_clipping_group_matches_the_export() {
+    let (project, _) = clipping_project();
+    assert_proof_matches_export(&project, None, &EncodeOptions::default());
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn proof_with_a_hidden_clipping_base_matches_the_export() {
+    let (mut project, base) = clipping_project();
+    project.set_layer_visible(base, false).unwrap();
+    assert_eq!(project.proof_input(None, &EncodeOptions::default()).unwrap().layers.len(), 0);
+    assert_proof_matches_export(&project, None, &EncodeOptions::default());
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn proof_uses_the_export_format_and_options() {
+    let (project, _) = clipping_project();
+    let options = EncodeOptions {
+        jpeg: JpegOptions { quality: 40, ..JpegOptions::default() },
+        alpha: AlphaHandling::Flatten { background: "#00ff00".to_string() },
+        ..EncodeOptions::default()
+    };
+    let input = project.proof_input(Some("jpeg".to_string()), &options).unwrap();
+    assert_eq!(input.output_format, OutputFormat::Jpeg);
+    assert_eq!(input.encode_options, options);
+    assert_proof_matches_export(&project, Some("jpeg"), &options);
+}
@@ -0,0 +1,97 @@
+//! Saved projects load back pixel for pixel.
+use ciborium::Value;
+use image::{ImageFormat, Rgba, RgbaImage};
+use img_editor_lib::{
+    CropParameters, EncodeOptions, ImageProject, Region, Selection, Transformation, PROJECT_FORMAT_VERSION,
+};
+use std::io::Cursor;
+
+/// `sample_project` saved by the first version of the project file layout.
+const PROJECT_V1: &[u8] = include_bytes!("fixtures/project_v1.cbor");
+
+fn png(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [u8; 4]) -> Vec<u8> {
+    let image = RgbaImage::from_fn(width, height, |x, y| Rgba(pixel(x, y)));
+    let mut bytes = Vec::new();
+    image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png).expect("PNG encodes");
+    bytes
+}
+
+/// Layers with cropped, disabled and region edits, a mask, clipping, and every layer property
+/// changed from its default.
+fn sample_project() -> ImageProject {
+    let mut project = ImageProject::new();
+    let background = project.add_layer(&png(24, 16, |x, y| [(x * 10) as u8, (y * 15) as u8, 120, 255])).unwrap();
+    project.transform_layer(background, &Transformation::Crop(CropParameters { x: 2, y: 3, width: 18, height: 12 })).unwrap();
+    let disabled = project.transform_layer(background, &Transformation::Rotate90).unwrap();
+    project.set_edit_enabled(background, disabled, false).unwrap();
+    project.transform_layer(background, &Transformation::Brighten {
+        value: 40,
+        region: Some(Region { x: 1, y: 1, width: 6, height: 5 }),
+    }).unwrap();
+    project.set_layer_locked(background, true).unwrap();
+
+    let overlay = project.add_layer(&png(10, 10, |x, _| [250, 30, 60, (x * 25) as u8])).unwrap();
+    project.set_layer_position(overlay, 5, -2).unwrap();
+    project.set_layer_opacity(overlay, 0.75).unwrap();
+    project.add_layer_mask(overlay, Some(&Selection::Ellipse(Region { x: 0, y: 0, width: 8, height: 10 }))).unwrap();
+    project.rename_layer(overlay, "Overlay").unwrap();
+
+    let copy = project.duplicate_layer(overlay).unwrap();
+    project.set_layer_clipped(copy, true).unwrap();
+    project.set_layer_visible(copy, false).unwrap();
+    project
+}
+
+fn assert_same_project(loaded: &ImageProject, expected: &ImageProject) {
+    assert_eq!(loaded.canvas_size(), expected.canvas_size());
+    assert_eq!(loaded.layers(), expected.layers());
+    for id in expected.layer_ids() {
+        let (loaded, expected) = (loaded.layer(id).unwrap(), expected.layer(id).unwrap());
+        assert_eq!(loaded.image(), expected.image(), "pixels of layer {id}");
+    }
+    let options = EncodeOptions::default();
+    assert_eq!(loaded.export(None, &options).unwrap(), expected.export(None, &options).unwrap());
+}
+
+/// The fixture with its version field replaced.
+fn with_version(version: u32) -> Vec<u8> {
+    let mut document: Value = ciborium::from_reader(PROJECT_V1).unwrap();
+    for (key, value) in document.as_map_mut().unwrap() {
+        if key.as_text() == Some("version") {
+            *value = Value::Integer(version.into());
+        }
+    }
+    let mut bytes = Vec::new();
+    ciborium::into_writer(&document, &mut bytes).unwrap();
+    bytes
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn saved_projects_load_unchanged() {
+    let project = sample_project();
+    let saved = project.save().unwrap();
+    let loaded = ImageProject::load(&saved).unwrap();
+    assert_same_project(&loaded, &project);
+    assert_eq!(loaded.save().unwrap(), saved);
+    assert!(!loaded.can_undo());
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn version_1_files_still_load() {
+    assert_eq!(PROJECT_FORMAT_VERSION, 1, "add a fixture for the new version and keep this one");
+    let loaded = ImageProject::load(PROJECT_V1).unwrap();
+    let expected = sample_project();
+    assert_same_project(&loaded, &expected);
+    assert_eq!(loaded.save().unwrap(), expected.save().unwrap());
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn unknown_versions_are_rejected() {
+    for version in [0, PROJECT_FORMAT_VERSION + 1] {
+        let error = ImageProject::load(&with_version(version)).err().expect("the version is rejected");
+        assert_eq!(error.code(), "INVALID_PROJECT", "version {version}");
+    }
+}
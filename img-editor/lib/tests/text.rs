@@ -0,0 +1,76 @@
+//! Text effects are bounded so that no overlay, however it is specified, takes long to draw.
+use img_editor_lib::{
+    Anchor, EditorError, FontRegistry, HorizontalAlign, Layer, TextOverlayParameters, TextShadow, TextStroke, Transformation,
+    VerticalAlign,
+};
+
+fn overlay() -> TextOverlayParameters {
+    TextOverlayParameters {
+        text: "Hello".to_string(),
+        x: 8,
+        y: 8,
+        size: 24,
+        color: "#ffffff".to_string(),
+        max_width: None,
+        line_height: 1.0,
+        align: HorizontalAlign::default(),
+        vertical_align: VerticalAlign::default(),
+        anchor: Anchor::default(),
+        rotation: 0.0,
+        letter_spacing: 0.0,
+        stroke: None,
+        shadow: None,
+        font: None,
+    }
+}
+
+fn shadow(offset_x: i32, offset_y: i32, blur: u32) -> Option<TextShadow> {
+    Some(TextShadow { offset_x, offset_y, blur, color: "#000000".to_string() })
+}
+
+fn draw(params: TextOverlayParameters) -> Result<(), EditorError> {
+    let mut layer = Layer::new_empty(64, 64)?;
+    layer.apply_transformation_with_fonts(Transformation::TextOverlay(params), &FontRegistry::new())
+}
+
+fn assert_rejected(params: TextOverlayParameters) {
+    match draw(params) {
+        Err(EditorError::InvalidArgument(_)) => {},
+        other => panic!("expected an invalid argument, got {other:?}"),
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn effects_within_bounds_are_drawn() {
+    let params = TextOverlayParameters {
+        stroke: Some(TextStroke { width: 24.0, color: "#ff0000".to_string() }),
+        shadow: shadow(-64, 64, 24),
+        ..overlay()
+    };
+    draw(params).expect("text with effects at their limits draws");
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn strokes_wider_than_the_text_are_rejected() {
+    for width in [24.5, 4096.0, f32::INFINITY, f32::NAN, -1.0] {
+        assert_rejected(TextOverlayParameters { stroke: Some(TextStroke { width, color: "#ff0000".to_string() }), ..overlay() });
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn shadow_offsets_beyond_the_image_are_rejected() {
+    for (offset_x, offset_y) in [(i32::MIN, 0), (0, i32::MIN), (65, 0), (0, -65), (i32::MAX, i32::MAX)] {
+        assert_rejected(TextOverlayParameters { shadow: shadow(offset_x, offset_y, 0), ..overlay() });
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+fn shadow_blurs_wider_than_the_text_are_rejected() {
+    for blur in [25, 4096, u32::MAX] {
+        assert_rejected(TextOverlayParameters { shadow: shadow(2, 2, blur), ..overlay() });
+    }
+}
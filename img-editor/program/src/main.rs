@@ -1,92 +1,11 @@
 #![no_main]
 sp1_zkvm::entrypoint!(main);
 
-use img_editor_lib::{ImageInput, Layer, ImageProofPublicValues};
-use sp1_zkvm::{io, syscalls};
-use image;
+use img_editor_lib::hash::{ethereum_message_hash, keccak256, sha256, Sha256};
+use img_editor_lib::{kept_metadata, Animation, read_icc_profile, render_input, render_tiled, track, EditorError, FontRegistry, ImageInput, ImageProofPublicValues};
+use sp1_zkvm::io;
 use alloy_sol_types::{SolType, private::FixedBytes};
 use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
-use sha3::{Digest, Keccak256};
-use hex;
-
-fn hash_image(image_data: &[u8]) -> [u8; 32] {
-    let mut state = [0x6a09e667u32, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 
-                    0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19];
-
-    // Process data in 64-byte blocks
-    let mut i = 0;
-    while i + 64 <= image_data.len() {
-        let mut w = [0u32; 64];
-        
-        // Convert bytes to words
-        for j in 0..16 {
-            let idx = i + j * 4;
-            w[j] = ((image_data[idx] as u32) << 24)
-                 | ((image_data[idx + 1] as u32) << 16)
-                 | ((image_data[idx + 2] as u32) << 8)
-                 | (image_data[idx + 3] as u32);
-        }
-
-        // Extend and compress
-        syscalls::syscall_sha256_extend(&mut w);
-        syscalls::syscall_sha256_compress(&mut w, &mut state);
-        i += 64;
-    }
-
-    // Handle remaining bytes and padding
-    let mut final_block = [0u8; 64];
-    let remaining = image_data.len() - i;
-    final_block[..remaining].copy_from_slice(&image_data[i..]);
-    final_block[remaining] = 0x80;
-
-    let mut w = [0u32; 64];
-    if remaining < 56 {
-        // Length fits in this block
-        for j in 0..14 {
-            let idx = j * 4;
-            w[j] = ((final_block[idx] as u32) << 24)
-                 | ((final_block[idx + 1] as u32) << 16)
-                 | ((final_block[idx + 2] as u32) << 8)
-                 | (final_block[idx + 3] as u32);
-        }
-        let len_bits = (image_data.len() as u64) * 8;
-        w[14] = (len_bits >> 32) as u32;
-        w[15] = len_bits as u32;
-
-        syscalls::syscall_sha256_extend(&mut w);
-        syscalls::syscall_sha256_compress(&mut w, &mut state);
-    } else {
-        // Need an additional block for length
-        for j in 0..16 {
-            let idx = j * 4;
-            w[j] = ((final_block[idx] as u32) << 24)
-                 | ((final_block[idx + 1] as u32) << 16)
-                 | ((final_block[idx + 2] as u32) << 8)
-                 | (final_block[idx + 3] as u32);
-        }
-
-        syscalls::syscall_sha256_extend(&mut w);
-        syscalls::syscall_sha256_compress(&mut w, &mut state);
-
-        w = [0u32; 64];
-        let len_bits = (image_data.len() as u64) * 8;
-        w[14] = (len_bits >> 32) as u32;
-        w[15] = len_bits as u32;
-
-        syscalls::syscall_sha256_extend(&mut w);
-        syscalls::syscall_sha256_compress(&mut w, &mut state);
-    }
-
-    // Convert state to bytes
-    let mut result = [0u8; 32];
-    for i in 0..8 {
-        result[i*4] = (state[i] >> 24) as u8;
-        result[i*4 + 1] = (state[i] >> 16) as u8;
-        result[i*4 + 2] = (state[i] >> 8) as u8;
-        result[i*4 + 3] = state[i] as u8;
-    }
-    result
-}
 
 fn verify_ethereum_signature(msg: &[u8], signature: &[u8], public_key: &[u8]) -> Option<[u8; 20]> {
     let sig_bytes = signature;
@@ -116,12 +35,8 @@ fn verify_ethereum_signature(msg: &[u8], signature: &[u8], public_key: &[u8]) ->
     };
 
     // Create the message hash - using the hex string of the hash
-    let hex_msg = hex::encode(msg);
-    let mut hasher = Keccak256::new();
-    hasher.update(format!("\x19Ethereum Signed Message:\n{}", hex_msg.len()));
-    hasher.update(hex_msg.as_bytes());
-    let message = hasher.finalize();
-    println!("Prefixed hash: 0x{}", hex::encode(&message));
+    let message = ethereum_message_hash(msg);
+    println!("Prefixed hash: 0x{}", hex::encode(message));
 
     // Recover public key
     let verifying_key = match VerifyingKey::recover_from_prehash(
@@ -137,13 +52,11 @@ fn verify_ethereum_signature(msg: &[u8], signature: &[u8], public_key: &[u8]) ->
     };
 
     // Get Ethereum address from public key
-    let mut hasher = Keccak256::new();
-    hasher.update(&verifying_key.to_encoded_point(false).as_bytes()[1..]);
-    let hash = hasher.finalize();
+    let hash = keccak256(&verifying_key.to_encoded_point(false).as_bytes()[1..]);
     
     let mut address = [0u8; 20];
     address.copy_from_slice(&hash[12..32]);
-    println!("Recovered address: 0x{}", hex::encode(&address));
+    println!("Recovered address: 0x{}", hex::encode(address));
     println!("Expected address: 0x{}", hex::encode(public_key));
 
     if address == public_key {
@@ -153,33 +66,75 @@ fn verify_ethereum_signature(msg: &[u8], signature: &[u8], public_key: &[u8]) ->
     }
 }
 
+/// Aborts execution with the stable error code first, so hosts can map the failure without
+/// parsing the message.
+fn fail(error: EditorError) -> ! {
+    panic!("{}: {}", error.code(), error)
+}
+
 pub fn main() {
-    let input: ImageInput = io::read();
+    let mut input: ImageInput = io::read();
     
-    let original_image_hash = hash_image(&input.image_data);
-    
-    let mut layer = Layer::new(&input.image_data)
-        .expect("Failed to create layer");
-    
-    for transformation in input.transformations.clone().iter() {
-        layer.apply_transformation(transformation.clone())
-            .expect("Failed to apply transformation");
+    let original_image_hash = track(format_args!("hash_input"), || sha256(&input.image_data));
+
+    // Commit to every custom font so verifiers know which typefaces rendered the text
+    let mut fonts = FontRegistry::new();
+    let mut font_hashes = Vec::with_capacity(input.fonts.len());
+    for font in std::mem::take(&mut input.fonts) {
+        font_hashes.push(FixedBytes(track(format_args!("hash_input"), || sha256(&font.data))));
+        fonts.register(&font.name, font.data)
+            .unwrap_or_else(|e| fail(e));
     }
     
-    let final_image = layer.to_bytes(image::ImageFormat::Png, None)
-        .expect("Failed to encode image");
+    // Commit to the source image of every extra layer composited above the base image
+    let layer_hashes = input.layers.iter()
+        .map(|layer| FixedBytes(track(format_args!("hash_input"), || sha256(&layer.image_data))))
+        .collect();
+
+    // Tiled rendering streams its output into the hash, so the encoded image is never held whole
+    let (final_image, transformed_image_hash) = match input.tile_rows {
+        Some(_) => {
+            let mut hasher = Sha256::new();
+            render_tiled(&input, &fonts, &mut hasher)
+                .unwrap_or_else(|e| fail(e));
+            (Vec::new(), hasher.finalize())
+        },
+        None => {
+            let final_image = render_input(&input, &fonts)
+                .unwrap_or_else(|e| fail(e));
+            let transformed_image_hash = track(format_args!("hash_output"), || sha256(&final_image));
+            (final_image, transformed_image_hash)
+        },
+    };
 
-    let transformed_image_hash = hash_image(&final_image);
+    // Commit to the names of the metadata fields copied into the transformed image
+    let metadata_fields = track(format_args!("metadata"), || kept_metadata(&input))
+        .unwrap_or_else(|e| fail(e));
+
+    // Commit to the color profile embedded in the transformed image, zero for untagged sRGB
+    let output_profile_hash = track(format_args!("hash_output"), || {
+        read_icc_profile(&final_image).map_or([0u8; 32], |icc| sha256(&icc))
+    });
+
+    // Commit to every frame of an animated output as decoded from the published file, zero for
+    // still images
+    let frames_hash = match input.animation {
+        Some(_) => track(format_args!("frames_hash"), || {
+            Animation::new(&final_image)
+                .unwrap_or_else(|e| fail(e))
+                .frame_sequence_hash(sha256)
+        }),
+        None => [0u8; 32],
+    };
     
     let mut public_key_bytes = [0u8; 32];
     let mut has_signature = false;
 
     if let Some(sig_data) = &input.signature_data {
-        if let Some(eth_addr) = verify_ethereum_signature(
-            &original_image_hash,
-            &sig_data.signature,
-            &sig_data.public_key
-        ) {
+        let signer = track(format_args!("signature"), || {
+            verify_ethereum_signature(&original_image_hash, &sig_data.signature, &sig_data.public_key)
+        });
+        if let Some(eth_addr) = signer {
             public_key_bytes[12..].copy_from_slice(&eth_addr);
             has_signature = true;
         }
@@ -193,8 +148,14 @@ pub fn main() {
         transformed_image_hash: FixedBytes(transformed_image_hash),
         signer_public_key: FixedBytes(public_key_bytes),
         has_signature,
+        font_hashes,
+        layer_hashes,
+        metadata_fields,
+        output_profile_hash: FixedBytes(output_profile_hash),
+        frames_hash: FixedBytes(frames_hash),
     };
     
-    let encoded_public_values = ImageProofPublicValues::abi_encode(&public_values);
+    // Encoded as parameters so the fixed-size fields keep their original offsets
+    let encoded_public_values = ImageProofPublicValues::abi_encode_params(&public_values);
     io::commit_slice(&encoded_public_values);
 }
@@ -0,0 +1,147 @@
+//! Measures what each transformation costs in the zkVM and in the editor preview.
+//!
+//! For every workload the image is proven once in execute mode, which reports the RISC-V cycles
+//! spent, and rendered natively a few times with the same code the WASM preview runs. Save a
+//! run with `--output`, then pass it as `--baseline` to a run against another revision of the
+//! library and program to print the saving. To compare against an earlier revision, check it
+//! out in a worktree next to this one and run this file there:
+//!
+//! ```bash
+//! root=$PWD
+//! git worktree add ../baseline <revision>
+//! cp script/src/bin/bench.rs ../baseline/script/src/bin/bench.rs
+//! (cd ../baseline/script && cargo run --release --bin bench -- --image "$root/photo.png" --output "$root/before.json")
+//! (cd script && cargo run --release --bin bench -- --image "$root/photo.png" --baseline "$root/before.json")
+//! git worktree remove ../baseline
+//! ```
+//!
+//! The input is built from JSON so the file compiles against revisions whose `ImageInput` has
+//! fewer fields. The timings here are native; the README lists the same edits timed on wasm32.
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use sp1_sdk::{include_elf, ProverClient, SP1Stdin};
+use img_editor_lib::{render_input, CropParameters, FontRegistry, ImageInput, Region, Transformation};
+use std::fs;
+use std::time::Instant;
+
+/// The ELF file for the Succinct RISC-V zkVM.
+pub const IMG_EDITOR_ELF: &[u8] = include_elf!("img-editor-program");
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    #[clap(long)]
+    image: String,
+
+    /// Native renders per workload; the fastest one is reported.
+    #[clap(long, default_value = "5")]
+    runs: u32,
+
+    /// Skip the zkVM and only time native rendering.
+    #[clap(long)]
+    native_only: bool,
+
+    /// Write the results as JSON.
+    #[clap(long)]
+    output: Option<String>,
+
+    /// Results of an earlier run, as written by `--output`, to compare against.
+    #[clap(long)]
+    baseline: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Measurement {
+    workload: String,
+    cycles: Option<u64>,
+    native_micros: u128,
+}
+
+fn workloads(width: u32, height: u32) -> Vec<(&'static str, Vec<Transformation>)> {
+    let half = Some(Region { x: width / 4, y: height / 4, width: width / 2, height: height / 2 });
+    vec![
+        ("none", vec![]),
+        ("brighten", vec![Transformation::Brighten { value: 30, region: None }]),
+        ("brighten region", vec![Transformation::Brighten { value: 30, region: half.clone() }]),
+        ("contrast", vec![Transformation::Contrast { contrast: 25.0, region: None }]),
+        ("contrast region", vec![Transformation::Contrast { contrast: 25.0, region: half.clone() }]),
+        ("grayscale", vec![Transformation::Grayscale { region: None }]),
+        ("grayscale region", vec![Transformation::Grayscale { region: half.clone() }]),
+        ("flip horizontal", vec![Transformation::FlipHorizontal { region: None }]),
+        ("flip vertical region", vec![Transformation::FlipVertical { region: half.clone() }]),
+        ("rotate 90", vec![Transformation::Rotate90]),
+        ("rotate 180", vec![Transformation::Rotate180]),
+        ("crop", vec![Transformation::Crop(CropParameters { x: width / 8, y: height / 8, width: width * 3 / 4, height: height * 3 / 4 })]),
+        ("chain", vec![
+            Transformation::Brighten { value: 10, region: half.clone() },
+            Transformation::Contrast { contrast: -15.0, region: half.clone() },
+            Transformation::Grayscale { region: half },
+            Transformation::FlipHorizontal { region: None },
+            Transformation::Rotate180,
+        ]),
+    ]
+}
+
+fn main() {
+    sp1_sdk::utils::setup_logger();
+    dotenv::dotenv().ok();
+    let args = Args::parse();
+
+    let image_data = fs::read(&args.image).expect("Failed to read image file");
+    let (width, height) = image::load_from_memory(&image_data)
+        .map(|image| (image.width(), image.height()))
+        .expect("Failed to decode image");
+    let client = ProverClient::from_env();
+    let fonts = FontRegistry::new();
+
+    let mut results = Vec::new();
+    for (workload, transformations) in workloads(width, height) {
+        let input: ImageInput = serde_json::from_value(serde_json::json!({
+            "image_data": image_data,
+            "transformations": transformations,
+            "signature_data": null,
+        }))
+        .expect("Failed to build input");
+
+        let cycles = (!args.native_only).then(|| {
+            let mut stdin = SP1Stdin::new();
+            stdin.write(&input);
+            let (_, report) = client.execute(IMG_EDITOR_ELF, &stdin).run().expect("Execution failed");
+            report.total_instruction_count()
+        });
+        let native_micros = (0..args.runs.max(1))
+            .map(|_| {
+                let start = Instant::now();
+                render_input(&input, &fonts).expect("Failed to render image");
+                start.elapsed().as_micros()
+            })
+            .min()
+            .unwrap_or_default();
+
+        results.push(Measurement { workload: workload.to_string(), cycles, native_micros });
+    }
+
+    let baseline: Vec<Measurement> = args.baseline.as_deref()
+        .map(|path| serde_json::from_slice(&fs::read(path).expect("Failed to read baseline")).expect("Failed to parse baseline"))
+        .unwrap_or_default();
+
+    println!("{:<22} {:>14} {:>9} {:>12} {:>9}", "workload", "cycles", "saved", "native µs", "speed-up");
+    for result in &results {
+        let before = baseline.iter().find(|before| before.workload == result.workload);
+        let saved = match (before.and_then(|before| before.cycles), result.cycles) {
+            (Some(before), Some(after)) if before > 0 => format!("{:.1}%", 100.0 * (1.0 - after as f64 / before as f64)),
+            _ => "-".to_string(),
+        };
+        let speed_up = match before {
+            Some(before) if result.native_micros > 0 => format!("{:.2}x", before.native_micros as f64 / result.native_micros as f64),
+            _ => "-".to_string(),
+        };
+        let cycles = result.cycles.map_or("-".to_string(), |cycles| cycles.to_string());
+        println!("{:<22} {:>14} {:>9} {:>12} {:>9}", result.workload, cycles, saved, result.native_micros, speed_up);
+    }
+
+    if let Some(path) = &args.output {
+        fs::write(path, serde_json::to_string_pretty(&results).expect("Failed to serialize results"))
+            .expect("Failed to write results");
+    }
+}
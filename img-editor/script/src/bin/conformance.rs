@@ -0,0 +1,44 @@
+//! Executes the library's conformance cases in the zkVM and compares the committed
+//! `transformed_image_hash` with the checked-in hash. Exits with an error when any case disagrees.
+//! Native and WASM rendering are checked by the library's `cargo test` suite.
+//!
+//! ```bash
+//! cargo run --release --bin conformance
+//! ```
+use alloy_sol_types::SolType;
+use img_editor_lib::{conformance_cases, ImageProofPublicValues};
+use sp1_sdk::{include_elf, ProverClient, SP1Stdin};
+use std::process::ExitCode;
+
+/// The ELF file for the Succinct RISC-V zkVM.
+pub const IMG_EDITOR_ELF: &[u8] = include_elf!("img-editor-program");
+
+fn main() -> ExitCode {
+    sp1_sdk::utils::setup_logger();
+    dotenv::dotenv().ok();
+
+    let client = ProverClient::from_env();
+    let mut failures = 0;
+    for case in conformance_cases() {
+        let mut stdin = SP1Stdin::new();
+        stdin.write(&case.input);
+        let (output, _) = client.execute(IMG_EDITOR_ELF, &stdin).run().expect("Execution failed");
+        let public_values = ImageProofPublicValues::abi_decode_params(output.as_slice(), false)
+            .expect("Failed to decode public values");
+        let actual_hash = hex::encode(public_values.transformed_image_hash.0);
+
+        if actual_hash == case.expected_hash {
+            println!("{:<34} ok", case.name);
+        } else {
+            println!("{:<34} FAILED", case.name);
+            println!("  zkvm hash {actual_hash}, expected {}", case.expected_hash);
+            failures += 1;
+        }
+    }
+
+    if failures > 0 {
+        eprintln!("{failures} conformance case(s) failed");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
@@ -1,13 +1,15 @@
 use clap::Parser;
 use sp1_sdk::{include_elf, ProverClient, SP1Stdin, HashableKey};
-use img_editor_lib::{ImageInput, ImageProofPublicValues, Layer, SignatureData, Transformation};
+use img_editor_lib::{
+    check_rendered_image, estimate_proof, render_input, AnimationInput, EncodeOptions, FontData, FontRegistry, ImageInput, ImageProofPublicValues,
+    LayerInput, OutputFormat, ProofEstimate, ProvingRates, SignatureData, Transformation,
+};
 use std::fs;
 use std::env;
 use serde_json;
 use serde::{Serialize, Deserialize};
 use hex;
 use alloy_sol_types::SolType;
-use image;
 
 /// The ELF file for the Succinct RISC-V zkVM.
 pub const IMG_EDITOR_ELF: &[u8] = include_elf!("img-editor-program");
@@ -22,6 +24,10 @@ struct Args {
     #[clap(long)]
     prove: bool,
 
+    /// Run the program without proving and report the cycles, proving time and cost it takes.
+    #[clap(long)]
+    estimate: bool,
+
     #[clap(long)]
     image: String,
 
@@ -33,6 +39,41 @@ struct Args {
 
     #[clap(long)]
     public_key: Option<String>,
+
+    /// Custom font as `name=path/to/font.ttf`. May be repeated.
+    #[clap(long = "font")]
+    fonts: Vec<String>,
+
+    /// Extra layer composited above the image, as `path/to/image.png` or `path@x,y`. May be
+    /// repeated; later layers are stacked higher.
+    #[clap(long = "layer")]
+    layers: Vec<String>,
+
+    /// Format of the proven image: png, jpeg, webp or gif.
+    #[clap(long, default_value = "png")]
+    output_format: OutputFormat,
+
+    /// Encoder settings for the proven image, as `EncodeOptions` JSON.
+    #[clap(long)]
+    encode_options: Option<String>,
+
+    /// Prove every frame of an animated image, written as an animated GIF or PNG.
+    #[clap(long)]
+    animated: bool,
+
+    /// Transformations limited to some frames, as `FrameEdit` JSON. Implies `--animated`.
+    #[clap(long)]
+    frame_edits: Option<String>,
+
+    /// Render in bands of this many rows to fit large images in the zkVM's memory. Only PNG
+    /// output of color adjustments, horizontal flips and crops can be tiled.
+    #[clap(long)]
+    tile_rows: Option<u32>,
+
+    /// With `--execute` or `--estimate`, also write the cycles spent in each stage of the program
+    /// as JSON.
+    #[clap(long)]
+    profile: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -49,11 +90,35 @@ pub struct ImageProofOutput {
     transformed_image_hash: String,
     signer_public_key: String,
     has_signature: bool,
+    font_hashes: Vec<String>,
+    layer_hashes: Vec<String>,
+    metadata_fields: Vec<String>,
+    output_profile_hash: String,
+    frames_hash: String,
     success: bool,
     message: String,
     proof_data: Option<ProofData>,
 }
 
+fn print_estimate(estimate: &ProofEstimate) {
+    println!("\n{:<16} {:<16} {:>14} {:>7}", "stage", "transformation", "cycles", "share");
+    for stage in &estimate.stages {
+        let transformation = stage.transformation.as_deref().unwrap_or("");
+        let share = 100.0 * stage.cycles as f64 / estimate.total_cycles.max(1) as f64;
+        println!("{:<16} {:<16} {:>14} {:>6.1}%", stage.stage, transformation, stage.cycles, share);
+    }
+    println!("{:<16} {:<16} {:>14}", "untracked", "", estimate.untracked_cycles);
+    println!("{:<16} {:<16} {:>14}", "total", "", estimate.total_cycles);
+}
+
+fn write_profile(path: &Option<String>, estimate: &ProofEstimate) {
+    if let Some(path) = path {
+        fs::write(path, serde_json::to_string_pretty(estimate).expect("Failed to serialize profile"))
+            .expect("Failed to write profile");
+        println!("Profile saved as {}", path);
+    }
+}
+
 fn main() {
     // Enable profiling
     env::set_var("TRACE_FILE", "image_editor_profile.json");
@@ -66,8 +131,8 @@ fn main() {
     // Parse the command line arguments.
     let args = Args::parse();
 
-    if args.execute == args.prove {
-        eprintln!("Error: You must specify either --execute or --prove");
+    if [args.execute, args.prove, args.estimate].iter().filter(|&&mode| mode).count() != 1 {
+        eprintln!("Error: You must specify one of --execute, --prove or --estimate");
         std::process::exit(1);
     }
 
@@ -88,56 +153,114 @@ fn main() {
         _ => None,
     };
 
+    let fonts: Vec<FontData> = args.fonts.iter()
+        .map(|font| {
+            let (name, path) = font.split_once('=').expect("Fonts must be given as name=path");
+            FontData {
+                name: name.to_string(),
+                data: fs::read(path).expect("Failed to read font file"),
+            }
+        })
+        .collect();
+    let mut font_registry = FontRegistry::new();
+    for font in fonts.iter() {
+        font_registry.register(&font.name, font.data.clone())
+            .expect("Failed to load font");
+    }
+
+    let layers: Vec<LayerInput> = args.layers.iter()
+        .map(|layer| {
+            let (path, position) = match layer.rsplit_once('@') {
+                Some((path, position)) => {
+                    let (x, y) = position.split_once(',').expect("Layer positions must be given as x,y");
+                    (path, (x.parse().expect("Invalid layer x"), y.parse().expect("Invalid layer y")))
+                },
+                None => (layer.as_str(), (0, 0)),
+            };
+            LayerInput {
+                image_data: fs::read(path).expect("Failed to read layer image"),
+                transformations: vec![],
+                x: position.0,
+                y: position.1,
+                opacity: 1.0,
+                mask: None,
+                clipped: false,
+            }
+        })
+        .collect();
+
+    let encode_options: EncodeOptions = args.encode_options.as_deref()
+        .map(|options| serde_json::from_str(options).expect("Failed to parse encode options"))
+        .unwrap_or_default();
+
+    let animation = match args.frame_edits.as_deref() {
+        Some(frame_edits) => Some(AnimationInput {
+            frame_edits: serde_json::from_str(frame_edits).expect("Failed to parse frame edits"),
+        }),
+        None => args.animated.then(AnimationInput::default),
+    };
+
     // Setup the prover client.
     let client = ProverClient::from_env();
 
-    // Create input with transformations - clone the values before moving
-    let input_image_data = image_data.clone();
-    let input_transformations = transformations.clone();
-
     let input = ImageInput {
-        image_data: input_image_data,
-        transformations: input_transformations,
+        image_data,
+        transformations,
         signature_data,
+        fonts,
+        layers,
+        output_format: args.output_format,
+        encode_options,
+        animation,
+        tile_rows: args.tile_rows,
+        canvas: None,
     };
 
     // Setup stdin with serialized input
     let mut stdin = SP1Stdin::new();
     stdin.write(&input);
 
+    if args.estimate {
+        let rates = ProvingRates::from_env().expect("Invalid proving rates");
+        let model = estimate_proof(&input, &rates).expect("Failed to estimate proof");
+        let (_, report) = client.execute(IMG_EDITOR_ELF, &stdin).run().unwrap();
+        let estimate = ProofEstimate::new(&input, report.cycle_tracker.clone(), report.total_instruction_count(), &rates);
+
+        print_estimate(&estimate);
+        println!("\nEstimated proving time: {:.0} s", estimate.proving_seconds);
+        println!("Estimated cost: {:.4}", estimate.cost);
+        println!("Offline model: {} cycles, {:.0} s, {:.4}", model.total_cycles, model.proving_seconds, model.cost);
+        write_profile(&args.profile, &estimate);
+
+        println!("{}", serde_json::to_string(&estimate).unwrap());
+        return;
+    }
+
     let output = match args.execute {
         true => {
             let (output, report) = client.execute(IMG_EDITOR_ELF, &stdin).run().unwrap();
             
             // Get the public values
             let public_values = output.as_slice();
-            let decoded_values = ImageProofPublicValues::abi_decode(public_values, false)
+            let decoded_values = ImageProofPublicValues::abi_decode_params(public_values, false)
                 .expect("Failed to decode public values");
             
-            // Regenerate the transformed image
-            let mut layer = Layer::new(&image_data)
-                .expect("Failed to create layer");
-            
-            for transformation in transformations.iter() {
-                layer.apply_transformation(transformation.clone())
-                    .expect("Failed to apply transformation");
-            }
-            
-            let final_image = layer.to_bytes(image::ImageFormat::Png, None)
-                .expect("Failed to encode image");
+            // Regenerate the transformed image and make sure it is the one the program hashed
+            let final_image = render_input(&input, &font_registry)
+                .expect("Failed to render image");
+            check_rendered_image(&final_image, &decoded_values.transformed_image_hash.0)
+                .expect("Rendered image does not match the proven hash");
             
             // Write transformed image
-            let output_path = format!("{}_transformed.png", args.image);
+            let output_path = format!("{}_transformed.{}", args.image, args.output_format.extension());
             fs::write(&output_path, &final_image).expect("Failed to write output image");
             println!("Image transformed and saved as {}", output_path);
 
-            // Print cycle counts
-            println!("\nCycle counts:");
-            for i in 0..report.cycle_tracker.len() {
-                if let Some(cycles) = report.cycle_tracker.get(&format!("transform_{}", i)) {
-                    println!("Transform {}: {} cycles", i, cycles);
-                }
-            }
+            // Print the cycles spent in each stage
+            let rates = ProvingRates::from_env().expect("Invalid proving rates");
+            let profile = ProofEstimate::new(&input, report.cycle_tracker.clone(), report.total_instruction_count(), &rates);
+            print_estimate(&profile);
+            write_profile(&args.profile, &profile);
 
             ImageProofOutput {
                 final_image,
@@ -145,6 +268,11 @@ fn main() {
                 transformed_image_hash: format!("0x{}", hex::encode(decoded_values.transformed_image_hash.0)),
                 signer_public_key: format!("0x{}", hex::encode(decoded_values.signer_public_key.0)),
                 has_signature: decoded_values.has_signature,
+                font_hashes: decoded_values.font_hashes.iter().map(|hash| format!("0x{}", hex::encode(hash.0))).collect(),
+                layer_hashes: decoded_values.layer_hashes.iter().map(|hash| format!("0x{}", hex::encode(hash.0))).collect(),
+                metadata_fields: decoded_values.metadata_fields.clone(),
+                output_profile_hash: format!("0x{}", hex::encode(decoded_values.output_profile_hash.0)),
+                frames_hash: format!("0x{}", hex::encode(decoded_values.frames_hash.0)),
                 success: true,
                 message: "Image transformed successfully".to_string(),
                 proof_data: None,
@@ -159,23 +287,17 @@ fn main() {
                     // Get and decode the public values from the proof
                     let public_values = proof.public_values.as_slice();
                     println!("Debug - Public values length: {}", public_values.len());
-                    let decoded_values = ImageProofPublicValues::abi_decode(public_values, false)
+                    let decoded_values = ImageProofPublicValues::abi_decode_params(public_values, false)
                         .expect("Failed to decode public values");
                     
-                    // Regenerate the transformed image
-                    let mut layer = Layer::new(&image_data)
-                        .expect("Failed to create layer");
-                    
-                    for transformation in transformations.iter() {
-                        layer.apply_transformation(transformation.clone())
-                            .expect("Failed to apply transformation");
-                    }
-                    
-                    let final_image = layer.to_bytes(image::ImageFormat::Png, None)
-                        .expect("Failed to encode image");
+                    // Regenerate the transformed image and make sure it is the one the program hashed
+                    let final_image = render_input(&input, &font_registry)
+                        .expect("Failed to render image");
+                    check_rendered_image(&final_image, &decoded_values.transformed_image_hash.0)
+                        .expect("Rendered image does not match the proven hash");
                     
                     // Write transformed image
-                    let output_path = format!("{}_transformed.png", args.image);
+                    let output_path = format!("{}_transformed.{}", args.image, args.output_format.extension());
                     fs::write(&output_path, &final_image).expect("Failed to write output image");
                     println!("Image transformed and saved as {}", output_path);
                     
@@ -202,6 +324,11 @@ fn main() {
                         transformed_image_hash: format!("0x{}", hex::encode(decoded_values.transformed_image_hash.0)),
                         signer_public_key: format!("0x{}", hex::encode(decoded_values.signer_public_key.0)),
                         has_signature: decoded_values.has_signature,
+                        font_hashes: decoded_values.font_hashes.iter().map(|hash| format!("0x{}", hex::encode(hash.0))).collect(),
+                        layer_hashes: decoded_values.layer_hashes.iter().map(|hash| format!("0x{}", hex::encode(hash.0))).collect(),
+                        metadata_fields: decoded_values.metadata_fields.clone(),
+                        output_profile_hash: format!("0x{}", hex::encode(decoded_values.output_profile_hash.0)),
+                        frames_hash: format!("0x{}", hex::encode(decoded_values.frames_hash.0)),
                         success: true,
                         message: "Proof generated and verified successfully".to_string(),
                         proof_data: Some(ProofData {
@@ -217,6 +344,11 @@ fn main() {
                     transformed_image_hash: "0x".to_string(),
                     signer_public_key: "0x".to_string(),
                     has_signature: false,
+                    font_hashes: vec![],
+                    layer_hashes: vec![],
+                    metadata_fields: vec![],
+                    output_profile_hash: "0x".to_string(),
+                    frames_hash: "0x".to_string(),
                     success: false,
                     message: format!("Failed to generate proof: {}", e),
                     proof_data: None,
@@ -19,6 +19,11 @@ contract ImageVerifier {
         bytes32 parent;           // Parent image hash (zero if original)
         address signerAddress;    // Address of the signer
         bool hasSignature;        // Whether the image has a signature
+        bytes32[] fontHashes;     // SHA-256 hashes of the custom fonts used to render text
+        bytes32[] layerHashes;    // SHA-256 hashes of the extra layers composited onto the image
+        string[] metadataFields;  // EXIF and XMP fields copied from the original image
+        bytes32 outputProfileHash; // SHA-256 hash of the embedded ICC profile, zero for untagged sRGB
+        bytes32 framesHash;       // SHA-256 hash over every frame of an animation, zero for still images
     }
 
     /// @notice Mapping from image hash to its data
@@ -33,6 +38,24 @@ contract ImageVerifier {
         bytes proof
     );
 
+    /// @notice Event emitted when a proof commits to custom fonts
+    event FontsCommitted(
+        bytes32 indexed transformedImageHash,
+        bytes32[] fontHashes
+    );
+
+    /// @notice Event emitted when a proof composites extra layers onto the image
+    event LayersCommitted(
+        bytes32 indexed transformedImageHash,
+        bytes32[] layerHashes
+    );
+
+    /// @notice Event emitted when a proof keeps metadata from the original image
+    event MetadataCommitted(
+        bytes32 indexed transformedImageHash,
+        string[] metadataFields
+    );
+
     constructor(address _verifier, bytes32 _imageTransformVKey) {
         verifier = _verifier;
         imageTransformVKey = _imageTransformVKey;
@@ -63,6 +86,34 @@ contract ImageVerifier {
         (originalImageHash, transformedImageHash, signerPublicKey, hasSignature) = 
             abi.decode(_publicValues, (bytes32, bytes32, bytes32, bool));
 
+        // Public values from programs without font support end after the fixed fields
+        bytes32[] memory fontHashes;
+        bytes32[] memory layerHashes;
+        string[] memory metadataFields;
+        bytes32 outputProfileHash;
+        if (_publicValues.length > 128) {
+            // The first dynamic offset equals the head size, which tells how many fields follow
+            uint256 headSize = uint256(bytes32(_publicValues[128:160]));
+            if (headSize >= 256) {
+                (, , , , fontHashes, layerHashes, metadataFields, outputProfileHash) = abi.decode(
+                    _publicValues,
+                    (bytes32, bytes32, bytes32, bool, bytes32[], bytes32[], string[], bytes32)
+                );
+            } else if (headSize >= 224) {
+                (, , , , fontHashes, layerHashes, metadataFields) = abi.decode(
+                    _publicValues,
+                    (bytes32, bytes32, bytes32, bool, bytes32[], bytes32[], string[])
+                );
+            } else {
+                (, , , , fontHashes, layerHashes) =
+                    abi.decode(_publicValues, (bytes32, bytes32, bytes32, bool, bytes32[], bytes32[]));
+            }
+            // Static, so read from its head slot rather than decoded with the fields above
+            if (headSize >= 288) {
+                imageData[transformedImageHash].framesHash = bytes32(_publicValues[256:288]);
+            }
+        }
+
         // Convert bytes32 to address by taking the last 20 bytes
         address signerAddress = address(uint160(uint256(signerPublicKey)));
 
@@ -70,6 +121,10 @@ contract ImageVerifier {
         imageData[transformedImageHash].parent = originalImageHash;
         imageData[transformedImageHash].signerAddress = signerAddress;
         imageData[transformedImageHash].hasSignature = hasSignature;
+        imageData[transformedImageHash].fontHashes = fontHashes;
+        imageData[transformedImageHash].layerHashes = layerHashes;
+        imageData[transformedImageHash].metadataFields = metadataFields;
+        imageData[transformedImageHash].outputProfileHash = outputProfileHash;
 
         // Add to parent's children
         imageData[originalImageHash].children.push(transformedImageHash);
@@ -81,6 +136,18 @@ contract ImageVerifier {
             hasSignature,
             _proofBytes
         );
+
+        if (fontHashes.length > 0) {
+            emit FontsCommitted(transformedImageHash, fontHashes);
+        }
+
+        if (layerHashes.length > 0) {
+            emit LayersCommitted(transformedImageHash, layerHashes);
+        }
+
+        if (metadataFields.length > 0) {
+            emit MetadataCommitted(transformedImageHash, metadataFields);
+        }
     }
 
     /// @notice Decodes SP1 public values into PNG data by removing the 8-byte prefix
@@ -128,6 +195,41 @@ contract ImageVerifier {
         return imageData[_imageHash].children.length;
     }
 
+    /// @notice Get the custom fonts a transformed image was rendered with
+    /// @param _imageHash The hash of the transformed image
+    /// @return The SHA-256 hashes of the committed fonts
+    function getFontHashes(bytes32 _imageHash) external view returns (bytes32[] memory) {
+        return imageData[_imageHash].fontHashes;
+    }
+
+    /// @notice Get the extra layers composited into a transformed image
+    /// @param _imageHash The hash of the transformed image
+    /// @return The SHA-256 hashes of the committed layer images, bottom first
+    function getLayerHashes(bytes32 _imageHash) external view returns (bytes32[] memory) {
+        return imageData[_imageHash].layerHashes;
+    }
+
+    /// @notice Get the metadata fields a transformed image kept from its original
+    /// @param _imageHash The hash of the transformed image
+    /// @return Field names such as "GPS.GPSLatitude", plus "XMP" when the XMP packet was kept
+    function getMetadataFields(bytes32 _imageHash) external view returns (string[] memory) {
+        return imageData[_imageHash].metadataFields;
+    }
+
+    /// @notice Get the color profile embedded in a transformed image
+    /// @param _imageHash The hash of the transformed image
+    /// @return The SHA-256 hash of the ICC profile, zero when the image is untagged sRGB
+    function getOutputProfileHash(bytes32 _imageHash) external view returns (bytes32) {
+        return imageData[_imageHash].outputProfileHash;
+    }
+
+    /// @notice Get the frame sequence of an animated transformed image
+    /// @param _imageHash The hash of the transformed image
+    /// @return The SHA-256 hash over the decoded frames, delays and loop count, zero for still images
+    function getFramesHash(bytes32 _imageHash) external view returns (bytes32) {
+        return imageData[_imageHash].framesHash;
+    }
+
     /// @notice Get the signature information for an image
     /// @param _imageHash The hash of the image to check
     /// @return signer The address of the signer